@@ -0,0 +1,60 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Benchmarks of the hot paths of the ShortBot.
+//!
+//! Run them with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use shortbot::endpoints::stocks_keyboard;
+use shortbot::finance::{load_ibex35_companies, parse_alive_positions, parse_registry};
+use std::fs::read_to_string;
+
+fn fixture(path: &str) -> String {
+    read_to_string(format!("{}/{path}", env!("CARGO_MANIFEST_DIR"))).unwrap()
+}
+
+fn keyboard(c: &mut Criterion) {
+    let market =
+        load_ibex35_companies(&format!("{}/data/ibex35.toml", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let tickers = market.list_tickers();
+
+    c.bench_function("stocks keyboard", |b| {
+        b.iter(|| stocks_keyboard(black_box(&tickers), 5))
+    });
+}
+
+fn parsing(c: &mut Criterion) {
+    let html = fixture("tests/fixtures/cnmv/alive_positions.html");
+    let csv = fixture("tests/fixtures/registry/esma.csv");
+
+    c.bench_function("parse CNMV positions", |b| {
+        b.iter(|| parse_alive_positions(black_box(&html)))
+    });
+    c.bench_function("parse registry", |b| {
+        b.iter(|| parse_registry(black_box(&csv), "ES0171996087"))
+    });
+}
+
+fn rendering(c: &mut Criterion) {
+    let html = fixture("tests/fixtures/cnmv/alive_positions.html");
+    let positions = parse_alive_positions(&html).unwrap();
+
+    c.bench_function("render short positions", |b| {
+        b.iter(|| black_box(&positions).to_string())
+    });
+}
+
+criterion_group!(benches, keyboard, parsing, rendering);
+criterion_main!(benches);
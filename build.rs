@@ -0,0 +1,38 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Build script of the ShortBot.
+//!
+//! It exposes the commit of the build as `SHORTBOT_COMMIT`. The value can be given as an
+//! environment variable when the sources are built outside of a git repository.
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=SHORTBOT_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+
+    let commit = std::env::var("SHORTBOT_COMMIT").ok().unwrap_or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .unwrap_or_else(|| String::from("unknown"))
+    });
+
+    println!("cargo:rustc-env=SHORTBOT_COMMIT={commit}");
+}
@@ -0,0 +1,106 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Source of the current time.
+//!
+//! # Description
+//!
+//! Logic that depends on the current time (e.g. the expiry of cached data) shall get it
+//! from a [Clock] rather than calling `Instant::now()` inline. This way, tests can use a
+//! [ManualClock] and move the time forward deterministically.
+
+use date::Date;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Source of the current time.
+pub trait Clock: Send + Sync {
+    /// Get the current instant, meant to measure elapsed times.
+    fn now(&self) -> Instant;
+
+    /// Get the current date (UTC).
+    fn today(&self) -> Date;
+}
+
+/// [Clock] that reads the time of the system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn today(&self) -> Date {
+        Date::today_utc()
+    }
+}
+
+/// [Clock] whose time only changes when it is told so.
+///
+/// # Description
+///
+/// The clock starts at the instant of its creation and at the given date.
+#[derive(Debug)]
+pub struct ManualClock {
+    time: Mutex<(Instant, Date)>,
+}
+
+impl ManualClock {
+    /// Class constructor.
+    pub fn new(today: Date) -> ManualClock {
+        ManualClock {
+            time: Mutex::new((Instant::now(), today)),
+        }
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        self.time.lock().expect("Poisoned lock of the clock").0 += duration;
+    }
+
+    /// Change the current date.
+    pub fn set_today(&self, today: Date) {
+        self.time.lock().expect("Poisoned lock of the clock").1 = today;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.time.lock().expect("Poisoned lock of the clock").0
+    }
+
+    fn today(&self) -> Date {
+        self.time.lock().expect("Poisoned lock of the clock").1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    fn manual_clock() {
+        let clock = ManualClock::new(Date::new(2024, 6, 12));
+        let start = clock.now();
+
+        assert_eq!(clock.now(), start);
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.now() - start, Duration::from_secs(90));
+
+        clock.set_today(Date::new(2024, 6, 13));
+        assert_eq!(clock.today(), Date::new(2024, 6, 13));
+    }
+}
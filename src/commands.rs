@@ -0,0 +1,163 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Registration of the commands of the bot in Telegram.
+//!
+//! # Description
+//!
+//! Telegram shows a menu with the commands of the bot. The list depends on the kind of
+//! chat (scope) and on the language of the user, so each pair is registered on its own.
+//!
+//! At startup, the lists registered in Telegram are compared against the ones declared
+//! by [CommandEng] and [CommandSpa], and only the lists that drifted are updated. Run the
+//! binary with [SYNC_FLAG] to update all of them regardless.
+
+use crate::{CommandEng, CommandSpa};
+use teloxide::{
+    prelude::*,
+    types::{BotCommand, BotCommandScope},
+    utils::command::BotCommands,
+    RequestError,
+};
+use tracing::{debug, info};
+
+/// Command line flag that registers all the commands again and exits.
+pub const SYNC_FLAG: &str = "--sync-commands";
+
+/// Supported languages of the commands.
+pub const COMMAND_LANGUAGES: [&str; 2] = ["es", "en"];
+
+/// Commands that are only meaningful in a private chat with the bot.
+const PRIVATE_ONLY: [&str; 4] = ["start", "inicio", "support", "apoyo"];
+
+/// Kind of chat that shows a list of commands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandAudience {
+    /// Private chats with the bot. Also used when no other scope applies.
+    Private,
+    /// Groups and supergroups.
+    Group,
+}
+
+impl CommandAudience {
+    /// All the audiences, in the order they are registered.
+    pub const ALL: [CommandAudience; 2] = [CommandAudience::Private, CommandAudience::Group];
+
+    /// Get the scopes of Telegram that match the audience.
+    pub fn scopes(&self) -> Vec<BotCommandScope> {
+        match self {
+            CommandAudience::Private => {
+                vec![BotCommandScope::Default, BotCommandScope::AllPrivateChats]
+            }
+            CommandAudience::Group => vec![BotCommandScope::AllGroupChats],
+        }
+    }
+}
+
+/// Build the list of commands for an audience and a language.
+///
+/// # Description
+///
+/// Commands are given without the leading `/`, as Telegram expects. Unsupported languages
+/// get the English list.
+pub fn commands(audience: CommandAudience, lang_code: &str) -> Vec<BotCommand> {
+    let commands = match lang_code {
+        "es" => CommandSpa::bot_commands(),
+        _ => CommandEng::bot_commands(),
+    };
+
+    commands
+        .into_iter()
+        .map(|c| BotCommand::new(c.command.trim_start_matches('/'), c.description))
+        .filter(|c| audience == CommandAudience::Private || !PRIVATE_ONLY.contains(&&*c.command))
+        .collect()
+}
+
+/// Make sure Telegram shows the declared commands for all the audiences and languages.
+///
+/// # Description
+///
+/// The registered lists are requested to Telegram (`getMyCommands`) and compared with the
+/// declared ones. A list is only updated when it differs, unless `force` is `true`.
+///
+/// ## Returns
+///
+/// The number of lists that were updated.
+pub async fn sync_commands(bot: &Bot, force: bool) -> Result<usize, RequestError> {
+    let mut updated = 0;
+
+    for audience in CommandAudience::ALL {
+        for lang_code in COMMAND_LANGUAGES {
+            let declared = commands(audience, lang_code);
+
+            for scope in audience.scopes() {
+                if !force {
+                    let registered = bot
+                        .get_my_commands()
+                        .scope(scope.clone())
+                        .language_code(lang_code)
+                        .await?;
+
+                    if registered == declared {
+                        debug!("Commands for {audience:?} ({lang_code}) in {scope:?} are in sync");
+                        continue;
+                    }
+                }
+
+                info!("Registering the commands for {audience:?} ({lang_code}) in {scope:?}");
+                bot.set_my_commands(declared.clone())
+                    .scope(scope)
+                    .language_code(lang_code)
+                    .await?;
+                updated += 1;
+            }
+        }
+    }
+
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn names(commands: &[BotCommand]) -> Vec<&str> {
+        commands.iter().map(|c| c.command.as_str()).collect()
+    }
+
+    #[rstest]
+    #[case("es", "inicio")]
+    #[case("en", "start")]
+    #[case("fr", "start")]
+    fn private_commands(#[case] lang_code: &str, #[case] start: &str) {
+        let commands = commands(CommandAudience::Private, lang_code);
+
+        assert!(names(&commands).contains(&start));
+        assert!(names(&commands).contains(&"short"));
+        assert!(commands.iter().all(|c| !c.command.starts_with('/')));
+    }
+
+    #[rstest]
+    #[case("es")]
+    #[case("en")]
+    fn group_commands(#[case] lang_code: &str) {
+        let commands = commands(CommandAudience::Group, lang_code);
+
+        assert!(names(&commands).contains(&"short"));
+        assert!(names(&commands)
+            .iter()
+            .all(|name| !PRIVATE_ONLY.contains(name)));
+    }
+}
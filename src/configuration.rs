@@ -24,9 +24,16 @@
 //! API token for the Telegram Bot client. All the environment variables that
 //! are meant to be used within this module shall use the prefix _SHORTBOT_.
 
+use crate::finance::ScrapeCoordinator;
+use crate::ops_alerts::Severity;
+use crate::telemetry::RedactionMode;
 use config::{Config, ConfigError, Environment, File};
 use secrecy::Secret;
 use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use teloxide::adaptors::throttle::{self, Limits};
 
 /// Name of the directory in which configuration files will be stored.
 const CONF_DIR: &str = "config";
@@ -37,10 +44,106 @@ const CONF_DIR: &str = "config";
 pub struct Settings {
     /// Level for the tracing crate.
     pub tracing_level: String,
+    /// How personal identifiers are shown in the logs.
+    #[serde(default)]
+    pub log_redaction: RedactionMode,
+    /// Secret key of the hashes of the identifiers in the logs. A random one is used when
+    /// missing, so the hashes change after a restart.
+    #[serde(default)]
+    pub log_redaction_key: Option<Secret<String>>,
     /// Application specific settings.
     pub application: ApplicationSettings,
     /// Data folder path.
     pub data_path: String,
+    /// Settings of the data providers.
+    pub providers: ProviderSettings,
+    /// Settings of the webhook mode. The bot uses long polling when missing.
+    #[serde(default)]
+    pub webhook: Option<WebhookSettings>,
+    /// Contact and support information shown by the /support command.
+    #[serde(default)]
+    pub support: SupportSettings,
+    /// Settings of the alerts sent to the admin chat. Alerts are only logged when missing.
+    #[serde(default)]
+    pub ops_alerts: Option<OpsAlertsSettings>,
+    /// Templates that replace the bundled texts of the bot.
+    #[serde(default)]
+    pub templates: TemplateSettings,
+}
+
+/// Paths of the templates that replace the bundled texts of the bot.
+///
+/// # Description
+///
+/// Relative paths are resolved against the data path, and missing templates are taken
+/// from the bundled ones. See [Templates][crate::templates::Templates] for the
+/// placeholders that the templates may include.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[allow(unused)]
+#[serde(default)]
+pub struct TemplateSettings {
+    /// Greeting of `/start` in English.
+    pub welcome_en: Option<String>,
+    /// Greeting of `/start` in Spanish.
+    pub welcome_es: Option<String>,
+    /// Answer to the messages that the bot doesn't understand, in English.
+    pub fallback_en: Option<String>,
+    /// Answer to the messages that the bot doesn't understand, in Spanish.
+    pub fallback_es: Option<String>,
+}
+
+/// Settings of the alerts about operational events.
+///
+/// # Description
+///
+/// - [OpsAlertsSettings::chat_id]: ID of the chat of the administrators, where the admin
+///   commands are accepted.
+/// - [OpsAlertsSettings::min_severity]: lowest severity sent to the chat: "info",
+///   "warning" or "critical".
+/// - [OpsAlertsSettings::min_interval]: seconds between two alerts of the same kind.
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+pub struct OpsAlertsSettings {
+    pub chat_id: i64,
+    #[serde(default)]
+    pub min_severity: Severity,
+    #[serde(default = "default_alerts_interval")]
+    pub min_interval: u64,
+}
+
+fn default_alerts_interval() -> u64 {
+    600
+}
+
+/// Contact and support information of the project.
+///
+/// # Description
+///
+/// Links are shown as buttons of the support card, and missing ones are not shown.
+/// Telegram only allows `http(s)` links in the buttons, so the email is shown as text.
+#[derive(Clone, Debug, Deserialize)]
+#[allow(unused)]
+#[serde(default)]
+pub struct SupportSettings {
+    /// URL of the source code repository.
+    pub repository: Option<String>,
+    /// URL of the donation page.
+    pub donation_url: Option<String>,
+    /// Contact email.
+    pub email: Option<String>,
+    /// Names of the contributors of the project.
+    pub contributors: Vec<String>,
+}
+
+impl Default for SupportSettings {
+    fn default() -> Self {
+        SupportSettings {
+            repository: Some(String::from("https://github.com/felipet/shortbot")),
+            donation_url: Some(String::from("https://buymeacoffee.com/felipetg")),
+            email: Some(String::from("torresfelipex1@gmail.com")),
+            contributors: vec![String::from("Felipe Torres González")],
+        }
+    }
 }
 
 /// Settings of the ShortBot application.
@@ -49,10 +152,294 @@ pub struct Settings {
 ///
 /// - [ApplicationSettings::api_token]: Telegram BOT API token. Override the value
 ///   of the YML file using an environment variable: `export SHORTBOT__APPLICATION__API_KEY="key"`.
+/// - [ApplicationSettings::throttle]: limits of the messages sent to Telegram.
+/// - [ApplicationSettings::dead_letter_file]: file in which the updates that the bot
+///   could not handle are written. They are only logged when missing.
+/// - [ApplicationSettings::slow_handler_ms]: handlers that take longer (milliseconds) are
+///   logged as slow.
 #[derive(Debug, Deserialize)]
 #[allow(unused)]
 pub struct ApplicationSettings {
     pub api_token: Secret<String>,
+    #[serde(default)]
+    pub throttle: ThrottleSettings,
+    #[serde(default)]
+    pub dead_letter_file: Option<String>,
+    #[serde(default = "default_slow_handler_ms")]
+    pub slow_handler_ms: u64,
+}
+
+fn default_slow_handler_ms() -> u64 {
+    2000
+}
+
+/// Limits of the messages sent to Telegram.
+///
+/// # Description
+///
+/// Messages beyond these limits are queued until they can be sent. The defaults are the
+/// limits documented by Telegram for regular bots.
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+#[serde(default)]
+pub struct ThrottleSettings {
+    /// Messages per second, overall.
+    pub messages_per_sec_overall: u32,
+    /// Messages per second to the same chat.
+    pub messages_per_sec_chat: u32,
+    /// Messages per minute to the same chat.
+    pub messages_per_min_chat: u32,
+    /// Messages per minute to the same channel.
+    pub messages_per_min_channel: u32,
+    /// Retry the requests that Telegram rejects with a `RetryAfter` error, once the
+    /// requested time has passed.
+    pub retry: bool,
+}
+
+impl Default for ThrottleSettings {
+    fn default() -> Self {
+        let limits = Limits::default();
+
+        ThrottleSettings {
+            messages_per_sec_overall: limits.messages_per_sec_overall,
+            messages_per_sec_chat: limits.messages_per_sec_chat,
+            messages_per_min_chat: limits.messages_per_min_chat,
+            messages_per_min_channel: limits.messages_per_min_channel,
+            retry: true,
+        }
+    }
+}
+
+impl ThrottleSettings {
+    /// Build the settings of the [Throttle][teloxide::adaptors::Throttle] adaptor.
+    pub fn build_settings(&self) -> throttle::Settings {
+        let settings = throttle::Settings::default().limits(Limits {
+            messages_per_sec_overall: self.messages_per_sec_overall,
+            messages_per_sec_chat: self.messages_per_sec_chat,
+            messages_per_min_chat: self.messages_per_min_chat,
+            messages_per_min_channel: self.messages_per_min_channel,
+        });
+
+        if self.retry {
+            settings
+        } else {
+            settings.no_retry()
+        }
+    }
+}
+
+/// Settings of the webhook mode.
+///
+/// # Description
+///
+/// - [WebhookSettings::url]: public URL that Telegram sends the updates to.
+/// - [WebhookSettings::address]: local address of the server, e.g. `0.0.0.0:8443`. The
+///   public URL shall be forwarded to this address.
+/// - [WebhookSettings::secret_token]: token that Telegram sends in the header
+///   `X-Telegram-Bot-Api-Secret-Token` of every update. Updates without it are rejected.
+///   1-256 characters among `A-Z`, `a-z`, `0-9`, `_` and `-`. A random token is generated
+///   at startup when missing. Override it using an environment variable:
+///   `export SHORTBOT__WEBHOOK__SECRET_TOKEN="token"`.
+/// - [WebhookSettings::drift_check_period]: seconds between two checks of the webhook
+///   registered in Telegram.
+/// - [WebhookSettings::max_body_size]: maximum size (bytes) of the body of the requests
+///   to the server. Bigger requests are rejected.
+/// - [WebhookSettings::max_auth_failures]: requests with a wrong secret token allowed
+///   from an IP before locking it out.
+/// - [WebhookSettings::auth_lockout_period]: seconds that an IP stays locked out, and
+///   seconds that its failures are remembered.
+/// - [WebhookSettings::trusted_proxies]: IPs of the reverse proxies in front of the
+///   server. The source of their requests is read from the `X-Forwarded-For` header, so
+///   the lockout doesn't apply to the proxy itself.
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+pub struct WebhookSettings {
+    pub url: String,
+    pub address: String,
+    #[serde(default)]
+    pub secret_token: Option<Secret<String>>,
+    #[serde(default = "default_drift_check_period")]
+    pub drift_check_period: u64,
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    #[serde(default = "default_max_auth_failures")]
+    pub max_auth_failures: u32,
+    #[serde(default = "default_auth_lockout_period")]
+    pub auth_lockout_period: u64,
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+fn default_drift_check_period() -> u64 {
+    300
+}
+
+fn default_max_body_size() -> usize {
+    1024 * 1024
+}
+
+fn default_max_auth_failures() -> u32 {
+    5
+}
+
+fn default_auth_lockout_period() -> u64 {
+    900
+}
+
+/// Settings of the data providers of short positions.
+///
+/// # Description
+///
+/// - [ProviderSettings::registry_file]: path or URL of a bulk file (CSV) with the net
+///   short positions published by ESMA or the CNMV. When given, it is used as a
+///   fallback of the CNMV's web page.
+/// - [ProviderSettings::shadow_registry_file]: path or URL of a bulk file used by a
+///   provider in shadow mode: its answers are compared with the ones shown to the
+///   users, and the differences are logged.
+/// - [ProviderSettings::http]: settings of the HTTP client shared by all the providers.
+/// - [ProviderSettings::scraping]: limits of the requests to the external web pages.
+/// - [ProviderSettings::cache]: settings of the cache of short positions.
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+pub struct ProviderSettings {
+    #[serde(default)]
+    pub registry_file: Option<String>,
+    #[serde(default)]
+    pub shadow_registry_file: Option<String>,
+    #[serde(default)]
+    pub http: HttpClientSettings,
+    #[serde(default)]
+    pub scraping: ScrapingSettings,
+    #[serde(default)]
+    pub cache: CacheSettings,
+}
+
+/// Settings of the cache of short positions.
+///
+/// # Description
+///
+/// Periods are given in seconds.
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+#[serde(default)]
+pub struct CacheSettings {
+    /// Time after which the short positions of a company are fetched again.
+    pub ttl: u64,
+    /// Time between two refreshes of the short positions of the whole index.
+    pub refresh_period: u64,
+    /// Time after which the registry files are read again.
+    pub registry_ttl: u64,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        CacheSettings {
+            ttl: 900,
+            refresh_period: 3600,
+            registry_ttl: 3600,
+        }
+    }
+}
+
+/// Settings of the coordinator of the requests to external web pages.
+///
+/// # Description
+///
+/// Delays are given in milliseconds.
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+#[serde(default)]
+pub struct ScrapingSettings {
+    /// Minimum time between two requests to the same domain.
+    pub default_delay: u64,
+    /// Specific delays for some domains, e.g. `"www.cnmv.es" = 2000`.
+    pub domain_delays: HashMap<String, u64>,
+    /// Maximum number of requests waiting for their turn.
+    pub max_queue: usize,
+}
+
+impl Default for ScrapingSettings {
+    fn default() -> Self {
+        ScrapingSettings {
+            default_delay: 1000,
+            domain_delays: HashMap::new(),
+            max_queue: 64,
+        }
+    }
+}
+
+impl ScrapingSettings {
+    /// Build a [ScrapeCoordinator] using these settings.
+    pub fn build_coordinator(&self) -> ScrapeCoordinator {
+        ScrapeCoordinator::new(
+            Duration::from_millis(self.default_delay),
+            self.domain_delays
+                .iter()
+                .map(|(domain, delay)| (domain.clone(), Duration::from_millis(*delay)))
+                .collect(),
+            self.max_queue,
+        )
+    }
+}
+
+/// Settings of the HTTP client used to reach external data sources.
+///
+/// # Description
+///
+/// All the values are optional, the defaults are meant for a regular deployment.
+/// Timeouts are given in seconds.
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+#[serde(default)]
+pub struct HttpClientSettings {
+    /// Timeout of a whole request.
+    pub timeout: u64,
+    /// Timeout of the connection stage of a request.
+    pub connect_timeout: u64,
+    /// Value of the `User-Agent` header.
+    pub user_agent: String,
+    /// URL of a proxy for all the requests, e.g. `http://proxy:3128`.
+    pub proxy: Option<String>,
+    /// Maximum number of idle connections kept per host.
+    pub pool_max_idle_per_host: usize,
+    /// Time after which an idle connection is closed.
+    pub pool_idle_timeout: u64,
+}
+
+impl Default for HttpClientSettings {
+    fn default() -> Self {
+        HttpClientSettings {
+            timeout: 30,
+            connect_timeout: 10,
+            user_agent: format!("ShortBot/{}", env!("CARGO_PKG_VERSION")),
+            proxy: None,
+            pool_max_idle_per_host: 4,
+            pool_idle_timeout: 90,
+        }
+    }
+}
+
+impl HttpClientSettings {
+    /// Build a [reqwest::Client] using these settings.
+    ///
+    /// # Description
+    ///
+    /// The client keeps a pool of connections, thus it shall be built once and shared by
+    /// all the objects that send requests.
+    pub fn build_client(&self) -> Result<reqwest::Client, reqwest::Error> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(self.timeout))
+            .connect_timeout(Duration::from_secs(self.connect_timeout))
+            .user_agent(self.user_agent.as_str())
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(Duration::from_secs(self.pool_idle_timeout));
+
+        if let Some(proxy) = self.proxy.as_deref() {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+
+        builder.build()
+    }
 }
 
 impl Settings {
@@ -68,6 +455,28 @@ impl Settings {
             .add_source(Environment::with_prefix("shortbot").separator("__"))
             .build()?;
 
-        settings.try_deserialize()
+        let settings: Settings = settings.try_deserialize()?;
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    /// Reject the values that would make the background tasks panic, as periods of 0
+    /// seconds can't be used to build a [tokio::time::Interval].
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut periods = vec![(
+            "providers.cache.refresh_period",
+            self.providers.cache.refresh_period,
+        )];
+        if let Some(webhook) = &self.webhook {
+            periods.push(("webhook.drift_check_period", webhook.drift_check_period));
+        }
+
+        match periods.into_iter().find(|(_, period)| *period == 0) {
+            Some((name, _)) => Err(ConfigError::Message(format!(
+                "{name} must be greater than 0"
+            ))),
+            None => Ok(()),
+        }
     }
 }
@@ -0,0 +1,218 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Dead-letter store of the updates that the bot could not handle.
+//!
+//! # Description
+//!
+//! Updates that don't match any branch of the dispatcher (e.g. edited messages or channel
+//! posts) and messages that the bot doesn't understand are recorded here, so new
+//! interaction patterns can be analyzed and supported later.
+//!
+//! Every update is logged, but the raw payload is only written when a file is configured
+//! ([ApplicationSettings::dead_letter_file][crate::configuration::ApplicationSettings]).
+//! Each line of the file is a JSON document:
+//!
+//! ```json
+//! {"timestamp": <UNIX time>, "kind": "<kind of update>", "reason": "<reason>", "update": {...}}
+//! ```
+//!
+//! Beware that the payloads include the identifiers and the messages of the users.
+//!
+//! The file is written by a dedicated thread that keeps it open, so the handlers never
+//! wait for the disk. When the thread can't keep up, the payloads that don't fit in its
+//! queue are dropped (the updates are still logged).
+
+use serde_json::{json, Value};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+use teloxide::types::Update;
+use tracing::{error, warn};
+
+/// Maximum number of payloads waiting to be written.
+const QUEUE_SIZE: usize = 1024;
+
+/// Store of the updates that the bot could not handle.
+#[derive(Debug, Default)]
+pub struct DeadLetters {
+    /// Queue of the lines for the writer thread.
+    sender: Option<SyncSender<String>>,
+    writer: Option<JoinHandle<()>>,
+    count: AtomicU64,
+}
+
+impl DeadLetters {
+    /// Build a store that writes the payloads to `path`, or only logs them when `None`.
+    pub fn new(path: Option<&str>) -> DeadLetters {
+        let Some(path) = path.map(PathBuf::from) else {
+            return DeadLetters::default();
+        };
+        let (sender, receiver) = sync_channel(QUEUE_SIZE);
+        let writer = std::thread::Builder::new()
+            .name(String::from("dead-letters"))
+            .spawn(move || write_lines(path, receiver))
+            .map_err(|e| error!("Failed to start the writer of the dead letters: {e}"))
+            .ok();
+
+        DeadLetters {
+            sender: writer.is_some().then_some(sender),
+            writer,
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the number of updates recorded since the start of the bot.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Record an update that could not be handled.
+    ///
+    /// ## Arguments
+    ///
+    /// - _update_: the raw update received from Telegram.
+    /// - _reason_: why the update was not handled.
+    pub fn record(&self, update: &Update, reason: &str) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+
+        let payload = serde_json::to_value(update).unwrap_or(Value::Null);
+        let kind = update_kind(&payload);
+        warn!("Dead letter: update {} ({kind}), {reason}", update.id);
+
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_secs());
+        let line = json!({
+            "timestamp": timestamp,
+            "kind": kind,
+            "reason": reason,
+            "update": payload,
+        });
+
+        match sender.try_send(line.to_string()) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                warn!(
+                    "Too many dead letters waiting, the payload of {} is dropped",
+                    update.id
+                )
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                error!("The writer of the dead letters is not running")
+            }
+        }
+    }
+}
+
+impl Drop for DeadLetters {
+    /// Wait until the pending payloads are written.
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Write the received lines to the file at `path`, until the channel is closed.
+fn write_lines(path: PathBuf, lines: Receiver<String>) {
+    let mut file = match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            error!(
+                "Failed to open the dead letter file {}: {e}",
+                path.display()
+            );
+            return;
+        }
+    };
+
+    for line in lines {
+        if let Err(e) = writeln!(file, "{line}") {
+            error!("Failed to write the dead letter to {}: {e}", path.display());
+        }
+    }
+}
+
+/// Get the kind of an update out of its JSON representation, e.g. `edited_message`.
+fn update_kind(payload: &Value) -> &str {
+    payload
+        .as_object()
+        .and_then(|fields| fields.keys().find(|key| *key != "update_id"))
+        .map_or("unknown", |key| key.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::fs::{read_to_string, remove_file};
+
+    fn edited_message() -> Update {
+        serde_json::from_str(
+            r#"{
+                "update_id": 42,
+                "edited_message": {
+                    "message_id": 7,
+                    "date": 1718182800,
+                    "edit_date": 1718182860,
+                    "chat": {"id": 1234, "type": "private", "first_name": "Ana"},
+                    "from": {"id": 1234, "is_bot": false, "first_name": "Ana"},
+                    "text": "GRF"
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[rstest]
+    fn record_to_file() {
+        let path = std::env::temp_dir().join(format!("dead_letters_{}.jsonl", std::process::id()));
+        let dead_letters = DeadLetters::new(path.to_str());
+
+        dead_letters.record(&edited_message(), "no handler");
+        dead_letters.record(&edited_message(), "no handler");
+        assert_eq!(dead_letters.count(), 2);
+
+        // Dropping the store waits for the writer.
+        drop(dead_letters);
+        let content = read_to_string(&path).unwrap();
+        remove_file(&path).unwrap();
+
+        assert_eq!(content.lines().count(), 2);
+
+        let line: Value = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(line["kind"], "edited_message");
+        assert_eq!(line["reason"], "no handler");
+        assert_eq!(line["update"]["edited_message"]["text"], "GRF");
+    }
+
+    #[rstest]
+    fn record_without_file() {
+        let dead_letters = DeadLetters::new(None);
+
+        dead_letters.record(&edited_message(), "no handler");
+
+        assert_eq!(dead_letters.count(), 1);
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Handler for the /about command.
+
+use crate::finance::{Ibex35Market, ShortCache};
+use crate::telemetry::{redact, CorrelationId};
+use crate::{HandlerResult, StartTime, ThrottledBot};
+use std::sync::Arc;
+use std::time::Duration;
+use teloxide::{prelude::*, types::ParseMode};
+use tracing::{debug, info};
+
+/// About handler.
+#[tracing::instrument(
+    name = "About handler",
+    skip(bot, msg, update, stock_market, cache, start_time, cid),
+    fields(
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
+    )
+)]
+pub async fn about(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    stock_market: Arc<Ibex35Market>,
+    cache: Arc<ShortCache>,
+    start_time: StartTime,
+    cid: CorrelationId,
+) -> HandlerResult {
+    info!("Command /about requested");
+
+    // First, try to retrieve the user of the chat.
+    let lang_code = match update.user() {
+        Some(user) => user.language_code.clone(),
+        None => None,
+    };
+
+    debug!("The user's language code is: {:?}", lang_code);
+
+    let now = cache.clock().now();
+    let uptime = _format_duration(now.duration_since(start_time.0));
+    let companies = stock_market.get_companies().len();
+    let cached = cache.snapshot().len();
+    let last_refresh = cache
+        .last_refresh()
+        .map(|instant| _format_duration(now.duration_since(instant)));
+
+    let message = match lang_code.as_deref().unwrap_or("en") {
+        "es" => format!(
+            "🤖 <b>ShortBot</b> v{} ({})\n\n\
+             ⏱️ En marcha desde hace: {uptime}\n\
+             🏢 Empresas seguidas: {companies} ({cached} con datos)\n\
+             🔄 Última actualización de los datos: {}",
+            env!("CARGO_PKG_VERSION"),
+            env!("SHORTBOT_COMMIT"),
+            last_refresh.map_or(String::from("pendiente"), |t| format!("hace {t}")),
+        ),
+        _ => format!(
+            "🤖 <b>ShortBot</b> v{} ({})\n\n\
+             ⏱️ Uptime: {uptime}\n\
+             🏢 Tracked companies: {companies} ({cached} with data)\n\
+             🔄 Last data refresh: {}",
+            env!("CARGO_PKG_VERSION"),
+            env!("SHORTBOT_COMMIT"),
+            last_refresh.map_or(String::from("pending"), |t| format!("{t} ago")),
+        ),
+    };
+
+    bot.send_message(msg.chat.id, message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+/// Format a duration as days, hours and minutes, e.g. `2d 3h 15m`.
+fn _format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / 1440, (minutes / 60) % 24, minutes % 60);
+
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m"),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h {minutes}m"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0, "0m")]
+    #[case(59, "0m")]
+    #[case(61 * 60, "1h 1m")]
+    #[case(2 * 86400 + 3 * 3600 + 15 * 60, "2d 3h 15m")]
+    fn format_duration(#[case] secs: u64, #[case] expected: &str) {
+        assert_eq!(_format_duration(Duration::from_secs(secs)), expected);
+    }
+}
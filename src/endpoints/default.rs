@@ -14,20 +14,39 @@
 
 //! Handler for the /help command.
 
-use crate::HandlerResult;
+use super::start::{get_client_name, market_highlight};
+use crate::deadletter::DeadLetters;
+use crate::finance::{Ibex35Market, ShortCache};
+use crate::intent::mentions_shorts;
+use crate::telemetry::{redact, CorrelationId};
+use crate::templates::Templates;
+use crate::{HandlerResult, ThrottledBot};
+use std::sync::Arc;
 use teloxide::{prelude::*, types::ParseMode};
 use tracing::{debug, info};
 
 /// Help handler.
 #[tracing::instrument(
     name = "Default handler",
-    skip(bot, msg, update),
+    skip(bot, msg, update, dead_letters, templates, stock_market, cache, cid),
     fields(
-        chat_id = %msg.chat.id,
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
     )
 )]
-pub async fn default(bot: Bot, msg: Message, update: Update) -> HandlerResult {
+#[allow(clippy::too_many_arguments)]
+pub async fn default(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    dead_letters: Arc<DeadLetters>,
+    templates: Arc<Templates>,
+    stock_market: Arc<Ibex35Market>,
+    cache: Arc<ShortCache>,
+    cid: CorrelationId,
+) -> HandlerResult {
     info!("Garbage sent");
+    dead_letters.record(&update, "message not understood");
 
     // First, try to retrieve the user of the chat.
     let lang_code = match update.user() {
@@ -37,9 +56,22 @@ pub async fn default(bot: Bot, msg: Message, update: Update) -> HandlerResult {
 
     debug!("The user's language code is: {:?}", lang_code);
 
-    let message = match lang_code.as_deref().unwrap_or("en") {
-        "es" => _warning_es(),
-        _ => _warning_en(),
+    // Users that ask for short positions in plain words get a hint instead of a warning.
+    let asks_for_shorts = msg.text().is_some_and(mentions_shorts);
+
+    let lang_code = lang_code.as_deref().unwrap_or("en");
+    let message = match (lang_code, asks_for_shorts) {
+        ("es", true) => String::from(
+            "¿Quieres ver las posiciones en corto de una empresa? Usa /short seguido de su ticker o su nombre, por ejemplo: <code>/short BBVA</code>",
+        ),
+        (_, true) => String::from(
+            "Do you want to see the short positions of a company? Use /short followed by its ticker or its name, for example: <code>/short BBVA</code>",
+        ),
+        (_, false) => templates.fallback(
+            lang_code,
+            &get_client_name(&msg),
+            &market_highlight(&stock_market, &cache, lang_code),
+        ),
     };
 
     bot.send_message(msg.chat.id, message)
@@ -48,11 +80,3 @@ pub async fn default(bot: Bot, msg: Message, update: Update) -> HandlerResult {
 
     Ok(())
 }
-
-fn _warning_es() -> String {
-    include_str!("../../data/templates/warning_es.txt").to_owned()
-}
-
-fn _warning_en() -> String {
-    include_str!("../../data/templates/warning_en.txt").to_owned()
-}
@@ -0,0 +1,171 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Catalog of the errors shown to the users.
+//!
+//! # Description
+//!
+//! Each error is mapped to a localized message that tells the user what to do next.
+//! Errors that the user can't fix include the correlation ID, so they can be traced
+//! when reported.
+
+use crate::finance::{CNMVError, ProviderError, RegistryError};
+use crate::telemetry::CorrelationId;
+use crate::{HandlerResult, ThrottledBot};
+use teloxide::{prelude::*, types::ParseMode, utils::html};
+
+/// Errors shown to the users.
+#[derive(Debug)]
+pub enum UserError<'a> {
+    /// The short positions could not be retrieved.
+    Provider(&'a ProviderError),
+    /// The ticker is not a known company.
+    UnknownTicker(&'a str),
+    /// No company matches the query of the user (a ticker, an ISIN or a name).
+    UnknownQuery(&'a str),
+    /// No company matches some of the queries of a batch request.
+    UnknownQueries(&'a [&'a str]),
+    /// The data of the market has not been fetched yet.
+    DataNotReady,
+}
+
+/// Build the message that explains an error to the user.
+pub fn error_message(error: &UserError, lang_code: &str, cid: &CorrelationId) -> String {
+    _error_message(error, lang_code, cid.as_ref())
+}
+
+fn _error_message(error: &UserError, lang_code: &str, cid: &str) -> String {
+    match error {
+        UserError::Provider(e) => _provider_message(e, lang_code, cid),
+        UserError::UnknownTicker(ticker) => match lang_code {
+            "es" => format!(
+                "<b>{}</b> no es una empresa conocida. Usa /short para ver la lista de empresas.",
+                html::escape(ticker)
+            ),
+            _ => format!(
+                "<b>{}</b> is not a known company. Use /short to see the list of companies.",
+                html::escape(ticker)
+            ),
+        },
+        UserError::UnknownQuery(query) => match lang_code {
+            "es" => format!(
+                "No se ha encontrado ninguna empresa para <b>{}</b>. Usa /short sin argumentos para elegir una empresa del Ibex35.",
+                html::escape(query.trim())
+            ),
+            _ => format!(
+                "No company found for <b>{}</b>. Use /short without arguments to pick a company of the Ibex35.",
+                html::escape(query.trim())
+            ),
+        },
+        UserError::UnknownQueries(queries) => {
+            let queries: Vec<String> = queries.iter().map(|q| html::escape(q)).collect();
+            match lang_code {
+                "es" => format!("No encontradas: {}", queries.join(", ")),
+                _ => format!("Not found: {}", queries.join(", ")),
+            }
+        }
+        UserError::DataNotReady => String::from(match lang_code {
+            "es" => "Los datos aún no están disponibles, inténtalo de nuevo en unos minutos.",
+            _ => "The data is not available yet, try again in a few minutes.",
+        }),
+    }
+}
+
+/// Send the message that explains an error to the user.
+pub async fn reply_error(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    error: UserError<'_>,
+    lang_code: &str,
+    cid: &CorrelationId,
+) -> HandlerResult {
+    bot.send_message(chat_id, error_message(&error, lang_code, cid))
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+fn _provider_message(error: &ProviderError, lang_code: &str, cid: &str) -> String {
+    match (error, lang_code) {
+        (ProviderError::Cnmv(CNMVError::Busy), "es") => {
+            String::from("La fuente de datos está saturada, inténtalo de nuevo en un minuto.")
+        }
+        (ProviderError::Cnmv(CNMVError::Busy), _) => {
+            String::from("The data source is busy, try again in a minute.")
+        }
+        (ProviderError::Cnmv(CNMVError::UnknownCompany), "es") => {
+            String::from("El regulador no publica datos de esta empresa.")
+        }
+        (ProviderError::Cnmv(CNMVError::UnknownCompany), _) => {
+            String::from("The regulator does not publish data of this company.")
+        }
+        (
+            ProviderError::Cnmv(CNMVError::ExternalError(_))
+            | ProviderError::Registry(RegistryError::Source(_)),
+            "es",
+        ) => String::from("La fuente de datos no responde, inténtalo de nuevo en unos minutos."),
+        (
+            ProviderError::Cnmv(CNMVError::ExternalError(_))
+            | ProviderError::Registry(RegistryError::Source(_)),
+            _,
+        ) => String::from("The data source is not responding, try again in a few minutes."),
+        (_, "es") => format!(
+            "Información no disponible, el problema ha quedado registrado (id del error: {cid})"
+        ),
+        (_, _) => format!("Information not available, the issue has been logged (error id: {cid})"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(ProviderError::Cnmv(CNMVError::Busy), "busy", false)]
+    #[case(
+        ProviderError::Cnmv(CNMVError::ExternalError(String::from("timeout"))),
+        "not responding",
+        false
+    )]
+    #[case(
+        ProviderError::Registry(RegistryError::Source(String::from("404"))),
+        "not responding",
+        false
+    )]
+    #[case(ProviderError::Cnmv(CNMVError::MissingColumn("owner")), "logged", true)]
+    #[case(ProviderError::NoProvider, "logged", true)]
+    fn provider_errors(#[case] error: ProviderError, #[case] hint: &str, #[case] with_id: bool) {
+        let message = _error_message(&UserError::Provider(&error), "en", "abc123");
+
+        assert!(message.contains(hint), "{message}");
+        assert_eq!(message.contains("abc123"), with_id, "{message}");
+    }
+
+    #[rstest]
+    fn unknown_ticker() {
+        assert_eq!(
+            _error_message(&UserError::UnknownTicker("<b>"), "es", "abc123"),
+            "<b>&lt;b&gt;</b> no es una empresa conocida. Usa /short para ver la lista de empresas."
+        );
+    }
+
+    #[rstest]
+    #[case(UserError::UnknownQuery(" <XYZ> "), "No company found for <b>&lt;XYZ&gt;</b>. Use /short without arguments to pick a company of the Ibex35.")]
+    #[case(UserError::UnknownQueries(&["XYZ", "A&B"]), "Not found: XYZ, A&amp;B")]
+    fn unknown_queries(#[case] error: UserError, #[case] expected: &str) {
+        assert_eq!(_error_message(&error, "en", "abc123"), expected);
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Handler for the /exposure command.
+//!
+//! # Description
+//!
+//! This is an educational helper: it relates the user's holding of a stock with the
+//! share of the company's capital that is currently sold short.
+
+use crate::endpoints::{error_message, reply_error, UserError};
+use crate::finance::{Ibex35Market, ShortCache};
+use crate::telemetry::{redact, CorrelationId};
+use crate::{HandlerResult, ThrottledBot};
+use std::sync::Arc;
+use teloxide::{prelude::*, types::ParseMode};
+use tracing::{debug, error, info};
+
+/// Maximum number of shares accepted as input.
+const MAX_SHARES: u64 = 1_000_000_000_000;
+
+/// Errors of the arguments of the /exposure command.
+#[derive(Debug, PartialEq)]
+enum InputError {
+    /// The command needs a ticker and a number of shares.
+    Usage,
+    /// The number of shares is not a positive integer or it is too big.
+    Shares,
+}
+
+/// Exposure handler.
+#[tracing::instrument(
+    name = "Exposure handler",
+    skip(bot, msg, update, args, stock_market, cache, cid),
+    fields(
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
+    )
+)]
+pub async fn exposure(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    args: String,
+    stock_market: Arc<Ibex35Market>,
+    cache: Arc<ShortCache>,
+    cid: CorrelationId,
+) -> HandlerResult {
+    info!("Command /exposure requested");
+
+    // First, try to retrieve the user of the chat.
+    let lang_code = match update.user() {
+        Some(user) => user.language_code.clone(),
+        None => None,
+    };
+
+    let lang_code = match lang_code.as_deref().unwrap_or("en") {
+        "es" => "es",
+        _ => "en",
+    };
+
+    debug!("The user's language code is: {:?}", lang_code);
+
+    let (ticker, shares) = match _parse_args(&args) {
+        Ok(input) => input,
+        Err(e) => {
+            info!("Wrong arguments for /exposure: {:?}", e);
+            bot.send_message(msg.chat.id, _input_error_msg(e, lang_code))
+                .parse_mode(ParseMode::Html)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let Some(stock) = stock_market.stock_by_ticker(&ticker) else {
+        info!("Unknown ticker for /exposure: {ticker}");
+        return reply_error(
+            &bot,
+            msg.chat.id,
+            UserError::UnknownTicker(&ticker),
+            lang_code,
+            &cid,
+        )
+        .await;
+    };
+
+    let message = match cache.short_positions(stock).await {
+        Ok(shorts) => {
+            let short_shares = (shares as f64 * shares_ratio(shorts.total)).round() as u64;
+            match lang_code {
+                "es" => format!(
+                    "📐 El <b>{:.2} %</b> del capital de {} está vendido en corto.\n\n\
+                     Por cada una de tus {} acciones, hay {:.4} acciones vendidas en corto: \
+                     el equivalente a <b>{}</b> acciones.\n\n\
+                     <i>Esta es una estimación con fines educativos, no una recomendación de inversión.</i>",
+                    shorts.total,
+                    stock.name(),
+                    _format_thousands(shares, '.'),
+                    shares_ratio(shorts.total),
+                    _format_thousands(short_shares, '.'),
+                ),
+                _ => format!(
+                    "📐 <b>{:.2} %</b> of {}'s capital is sold short.\n\n\
+                     For each of your {} shares, {:.4} shares are sold short: \
+                     the equivalent of <b>{}</b> shares.\n\n\
+                     <i>This is an educational estimate, not investment advice.</i>",
+                    shorts.total,
+                    stock.name(),
+                    _format_thousands(shares, ','),
+                    shares_ratio(shorts.total),
+                    _format_thousands(short_shares, ','),
+                ),
+            }
+        }
+        Err(e) => {
+            error!("Failed to retrieve the short positions: {e}");
+            error_message(&UserError::Provider(&e), lang_code, &cid)
+        }
+    };
+
+    bot.send_message(msg.chat.id, message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+/// Convert a percentage of the capital to a ratio of shares.
+fn shares_ratio(weight: f32) -> f64 {
+    weight as f64 / 100.0
+}
+
+/// Parse the arguments of the command: `<ticker> <shares>`.
+///
+/// # Description
+///
+/// The ticker is not case sensitive. The number of shares accepts thousands separators,
+/// such as `1,000`, `1.000` or `1_000`, as long as they split groups of three digits.
+fn _parse_args(args: &str) -> Result<(String, u64), InputError> {
+    let mut tokens = args.split_whitespace();
+
+    let (Some(ticker), Some(shares), None) = (tokens.next(), tokens.next(), tokens.next()) else {
+        return Err(InputError::Usage);
+    };
+
+    let digits = _strip_separators(shares).ok_or(InputError::Shares)?;
+
+    match digits.parse::<u64>() {
+        Ok(shares) if shares > 0 && shares <= MAX_SHARES => Ok((ticker.to_uppercase(), shares)),
+        _ => Err(InputError::Shares),
+    }
+}
+
+/// Remove the thousands separators of a number.
+///
+/// # Description
+///
+/// A separator is only accepted when it splits well-formed groups of three digits, and the
+/// same separator must be used across the whole number. Anything else, such as a decimal
+/// like `10.5`, returns `None`.
+fn _strip_separators(number: &str) -> Option<String> {
+    let separator = number.chars().find(|c| matches!(c, ',' | '.' | '_'));
+    let groups: Vec<&str> = match separator {
+        Some(separator) => number.split(separator).collect(),
+        None => vec![number],
+    };
+
+    let well_formed = groups.iter().enumerate().all(|(i, group)| {
+        let valid_len = if i == 0 {
+            !group.is_empty() && (groups.len() == 1 || group.len() <= 3)
+        } else {
+            group.len() == 3
+        };
+        valid_len && group.chars().all(|c| c.is_ascii_digit())
+    });
+
+    well_formed.then(|| groups.concat())
+}
+
+fn _input_error_msg(error: InputError, lang_code: &str) -> &'static str {
+    match (error, lang_code) {
+        (InputError::Usage, "es") => {
+            "Uso: <code>/exposicion TICKER ACCIONES</code>, por ejemplo: <code>/exposicion GRF 1000</code>"
+        }
+        (InputError::Usage, _) => {
+            "Usage: <code>/exposure TICKER SHARES</code>, for example: <code>/exposure GRF 1000</code>"
+        }
+        (InputError::Shares, "es") => "El número de acciones debe ser un entero positivo.",
+        (InputError::Shares, _) => "The number of shares must be a positive integer.",
+    }
+}
+
+/// Format an integer using a separator for the thousands.
+fn _format_thousands(n: u64, separator: char) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(separator);
+        }
+        formatted.push(c);
+    }
+
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("GRF 1000", Ok((String::from("GRF"), 1000)))]
+    #[case("  grf   1,000 ", Ok((String::from("GRF"), 1000)))]
+    #[case("SAN 1.250.000", Ok((String::from("SAN"), 1_250_000)))]
+    #[case("", Err(InputError::Usage))]
+    #[case("GRF", Err(InputError::Usage))]
+    #[case("GRF 1000 2000", Err(InputError::Usage))]
+    #[case("GRF 0", Err(InputError::Shares))]
+    #[case("GRF -5", Err(InputError::Shares))]
+    #[case("GRF 10.5k", Err(InputError::Shares))]
+    #[case("GRF ,.", Err(InputError::Shares))]
+    #[case("GRF 10.5", Err(InputError::Shares))]
+    #[case("GRF 1,5", Err(InputError::Shares))]
+    #[case("GRF 1_000", Ok((String::from("GRF"), 1000)))]
+    #[case("GRF 1,000.000", Err(InputError::Shares))]
+    #[case("GRF 1000,000", Err(InputError::Shares))]
+    #[case("GRF 99999999999999999999", Err(InputError::Shares))]
+    fn parse_args(#[case] args: &str, #[case] expected: Result<(String, u64), InputError>) {
+        assert_eq!(_parse_args(args), expected);
+    }
+
+    #[rstest]
+    #[case(0, "0")]
+    #[case(999, "999")]
+    #[case(1000, "1.000")]
+    #[case(1_234_567, "1.234.567")]
+    fn format_thousands(#[case] n: u64, #[case] expected: &str) {
+        assert_eq!(_format_thousands(n, '.'), expected);
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Handler for the /fundstats command.
+//!
+//! # Description
+//!
+//! The report is built from the alive positions kept by the [ShortCache]. The dates in
+//! which the fund opened and closed its positions are not included: the exits can only
+//! be found in the historical series of the CNMV, which would take one scrape per
+//! company of the index for each request.
+
+use crate::endpoints::{error_message, UserError};
+use crate::finance::{owner_key, FundSummary, Ibex35Market, OwnerAliases, ShortCache};
+use crate::telemetry::{redact, CorrelationId};
+use crate::{HandlerResult, ThrottledBot};
+use std::sync::Arc;
+use teloxide::{prelude::*, types::ParseMode, utils::html};
+use tracing::{debug, info};
+
+/// Maximum number of funds listed when the query is ambiguous.
+const MAX_CANDIDATES: usize = 10;
+
+/// Fund statistics handler.
+#[tracing::instrument(
+    name = "Fund stats handler",
+    skip(bot, msg, update, query, stock_market, cache, aliases, cid),
+    fields(
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn fund_stats(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    query: String,
+    stock_market: Arc<Ibex35Market>,
+    cache: Arc<ShortCache>,
+    aliases: Arc<OwnerAliases>,
+    cid: CorrelationId,
+) -> HandlerResult {
+    info!("Command /fundstats requested");
+
+    // First, try to retrieve the user of the chat.
+    let lang_code = match update.user() {
+        Some(user) => user.language_code.clone(),
+        None => None,
+    };
+
+    let lang_code = match lang_code.as_deref().unwrap_or("en") {
+        "es" => "es",
+        _ => "en",
+    };
+
+    debug!("The user's language code is: {:?}", lang_code);
+
+    let message = if owner_key(&query).is_empty() {
+        String::from(match lang_code {
+            "es" => "Uso: <code>/fondo NOMBRE</code>, por ejemplo: <code>/fondo Marshall Wace</code>",
+            _ => "Usage: <code>/fundstats NAME</code>, for example: <code>/fundstats Marshall Wace</code>",
+        })
+    } else {
+        let funds = cache.funds(&stock_market, &aliases);
+        debug!("{} funds with alive positions", funds.len());
+
+        if funds.is_empty() {
+            error_message(&UserError::DataNotReady, lang_code, &cid)
+        } else {
+            let companies = cache
+                .snapshot()
+                .keys()
+                .filter(|ticker| stock_market.stock_by_ticker(ticker.as_str()).is_some())
+                .count();
+
+            match _find_funds(&funds, &aliases.key(&query))[..] {
+                [] => match lang_code {
+                    "es" => format!(
+                        "No hay posiciones en corto abiertas de <b>{}</b>.",
+                        html::escape(query.trim())
+                    ),
+                    _ => format!(
+                        "There are no open short positions of <b>{}</b>.",
+                        html::escape(query.trim())
+                    ),
+                },
+                [fund] => _fund_msg(fund, companies, lang_code),
+                ref candidates => _candidates_msg(candidates, lang_code),
+            }
+        }
+    };
+
+    bot.send_message(msg.chat.id, message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+/// Find the funds whose key contains the key of the query.
+///
+/// # Description
+///
+/// Comparing the keys ignores the case and the punctuation of the names. A fund whose
+/// key is exactly the key of the query is preferred over partial matches.
+fn _find_funds<'a>(funds: &'a [FundSummary], key: &str) -> Vec<&'a FundSummary> {
+    if let Some(fund) = funds.iter().find(|fund| fund.key == key) {
+        return vec![fund];
+    }
+
+    funds.iter().filter(|fund| fund.key.contains(key)).collect()
+}
+
+fn _fund_msg(fund: &FundSummary, companies: usize, lang_code: &str) -> String {
+    let mut message = match lang_code {
+        "es" => format!(
+            "🏦 <b>{}</b>\n\nPosiciones en corto en <b>{}</b> de {} empresas del Ibex35 con datos.\n𝚺 Suma de las posiciones: <b>{:.2} %</b>\n",
+            html::escape(&fund.name),
+            fund.positions.len(),
+            companies,
+            fund.total_weight(),
+        ),
+        _ => format!(
+            "🏦 <b>{}</b>\n\nShort positions in <b>{}</b> of {} companies of the Ibex35 with data.\n𝚺 Summation of the positions: <b>{:.2} %</b>\n",
+            html::escape(&fund.name),
+            fund.positions.len(),
+            companies,
+            fund.total_weight(),
+        ),
+    };
+
+    for (ticker, weight) in &fund.positions {
+        message.push_str(&format!("\n✓ {ticker}: <b>{weight} %</b>"));
+    }
+
+    message.push_str(match lang_code {
+        "es" => "\n\n<i>Solo se incluyen las posiciones abiertas notificadas (>= 0.5%).</i>",
+        _ => "\n\n<i>Only the notified open positions are included (>= 0.5%).</i>",
+    });
+
+    message
+}
+
+fn _candidates_msg(candidates: &[&FundSummary], lang_code: &str) -> String {
+    let mut message = String::from(match lang_code {
+        "es" => "Varios fondos coinciden con la búsqueda:\n",
+        _ => "Several funds match the search:\n",
+    });
+
+    for fund in candidates.iter().take(MAX_CANDIDATES) {
+        message.push_str(&format!("\n• {}", html::escape(&fund.name)));
+    }
+
+    if candidates.len() > MAX_CANDIDATES {
+        message.push_str(&match lang_code {
+            "es" => format!("\n… y {} más", candidates.len() - MAX_CANDIDATES),
+            _ => format!("\n… and {} more", candidates.len() - MAX_CANDIDATES),
+        });
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finance::Ticker;
+    use rstest::{fixture, rstest};
+
+    #[fixture]
+    fn funds() -> Vec<FundSummary> {
+        [
+            "Marshall Wace LLP",
+            "Millennium Partners LP",
+            "Millennium Capital LP",
+        ]
+        .into_iter()
+        .map(|name| FundSummary {
+            name: String::from(name),
+            key: owner_key(name),
+            positions: vec![(Ticker::new("GRF").unwrap(), 0.5)],
+        })
+        .collect()
+    }
+
+    #[rstest]
+    #[case("marshall wace", vec!["Marshall Wace LLP"])]
+    #[case("Marshall Wace, LLP", vec!["Marshall Wace LLP"])]
+    #[case("millennium", vec!["Millennium Partners LP", "Millennium Capital LP"])]
+    #[case("millennium partners lp", vec!["Millennium Partners LP"])]
+    #[case("citadel", vec![])]
+    fn find_funds(funds: Vec<FundSummary>, #[case] query: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(
+            _find_funds(&funds, &owner_key(query))
+                .iter()
+                .map(|fund| fund.name.as_str())
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Handlers for the /glossary command and its keyboard.
+
+use crate::glossary::{Glossary, GlossaryEntry};
+use crate::telemetry::{redact, CorrelationId};
+use crate::{HandlerResult, ThrottledBot};
+use std::sync::Arc;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode},
+    utils::html,
+};
+use tracing::{debug, info};
+
+/// Prefix of the callback data of the glossary buttons.
+pub const GLOSSARY_CALLBACK: &str = "glossary:";
+
+/// Glossary handler.
+#[tracing::instrument(
+    name = "Glossary handler",
+    skip(bot, msg, update, term, glossary, cid),
+    fields(
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
+    )
+)]
+pub async fn glossary(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    term: String,
+    glossary: Arc<Glossary>,
+    cid: CorrelationId,
+) -> HandlerResult {
+    info!("Command /glossary requested");
+
+    let lang_code = _lang_code(&update);
+    debug!("The user's language code is: {:?}", lang_code);
+
+    let message = if term.trim().is_empty() {
+        String::from(match lang_code {
+            "es" => "📖 Elige un término del glosario:",
+            _ => "📖 Choose a term of the glossary:",
+        })
+    } else {
+        match glossary.search(&term) {
+            Some((key, entry)) => {
+                debug!("Found the glossary entry {key}");
+                _definition_msg(entry, lang_code)
+            }
+            None => _not_found_msg(&term, lang_code),
+        }
+    };
+
+    bot.send_message(msg.chat.id, message)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(_glossary_keyboard(&glossary, lang_code))
+        .await?;
+
+    Ok(())
+}
+
+/// Handler of the buttons of the glossary.
+#[tracing::instrument(
+    name = "Glossary term handler",
+    skip(bot, q, update, glossary, cid),
+    fields(
+        chat_id = %redact(q.from.id),
+        correlation_id = %cid,
+    )
+)]
+pub async fn glossary_term(
+    bot: ThrottledBot,
+    q: CallbackQuery,
+    update: Update,
+    glossary: Arc<Glossary>,
+    cid: CorrelationId,
+) -> HandlerResult {
+    let lang_code = _lang_code(&update);
+    let key = q
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix(GLOSSARY_CALLBACK))
+        .unwrap_or_default();
+
+    info!("Glossary term requested: {key}");
+
+    bot.answer_callback_query(q.id.clone()).await?;
+
+    let chat_id = match &q.message {
+        Some(message) => message.chat.id,
+        None => q.from.id.into(),
+    };
+
+    let message = match glossary.entry(key) {
+        Some(entry) => _definition_msg(entry, lang_code),
+        None => String::from(match lang_code {
+            "es" => "Ese término ya no está en el glosario.",
+            _ => "That term is no longer in the glossary.",
+        }),
+    };
+
+    bot.send_message(chat_id, message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+/// Build a button that opens a term of the glossary.
+///
+/// # Description
+///
+/// This is meant to link the concepts used by other messages, such as the reports
+/// of short positions, with their explanation.
+pub fn glossary_button(text: &str, key: &str) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(text, format!("{GLOSSARY_CALLBACK}{key}"))
+}
+
+fn _lang_code(update: &Update) -> &'static str {
+    match update.user().and_then(|user| user.language_code.as_deref()) {
+        Some("es") => "es",
+        _ => "en",
+    }
+}
+
+fn _definition_msg(entry: &GlossaryEntry, lang_code: &str) -> String {
+    let definition = entry.localized(lang_code);
+    format!("📖 <b>{}</b>\n\n{}", definition.term, definition.definition)
+}
+
+fn _not_found_msg(term: &str, lang_code: &str) -> String {
+    let term = html::escape(term.trim());
+    match lang_code {
+        "es" => format!("No hay ningún término «{term}» en el glosario."),
+        _ => format!("There is no term \"{term}\" in the glossary."),
+    }
+}
+
+/// Keyboard with all the terms of the glossary, 2 per row.
+fn _glossary_keyboard(glossary: &Glossary, lang_code: &str) -> InlineKeyboardMarkup {
+    let buttons: Vec<InlineKeyboardButton> = glossary
+        .entries()
+        .map(|(key, entry)| glossary_button(&entry.localized(lang_code).term, key))
+        .collect();
+
+    InlineKeyboardMarkup::new(buttons.chunks(2).map(|row| row.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(" <foo ", "en", "There is no term \"&lt;foo\" in the glossary.")]
+    #[case("a&b", "es", "No hay ningún término «a&amp;b» en el glosario.")]
+    fn not_found_msg(#[case] term: &str, #[case] lang_code: &str, #[case] expected: &str) {
+        assert_eq!(_not_found_msg(term, lang_code), expected);
+    }
+}
@@ -14,19 +14,26 @@
 
 //! Handler for the /help command.
 
-use crate::{CommandEng, CommandSpa, HandlerResult};
+use crate::telemetry::{redact, CorrelationId};
+use crate::{CommandEng, CommandSpa, HandlerResult, ThrottledBot};
 use teloxide::{prelude::*, types::ParseMode, utils::command::BotCommands};
 use tracing::{debug, info};
 
 /// Help handler.
 #[tracing::instrument(
     name = "Help handler",
-    skip(bot, msg, update),
+    skip(bot, msg, update, cid),
     fields(
-        chat_id = %msg.chat.id,
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
     )
 )]
-pub async fn help(bot: Bot, msg: Message, update: Update) -> HandlerResult {
+pub async fn help(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    cid: CorrelationId,
+) -> HandlerResult {
     info!("Command /help requested");
 
     // First, try to retrieve the user of the chat.
@@ -0,0 +1,221 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Handler for the /history command.
+//!
+//! # Description
+//!
+//! This command shows the short positions of a company as they were on a past date,
+//! e.g. around the publication of its results. The state is rebuilt from the historical
+//! series of notifications published by the CNMV.
+
+use crate::clock::Clock;
+use crate::endpoints::{error_message, reply_error, UserError};
+use crate::finance::{parse_position_date, AliveShortPositions, CNMVProvider, Ibex35Market};
+use crate::finance::{IbexCompany, ProviderError, ShortCache};
+use crate::telemetry::{redact, CorrelationId};
+use crate::{HandlerResult, ThrottledBot};
+use date::Date;
+use std::sync::Arc;
+use teloxide::{prelude::*, types::ParseMode};
+use tracing::{debug, error, info};
+
+/// Errors of the arguments of the /history command.
+#[derive(Debug, PartialEq)]
+enum InputError {
+    /// The command needs a ticker and a date.
+    Usage,
+    /// The date can't be parsed.
+    Date,
+    /// The date is later than today.
+    FutureDate,
+}
+
+/// History handler.
+#[tracing::instrument(
+    name = "History handler",
+    skip(bot, msg, update, args, stock_market, cnmv, cache, cid),
+    fields(
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn history(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    args: String,
+    stock_market: Arc<Ibex35Market>,
+    cnmv: Arc<CNMVProvider>,
+    cache: Arc<ShortCache>,
+    cid: CorrelationId,
+) -> HandlerResult {
+    info!("Command /history requested");
+
+    // First, try to retrieve the user of the chat.
+    let lang_code = match update.user() {
+        Some(user) => user.language_code.clone(),
+        None => None,
+    };
+
+    let lang_code = match lang_code.as_deref().unwrap_or("en") {
+        "es" => "es",
+        _ => "en",
+    };
+
+    debug!("The user's language code is: {:?}", lang_code);
+
+    let (ticker, date) = match _parse_args(&args, cache.clock()) {
+        Ok(input) => input,
+        Err(e) => {
+            info!("Wrong arguments for /history: {:?}", e);
+            bot.send_message(msg.chat.id, _input_error_msg(e, lang_code))
+                .parse_mode(ParseMode::Html)
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let Some(stock) = stock_market.stock_by_ticker(&ticker) else {
+        info!("Unknown ticker for /history: {ticker}");
+        return reply_error(
+            &bot,
+            msg.chat.id,
+            UserError::UnknownTicker(&ticker),
+            lang_code,
+            &cid,
+        )
+        .await;
+    };
+
+    let message = match cnmv.historical_positions(stock).await {
+        Ok(history) => _history_msg(stock, &history.as_of(date), lang_code),
+        Err(e) => {
+            error!("Failed to retrieve the historical series: {e}");
+            error_message(
+                &UserError::Provider(&ProviderError::Cnmv(e)),
+                lang_code,
+                &cid,
+            )
+        }
+    };
+
+    bot.send_message(msg.chat.id, message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+/// Parse the arguments of the command: `<ticker> <date>`.
+///
+/// # Description
+///
+/// The date is accepted in the Spanish format (_dd/mm/yyyy_) and in the ISO 8601 format
+/// (_yyyy-mm-dd_), and it can't be later than the current date of the `clock`.
+fn _parse_args(args: &str, clock: &dyn Clock) -> Result<(String, Date), InputError> {
+    let mut tokens = args.split_whitespace();
+
+    let (Some(ticker), Some(date), None) = (tokens.next(), tokens.next(), tokens.next()) else {
+        return Err(InputError::Usage);
+    };
+
+    match parse_position_date(date) {
+        Some(date) if date > clock.today() => Err(InputError::FutureDate),
+        Some(date) => Ok((ticker.to_uppercase(), date)),
+        None => Err(InputError::Date),
+    }
+}
+
+fn _input_error_msg(error: InputError, lang_code: &str) -> &'static str {
+    match (error, lang_code) {
+        (InputError::Usage, "es") => {
+            "Uso: <code>/historico TICKER FECHA</code>, por ejemplo: <code>/historico GRF 28/02/2024</code>"
+        }
+        (InputError::Usage, _) => {
+            "Usage: <code>/history TICKER DATE</code>, for example: <code>/history GRF 2024-02-28</code>"
+        }
+        (InputError::Date, "es") => {
+            "La fecha debe tener el formato <code>dd/mm/aaaa</code> o <code>aaaa-mm-dd</code>."
+        }
+        (InputError::Date, _) => {
+            "The date must have the format <code>yyyy-mm-dd</code> or <code>dd/mm/yyyy</code>."
+        }
+        (InputError::FutureDate, "es") => "La fecha no puede ser posterior a hoy.",
+        (InputError::FutureDate, _) => "The date can't be later than today.",
+    }
+}
+
+fn _history_msg(stock: &IbexCompany, positions: &AliveShortPositions, lang_code: &str) -> String {
+    let date = positions.date.format("%d/%m/%Y");
+
+    match (positions.positions.is_empty(), lang_code) {
+        (true, "es") => format!(
+            "🕰️ <b>{}</b> no tenía posiciones en corto públicas el {date}.",
+            stock.name()
+        ),
+        (true, _) => format!(
+            "🕰️ <b>{}</b> had no public short positions on {date}.",
+            stock.name()
+        ),
+        (false, "es") => format!(
+            "🕰️ Posiciones en corto de <b>{}</b> el {date}: <b>{:.2} %</b>\n\n{positions}",
+            stock.name(),
+            positions.total
+        ),
+        (false, _) => format!(
+            "🕰️ Short positions of <b>{}</b> on {date}: <b>{:.2} %</b>\n\n{positions}",
+            stock.name(),
+            positions.total
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("GRF 28/02/2024", Ok((String::from("GRF"), Date::new(2024, 2, 28))))]
+    #[case(" grf  2024-02-28 ", Ok((String::from("GRF"), Date::new(2024, 2, 28))))]
+    #[case("GRF 12/06/2024", Ok((String::from("GRF"), Date::new(2024, 6, 12))))]
+    #[case("", Err(InputError::Usage))]
+    #[case("GRF", Err(InputError::Usage))]
+    #[case("GRF 28/02/2024 SAN", Err(InputError::Usage))]
+    #[case("GRF 31/02/2024", Err(InputError::Date))]
+    #[case("GRF yesterday", Err(InputError::Date))]
+    #[case("GRF 13/06/2024", Err(InputError::FutureDate))]
+    fn parse_args(#[case] args: &str, #[case] expected: Result<(String, Date), InputError>) {
+        let clock = ManualClock::new(Date::new(2024, 6, 12));
+        assert_eq!(_parse_args(args, &clock), expected);
+    }
+
+    #[rstest]
+    fn future_date_follows_the_clock() {
+        let clock = ManualClock::new(Date::new(2024, 6, 12));
+        assert_eq!(
+            _parse_args("GRF 13/06/2024", &clock),
+            Err(InputError::FutureDate)
+        );
+
+        clock.set_today(Date::new(2024, 6, 13));
+        assert_eq!(
+            _parse_args("GRF 13/06/2024", &clock),
+            Ok((String::from("GRF"), Date::new(2024, 6, 13)))
+        );
+    }
+}
@@ -14,8 +14,10 @@
 
 //! Handler that lists all the available stocks to the client.
 
-use crate::finance::Ibex35Market;
-use crate::{HandlerResult, ShortBotDialogue, State};
+use crate::finance::{Ibex35Market, Ticker};
+use crate::recent::RecentTickers;
+use crate::telemetry::{redact, CorrelationId};
+use crate::{HandlerResult, ShortBotDialogue, State, ThrottledBot};
 use std::sync::Arc;
 use teloxide::{
     prelude::*,
@@ -25,17 +27,20 @@ use tracing::{debug, info, trace};
 
 #[tracing::instrument(
     name = "List stocks handler",
-    skip(bot, dialogue, msg, stock_market, update),
+    skip(bot, dialogue, msg, stock_market, recent, update, cid),
     fields(
-        chat_id = %msg.chat.id,
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
     )
 )]
 pub async fn list_stocks(
-    bot: Bot,
+    bot: ThrottledBot,
     dialogue: ShortBotDialogue,
     msg: Message,
     stock_market: Arc<Ibex35Market>,
+    recent: Arc<RecentTickers>,
     update: Update,
+    cid: CorrelationId,
 ) -> HandlerResult {
     info!("Command /short requested");
 
@@ -55,50 +60,18 @@ pub async fn list_stocks(
     );
 
     // Present the tickers in a table with 5 columns to reduce the number of rows.
-    let cols_per_row: usize = 5;
-    let stock_len = market.len();
-
-    // Populate the first row
-    let mut keyboard_markup =
-        InlineKeyboardMarkup::new([vec![InlineKeyboardButton::callback::<&str, &str>(
-            market[0].as_ref(),
-            market[0].as_ref(),
-        )]]);
-
-    for company in market.iter().take(cols_per_row).skip(1) {
-        keyboard_markup = keyboard_markup.append_to_row(
-            0,
-            InlineKeyboardButton::callback::<&str, &str>(company, company),
-        );
-    }
-
-    // Populate rows by chunks of `cols_per_row` buttons
-    for i in 1..(stock_len / cols_per_row) {
-        for j in 0..cols_per_row {
-            keyboard_markup = keyboard_markup.append_to_row(
-                i,
-                InlineKeyboardButton::callback::<&str, &str>(
-                    market[j + i * cols_per_row].as_ref(),
-                    market[j + i * cols_per_row].as_ref(),
-                ),
-            );
-        }
-    }
-
-    // Finally, add the remainder in case the number of items is not divisible by `cols_per_row`
-    if stock_len % cols_per_row != 0 {
-        let mut i = stock_len - cols_per_row;
-        while i < stock_len {
-            keyboard_markup = keyboard_markup.append_to_row(
-                stock_len / cols_per_row + 1,
-                InlineKeyboardButton::callback::<&str, &str>(
-                    market[i].as_ref(),
-                    market[i].as_ref(),
-                ),
-            );
-
-            i += 1;
-        }
+    let mut keyboard_markup = stocks_keyboard(&market, 5);
+
+    // The companies checked recently go first. Only those of the index can be selected.
+    let recent: Vec<Ticker> = recent
+        .get(msg.chat.id)
+        .into_iter()
+        .filter(|ticker| stock_market.stock_by_ticker(ticker.as_str()).is_some())
+        .collect();
+    if !recent.is_empty() {
+        keyboard_markup
+            .inline_keyboard
+            .insert(0, _recent_row(&recent));
     }
 
     bot.send_message(msg.chat.id, _select_stock_message(lang_code.as_deref()))
@@ -112,6 +85,30 @@ pub async fn list_stocks(
     Ok(())
 }
 
+/// Build a keyboard with a button per ticker.
+///
+/// # Description
+///
+/// Buttons are arranged in rows of `cols_per_row` buttons, and the last row holds the
+/// remainder. The callback data of each button is its ticker.
+pub fn stocks_keyboard<T: AsRef<str>>(tickers: &[T], cols_per_row: usize) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(tickers.chunks(cols_per_row.max(1)).map(|row| {
+        row.iter()
+            .map(|ticker| InlineKeyboardButton::callback(ticker.as_ref(), ticker.as_ref()))
+            .collect::<Vec<_>>()
+    }))
+}
+
+/// Build a row of buttons for the tickers checked recently.
+fn _recent_row<T: AsRef<str>>(tickers: &[T]) -> Vec<InlineKeyboardButton> {
+    tickers
+        .iter()
+        .map(|ticker| {
+            InlineKeyboardButton::callback(format!("🕘 {}", ticker.as_ref()), ticker.as_ref())
+        })
+        .collect()
+}
+
 fn _select_stock_message(lang_code: Option<&str>) -> String {
     let lang_code = lang_code.unwrap_or("en");
 
@@ -120,3 +117,34 @@ fn _select_stock_message(lang_code: Option<&str>) -> String {
         _ => String::from("Select a ticker:"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(35, 5, vec![5, 5, 5, 5, 5, 5, 5])]
+    #[case(37, 5, vec![5, 5, 5, 5, 5, 5, 5, 2])]
+    #[case(3, 5, vec![3])]
+    #[case(0, 5, vec![])]
+    fn keyboard_rows(#[case] tickers: usize, #[case] cols: usize, #[case] rows: Vec<usize>) {
+        let tickers: Vec<String> = (0..tickers).map(|i| format!("T{i}")).collect();
+
+        let keyboard = stocks_keyboard(&tickers, cols);
+
+        assert_eq!(
+            keyboard
+                .inline_keyboard
+                .iter()
+                .map(|row| row.len())
+                .collect::<Vec<_>>(),
+            rows
+        );
+        // Every ticker shows up once.
+        assert_eq!(
+            keyboard.inline_keyboard.iter().flatten().count(),
+            tickers.len()
+        );
+    }
+}
@@ -14,28 +14,41 @@
 
 //! Handler that lists all the available stocks to the client.
 
+use crate::endpoints::{error_message, glossary_button, reply_error, UserError};
 use crate::finance::AliveShortPositions;
-use crate::finance::CNMVProvider;
-use crate::finance::Ibex35Market;
-use crate::{HandlerResult, ShortBotDialogue};
+use crate::finance::{Ibex35Market, IbexCompany, IssuerRegistry};
+use crate::finance::{Ranking, ShortCache, Ticker};
+use crate::recent::RecentTickers;
+use crate::telemetry::{redact, CorrelationId};
+use crate::text::collapse_whitespace;
+use crate::{HandlerResult, ShortBotDialogue, ThrottledBot};
 use std::sync::Arc;
 use teloxide::prelude::*;
-use teloxide::types::ParseMode;
-use tracing::{debug, info};
+use teloxide::types::{InlineKeyboardMarkup, ParseMode};
+use teloxide::utils::html;
+use tracing::{debug, error, info};
+
+/// Maximum number of companies of a batch request.
+const MAX_BATCH: usize = 10;
 
 #[tracing::instrument(
     name = "Receive stock handler",
-    skip(bot, dialogue, stock_market, q, update),
+    skip(bot, dialogue, stock_market, cache, recent, q, update, cid),
     fields(
-        chat_id = %dialogue.chat_id(),
+        chat_id = %redact(dialogue.chat_id()),
+        correlation_id = %cid,
     )
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn receive_stock(
-    bot: Bot,
+    bot: ThrottledBot,
     dialogue: ShortBotDialogue,
     stock_market: Arc<Ibex35Market>,
+    cache: Arc<ShortCache>,
+    recent: Arc<RecentTickers>,
     q: CallbackQuery,
     update: Update,
+    cid: CorrelationId,
 ) -> HandlerResult {
     // Let's try to retrieve the user of the chat.
     let lang_code = match update.user() {
@@ -50,17 +63,7 @@ pub async fn receive_stock(
 
     debug!("The user's language code is: {:?}", lang_code);
 
-    if let Some(ticker) = &q.data {
-        let message = match lang_code {
-            "es" => _chose_es(stock_market.stock_by_ticker(ticker).unwrap().name()),
-            _ => _chose_en(stock_market.stock_by_ticker(ticker).unwrap().name()),
-        };
-
-        bot.send_message(dialogue.chat_id(), message)
-            .parse_mode(ParseMode::Html)
-            .await?;
-        info!("Selected stock: {}", ticker);
-    } else {
+    let Some(data) = q.data.as_deref() else {
         bot.send_message(
             dialogue.chat_id(),
             if lang_code == "es" {
@@ -74,42 +77,521 @@ pub async fn receive_stock(
         info!("Short position request served");
         dialogue.exit().await?;
         return Ok(());
-    }
+    };
+
+    let Some(stock_object) = _selected_stock(data, &stock_market) else {
+        info!("The callback data {data} is not a ticker of the listing");
+        reply_error(
+            &bot,
+            dialogue.chat_id(),
+            UserError::UnknownTicker(data),
+            lang_code,
+            &cid,
+        )
+        .await?;
+        dialogue.exit().await?;
+        return Ok(());
+    };
+
+    let message = match lang_code {
+        "es" => _chose_es(stock_object.name()),
+        _ => _chose_en(stock_object.name()),
+    };
+
+    bot.send_message(dialogue.chat_id(), message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    info!("Selected stock: {}", stock_object.ticker());
 
-    let provider = CNMVProvider::new();
-    let stock_object = stock_market.stock_by_ticker(&q.data.unwrap()[..]).unwrap();
     debug!("Stock descriptor: {stock_object}");
-    let positions = provider.short_positions(stock_object).await;
-    debug!("Received AliveShortPositions: {:?}", positions);
+    recent.record(dialogue.chat_id(), stock_object.ticker());
+    _send_report(
+        &bot,
+        dialogue.chat_id(),
+        stock_object,
+        Some(&stock_market),
+        &cache,
+        lang_code,
+        &cid,
+    )
+    .await?;
 
-    if positions.is_ok() {
-        let shorts = positions.unwrap();
+    info!("Short position request served");
+    dialogue.exit().await?;
 
-        if shorts.total <= 0.0 {
-            bot.send_message(dialogue.chat_id(), _no_shorts_msg(lang_code))
-                .parse_mode(ParseMode::Html)
-                .await?;
-        } else {
-            // Build the second part of the message only if there are alive short positions.
+    Ok(())
+}
+
+/// Find the company of the ticker given by the callback data of a button.
+///
+/// # Description
+///
+/// The data comes from the client, so it might be forged, or it might belong to an old
+/// keyboard whose company is no longer in the listing.
+fn _selected_stock<'a>(data: &str, stock_market: &'a Ibex35Market) -> Option<&'a IbexCompany> {
+    let ticker = Ticker::new(data).ok()?;
+    stock_market.stock_by_ticker(ticker.as_str())
+}
+
+/// Handler of the /short command when a company is given as argument.
+///
+/// # Description
+///
+/// The company can be given by its ticker, its ISIN or its name. Companies of the
+/// Ibex35 are searched first, then the registry of issuers of the continuous market.
+///
+/// Several companies can be given at once, e.g. `/short SAN BBVA REP`, and a single
+/// report with all of them is sent.
+#[tracing::instrument(
+    name = "Short lookup handler",
+    skip(bot, msg, update, args, stock_market, issuers, cache, recent, cid),
+    fields(
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn short_lookup(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    args: String,
+    stock_market: Arc<Ibex35Market>,
+    issuers: Arc<IssuerRegistry>,
+    cache: Arc<ShortCache>,
+    recent: Arc<RecentTickers>,
+    cid: CorrelationId,
+) -> HandlerResult {
+    info!("Command /short requested with arguments");
+
+    // Let's try to retrieve the user of the chat.
+    let lang_code = match update.user() {
+        Some(user) => user.language_code.clone(),
+        None => None,
+    };
+
+    let lang_code = match lang_code.as_deref().unwrap_or("en") {
+        "es" => "es",
+        _ => "en",
+    };
+
+    debug!("The user's language code is: {:?}", lang_code);
+
+    match _find_stock(&args, &stock_market, &issuers) {
+        Lookup::Index(stock) => {
+            debug!("Stock descriptor: {stock}");
+            recent.record(msg.chat.id, stock.ticker());
+            _send_report(
+                &bot,
+                msg.chat.id,
+                stock,
+                Some(&stock_market),
+                &cache,
+                lang_code,
+                &cid,
+            )
+            .await?;
+        }
+        Lookup::Issuer(stock) => {
+            debug!("Issuer descriptor: {stock}");
+            recent.record(msg.chat.id, stock.ticker());
+            _send_report(&bot, msg.chat.id, stock, None, &cache, lang_code, &cid).await?;
+        }
+        Lookup::Ambiguous(stocks) => {
+            info!("Ambiguous query for /short: {} matches", stocks.len());
+            let tickers: Vec<&str> = stocks.iter().map(|s| s.ticker().as_str()).collect();
             let message = match lang_code {
-                "es" => _shorts_msg_es(&shorts),
-                _ => _shorts_msg_en(&shorts),
+                "es" => format!(
+                    "Varias empresas coinciden con <b>{}</b>: {}",
+                    html::escape(args.trim()),
+                    tickers.join(", ")
+                ),
+                _ => format!(
+                    "Several companies match <b>{}</b>: {}",
+                    html::escape(args.trim()),
+                    tickers.join(", ")
+                ),
             };
-            bot.send_message(dialogue.chat_id(), message)
+            bot.send_message(msg.chat.id, message)
                 .parse_mode(ParseMode::Html)
                 .await?;
         }
-    } else {
-        let message = if lang_code == "es" {
-            "Información no disponible"
-        } else {
-            "Information not available"
-        };
-        bot.send_message(dialogue.chat_id(), message).await?;
+        Lookup::NotFound if _batch_queries(&args).len() > 1 => {
+            _send_batch_report(
+                &bot,
+                msg.chat.id,
+                &_batch_queries(&args),
+                &stock_market,
+                &issuers,
+                &cache,
+                &recent,
+                lang_code,
+                &cid,
+            )
+            .await?;
+        }
+        Lookup::NotFound => {
+            info!("Unknown company for /short: {}", args.trim());
+            reply_error(
+                &bot,
+                msg.chat.id,
+                UserError::UnknownQuery(&args),
+                lang_code,
+                &cid,
+            )
+            .await?;
+        }
+    }
+
+    info!("Short position request served");
+
+    Ok(())
+}
+
+/// Handler of the /again command, which checks again the last company of the chat.
+#[tracing::instrument(
+    name = "Again handler",
+    skip(bot, msg, update, stock_market, issuers, cache, recent, cid),
+    fields(
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn again(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    stock_market: Arc<Ibex35Market>,
+    issuers: Arc<IssuerRegistry>,
+    cache: Arc<ShortCache>,
+    recent: Arc<RecentTickers>,
+    cid: CorrelationId,
+) -> HandlerResult {
+    info!("Command /again requested");
+
+    // Let's try to retrieve the user of the chat.
+    let lang_code = match update.user() {
+        Some(user) => user.language_code.clone(),
+        None => None,
+    };
+
+    let lang_code = match lang_code.as_deref().unwrap_or("en") {
+        "es" => "es",
+        _ => "en",
+    };
+
+    debug!("The user's language code is: {:?}", lang_code);
+
+    let Some(ticker) = recent.last(msg.chat.id) else {
+        bot.send_message(
+            msg.chat.id,
+            match lang_code {
+                "es" => "Aún no has consultado ninguna empresa. Usa /short para elegir una.",
+                _ => "You have not checked any company yet. Use /short to pick one.",
+            },
+        )
+        .await?;
+        return Ok(());
+    };
+
+    match (
+        stock_market.stock_by_ticker(ticker.as_str()),
+        issuers.stock_by_ticker(ticker.as_str()),
+    ) {
+        (Some(stock), _) => {
+            _send_report(
+                &bot,
+                msg.chat.id,
+                stock,
+                Some(&stock_market),
+                &cache,
+                lang_code,
+                &cid,
+            )
+            .await?
+        }
+        (None, Some(stock)) => {
+            _send_report(&bot, msg.chat.id, stock, None, &cache, lang_code, &cid).await?
+        }
+        (None, None) => {
+            // The listing changed since the ticker was checked.
+            reply_error(
+                &bot,
+                msg.chat.id,
+                UserError::UnknownTicker(ticker.as_str()),
+                lang_code,
+                &cid,
+            )
+            .await?
+        }
     }
 
     info!("Short position request served");
-    dialogue.exit().await?;
+
+    Ok(())
+}
+
+/// Result of looking up a company.
+#[derive(Debug)]
+enum Lookup<'a> {
+    /// The company is part of the Ibex35.
+    Index(&'a IbexCompany),
+    /// The company is in the registry of issuers.
+    Issuer(&'a IbexCompany),
+    /// Several companies match the given name.
+    Ambiguous(Vec<&'a IbexCompany>),
+    NotFound,
+}
+
+/// Find a company by its ticker, its ISIN or its name.
+///
+/// # Description
+///
+/// Tickers and ISINs are exact matches (not case sensitive), and they are preferred
+/// over names, which are partial matches. Companies of the Ibex35 are preferred over
+/// the issuers of the registry.
+fn _find_stock<'a>(
+    query: &str,
+    market: &'a Ibex35Market,
+    issuers: &'a IssuerRegistry,
+) -> Lookup<'a> {
+    let query = collapse_whitespace(query);
+
+    if query.is_empty() {
+        return Lookup::NotFound;
+    }
+
+    let upper = query.to_uppercase();
+
+    if let Some(stock) = market.stock_by_ticker(&upper).or_else(|| {
+        market
+            .get_companies()
+            .into_iter()
+            .find(|s| s.isin() == upper)
+    }) {
+        return Lookup::Index(stock);
+    }
+
+    if let Some(stock) = issuers
+        .stock_by_ticker(&upper)
+        .or_else(|| issuers.stock_by_isin(&upper))
+    {
+        return Lookup::Issuer(stock);
+    }
+
+    let mut matches = market.stock_by_name(&query).unwrap_or_default();
+
+    match matches.len() {
+        1 => return Lookup::Index(matches[0]),
+        0 => (),
+        _ => {
+            matches.sort_by_key(|s| s.ticker());
+            return Lookup::Ambiguous(matches);
+        }
+    }
+
+    let mut matches = issuers.stock_by_name(&query);
+
+    match matches.len() {
+        0 => Lookup::NotFound,
+        1 => Lookup::Issuer(matches[0]),
+        _ => {
+            matches.sort_by_key(|s| s.ticker());
+            Lookup::Ambiguous(matches)
+        }
+    }
+}
+
+/// Split the arguments of a batch request into the queries of each company.
+///
+/// # Description
+///
+/// Queries are separated by whitespace or commas. Repeated queries are removed.
+fn _batch_queries(args: &str) -> Vec<&str> {
+    let mut queries: Vec<&str> = Vec::new();
+
+    for query in args.split(|c: char| c.is_whitespace() || c == ',') {
+        if !query.is_empty() && !queries.iter().any(|q| q.eq_ignore_ascii_case(query)) {
+            queries.push(query);
+        }
+    }
+
+    queries
+}
+
+/// Outcome of the lookup of a company of a batch request.
+#[derive(Debug)]
+enum BatchEntry<'a> {
+    /// Total short position of the company.
+    Found(&'a IbexCompany, f32),
+    /// The short positions could not be retrieved.
+    Failed(&'a IbexCompany),
+    /// The query matches no company, or several of them.
+    Unknown(&'a str),
+}
+
+/// Send a single report with the total short position of several companies.
+#[allow(clippy::too_many_arguments)]
+async fn _send_batch_report(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    queries: &[&str],
+    market: &Ibex35Market,
+    issuers: &IssuerRegistry,
+    cache: &ShortCache,
+    recent: &RecentTickers,
+    lang_code: &str,
+    cid: &CorrelationId,
+) -> HandlerResult {
+    info!("Batch request of {} companies", queries.len());
+
+    if queries.len() > MAX_BATCH {
+        let message = match lang_code {
+            "es" => format!("Puedes consultar hasta {MAX_BATCH} empresas a la vez."),
+            _ => format!("You can check up to {MAX_BATCH} companies at once."),
+        };
+        bot.send_message(chat_id, message).await?;
+        return Ok(());
+    }
+
+    let mut entries = Vec::with_capacity(queries.len());
+
+    for query in queries {
+        let entry = match _find_stock(query, market, issuers) {
+            Lookup::Index(stock) | Lookup::Issuer(stock) => {
+                recent.record(chat_id, stock.ticker());
+                match cache.short_positions(stock).await {
+                    Ok(shorts) => BatchEntry::Found(stock, shorts.total),
+                    Err(e) => {
+                        error!("Failed to retrieve the short positions of {stock}: {e}");
+                        BatchEntry::Failed(stock)
+                    }
+                }
+            }
+            Lookup::Ambiguous(_) | Lookup::NotFound => BatchEntry::Unknown(query),
+        };
+        entries.push(entry);
+    }
+
+    bot.send_message(chat_id, _batch_msg(&entries, lang_code, cid))
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+fn _batch_msg(entries: &[BatchEntry], lang_code: &str, cid: &CorrelationId) -> String {
+    let mut message = String::from(match lang_code {
+        "es" => "<b>Posiciones en corto</b> (suma de las posiciones abiertas)\n",
+        _ => "<b>Short positions</b> (summation of the open positions)\n",
+    });
+    let mut unknown = Vec::new();
+
+    for entry in entries {
+        let line = match (entry, lang_code) {
+            (BatchEntry::Found(stock, total), _) if *total > 0.0 => {
+                format!(
+                    "✓ {} ({}): <b>{total:.2} %</b>",
+                    stock.ticker(),
+                    stock.name()
+                )
+            }
+            (BatchEntry::Found(stock, _), "es") => {
+                format!(
+                    "✓ {} ({}): sin posiciones abiertas",
+                    stock.ticker(),
+                    stock.name()
+                )
+            }
+            (BatchEntry::Found(stock, _), _) => {
+                format!("✓ {} ({}): no open positions", stock.ticker(), stock.name())
+            }
+            (BatchEntry::Failed(stock), "es") => {
+                format!("⚠️ {}: información no disponible", stock.ticker())
+            }
+            (BatchEntry::Failed(stock), _) => {
+                format!("⚠️ {}: information not available", stock.ticker())
+            }
+            (BatchEntry::Unknown(query), _) => {
+                unknown.push(*query);
+                continue;
+            }
+        };
+        message.push('\n');
+        message.push_str(&line);
+    }
+
+    if !unknown.is_empty() {
+        message.push_str("\n\n");
+        message.push_str(&error_message(
+            &UserError::UnknownQueries(&unknown),
+            lang_code,
+            cid,
+        ));
+    }
+
+    message
+}
+
+/// Send the report of the short positions of a company.
+///
+/// ## Arguments
+///
+/// - _market_: the index of the company, used to rank it. `None` for the issuers
+///   outside the index, whose report includes a disclaimer about the coverage.
+async fn _send_report(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    stock: &IbexCompany,
+    market: Option<&Ibex35Market>,
+    cache: &ShortCache,
+    lang_code: &str,
+    cid: &CorrelationId,
+) -> HandlerResult {
+    let positions = cache.short_positions(stock).await;
+    debug!("Received AliveShortPositions: {:?}", positions);
+
+    let disclaimer = match (market, lang_code) {
+        (Some(_), _) => "",
+        (None, "es") => "\n\n<i>Esta empresa no forma parte del Ibex35: la cobertura de sus datos no está garantizada.</i>",
+        (None, _) => "\n\n<i>This company is not part of the Ibex35: the coverage of its data is best-effort.</i>",
+    };
+
+    match positions {
+        Ok(shorts) => {
+            if shorts.total <= 0.0 {
+                bot.send_message(
+                    chat_id,
+                    format!("{}{disclaimer}", _no_shorts_msg(lang_code)),
+                )
+                .parse_mode(ParseMode::Html)
+                .await?;
+            } else {
+                // The ranking is misleading until most of the index has been fetched.
+                let ranking = market.and_then(|market| {
+                    cache
+                        .ranking(market, stock.ticker())
+                        .filter(|r| r.companies * 2 >= market.get_companies().len())
+                });
+                debug!("Ranking of the stock: {:?}", ranking);
+
+                // Build the second part of the message only if there are alive short positions.
+                let message = match lang_code {
+                    "es" => _shorts_msg_es(&shorts, ranking.as_ref()),
+                    _ => _shorts_msg_en(&shorts, ranking.as_ref()),
+                };
+                bot.send_message(chat_id, format!("{message}{disclaimer}"))
+                    .parse_mode(ParseMode::Html)
+                    .reply_markup(_glossary_links(lang_code))
+                    .await?;
+            }
+        }
+        Err(e) => {
+            error!("Failed to retrieve the short positions: {e}");
+            reply_error(bot, chat_id, UserError::Provider(&e), lang_code, cid).await?;
+        }
+    }
 
     Ok(())
 }
@@ -135,21 +617,176 @@ fn _no_shorts_msg(lang_code: &str) -> &str {
     }
 }
 
-fn _shorts_msg_en(shorts: &AliveShortPositions) -> String {
+fn _shorts_msg_en(shorts: &AliveShortPositions, ranking: Option<&Ranking>) -> String {
     let s = format!(
         include_str!("../../data/templates/short_position_en.txt"),
         shorts.total,
     );
-    format!("{}{}{}", s, "\n\nList of individual positions:\n", shorts,)
+    let r = ranking.map_or(String::new(), |r| {
+        format!(
+            "\n📊 {}{} most shorted of {} companies of the Ibex35 ({}{} percentile)",
+            r.position,
+            _ordinal_suffix_en(r.position),
+            r.companies,
+            r.percentile,
+            _ordinal_suffix_en(r.percentile as usize),
+        )
+    });
+    format!(
+        "{}{}{}{}",
+        s, r, "\n\nList of individual positions:\n", shorts,
+    )
 }
 
-fn _shorts_msg_es(shorts: &AliveShortPositions) -> String {
+fn _shorts_msg_es(shorts: &AliveShortPositions, ranking: Option<&Ranking>) -> String {
     let s = format!(
         include_str!("../../data/templates/short_position_es.txt"),
         shorts.total,
     );
+    let r = ranking.map_or(String::new(), |r| {
+        format!(
+            "\n📊 {}.ª con más posiciones en corto de {} empresas del Ibex35 (percentil {})",
+            r.position, r.companies, r.percentile,
+        )
+    });
     format!(
-        "{}{}{}",
-        s, "\n\nLista de posiciones individuales:\n", shorts,
+        "{}{}{}{}",
+        s, r, "\n\nLista de posiciones individuales:\n", shorts,
     )
 }
+
+/// Links to the concepts of the report in the glossary.
+fn _glossary_links(lang_code: &str) -> InlineKeyboardMarkup {
+    let (position, threshold) = match lang_code {
+        "es" => ("📖 Posición corta neta", "📖 Umbral de notificación"),
+        _ => ("📖 Net short position", "📖 Disclosure threshold"),
+    };
+
+    InlineKeyboardMarkup::new([[
+        glossary_button(position, "net_short_position"),
+        glossary_button(threshold, "disclosure_threshold"),
+    ]])
+}
+
+fn _ordinal_suffix_en(n: usize) -> &'static str {
+    match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::{fixture, rstest};
+    use std::collections::HashMap;
+
+    fn companies(stocks: &[(&str, &str, &str)]) -> HashMap<Ticker, IbexCompany> {
+        stocks
+            .iter()
+            .map(|(name, ticker, isin)| {
+                let ticker = Ticker::new(ticker).unwrap();
+                (
+                    ticker.clone(),
+                    IbexCompany::new(None, name, ticker, isin, None),
+                )
+            })
+            .collect()
+    }
+
+    #[fixture]
+    fn market() -> Ibex35Market {
+        Ibex35Market::new(companies(&[
+            ("BANCO SANTANDER", "SAN", "ES0113900J37"),
+            ("BANCO SABADELL", "SAB", "ES0113860A34"),
+            ("GRIFOLS", "GRF", "ES0171996087"),
+        ]))
+    }
+
+    #[fixture]
+    fn issuers() -> IssuerRegistry {
+        IssuerRegistry::new(companies(&[
+            ("VISCOFAN", "VIS", "ES0184262212"),
+            ("VIDRALA", "VID", "ES0183746314"),
+        ]))
+    }
+
+    #[rstest]
+    #[case("GRF", Some("GRF"))]
+    #[case("grf", Some("GRF"))]
+    #[case("VIS", None)]
+    #[case("OLD", None)]
+    #[case("GRF;DROP", None)]
+    #[case("", None)]
+    fn selected_stock(market: Ibex35Market, #[case] data: &str, #[case] expected: Option<&str>) {
+        let ticker = _selected_stock(data, &market).map(|stock| stock.ticker().to_string());
+        assert_eq!(ticker.as_deref(), expected);
+    }
+
+    #[rstest]
+    #[case("grf", "index GRF")]
+    #[case("ES0171996087", "index GRF")]
+    #[case(" grifols ", "index GRF")]
+    #[case("VIS", "issuer VIS")]
+    #[case("es0183746314", "issuer VID")]
+    #[case("viscofan", "issuer VIS")]
+    #[case("banco", "ambiguous SAB,SAN")]
+    #[case("BANCO  SANTÁNDER", "index SAN")]
+    #[case("vi", "ambiguous VID,VIS")]
+    #[case("ACME", "not found")]
+    #[case("", "not found")]
+    fn find_stock(
+        market: Ibex35Market,
+        issuers: IssuerRegistry,
+        #[case] query: &str,
+        #[case] expected: &str,
+    ) {
+        let found = match _find_stock(query, &market, &issuers) {
+            Lookup::Index(stock) => format!("index {}", stock.ticker()),
+            Lookup::Issuer(stock) => format!("issuer {}", stock.ticker()),
+            Lookup::Ambiguous(stocks) => format!(
+                "ambiguous {}",
+                stocks
+                    .iter()
+                    .map(|s| s.ticker().as_str())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            Lookup::NotFound => String::from("not found"),
+        };
+
+        assert_eq!(found, expected, "query: {query}");
+    }
+
+    #[rstest]
+    #[case("SAN BBVA REP", vec!["SAN", "BBVA", "REP"])]
+    #[case(" SAN,BBVA ,  san ", vec!["SAN", "BBVA"])]
+    #[case("banco santander", vec!["banco", "santander"])]
+    #[case("", vec![])]
+    fn batch_queries(#[case] args: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(_batch_queries(args), expected);
+    }
+
+    #[rstest]
+    fn batch_message(market: Ibex35Market) {
+        let entries = [
+            BatchEntry::Found(market.stock_by_ticker("SAN").unwrap(), 0.55),
+            BatchEntry::Found(market.stock_by_ticker("SAB").unwrap(), 0.0),
+            BatchEntry::Failed(market.stock_by_ticker("GRF").unwrap()),
+            BatchEntry::Unknown("<XYZ>"),
+        ];
+        let update: Update = serde_json::from_str(r#"{"update_id": 42, "poll": null}"#).unwrap();
+
+        assert_eq!(
+            _batch_msg(&entries, "en", &CorrelationId::new(&update)),
+            "<b>Short positions</b> (summation of the open positions)\n\
+             \n✓ SAN (BANCO SANTANDER): <b>0.55 %</b>\
+             \n✓ SAB (BANCO SABADELL): no open positions\
+             \n⚠️ GRF: information not available\
+             \n\nNot found: &lt;XYZ&gt;"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Handler for the /sectors command.
+
+use crate::endpoints::{error_message, UserError};
+use crate::finance::{Ibex35Market, SectorSummary, ShortCache};
+use crate::telemetry::{redact, CorrelationId};
+use crate::{HandlerResult, ThrottledBot};
+use std::sync::Arc;
+use teloxide::{prelude::*, types::ParseMode};
+use tracing::{debug, info};
+
+/// Sectors handler.
+#[tracing::instrument(
+    name = "Sectors handler",
+    skip(bot, msg, update, stock_market, cache, cid),
+    fields(
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
+    )
+)]
+pub async fn sectors(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    stock_market: Arc<Ibex35Market>,
+    cache: Arc<ShortCache>,
+    cid: CorrelationId,
+) -> HandlerResult {
+    info!("Command /sectors requested");
+
+    // First, try to retrieve the user of the chat.
+    let lang_code = match update.user() {
+        Some(user) => user.language_code.clone(),
+        None => None,
+    };
+
+    let lang_code = match lang_code.as_deref().unwrap_or("en") {
+        "es" => "es",
+        _ => "en",
+    };
+
+    debug!("The user's language code is: {:?}", lang_code);
+
+    let summary = cache.sectors(&stock_market);
+    debug!("Sector summary: {:?}", summary);
+
+    let message = if summary.is_empty() {
+        error_message(&UserError::DataNotReady, lang_code, &cid)
+    } else {
+        _sectors_msg(&summary, lang_code)
+    };
+
+    bot.send_message(msg.chat.id, message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+fn _sectors_msg(summary: &[SectorSummary], lang_code: &str) -> String {
+    let mut message = String::from(match lang_code {
+        "es" => "<b>Posiciones en corto por sector</b>\n(media de las empresas del sector)\n",
+        _ => "<b>Short interest per sector</b>\n(average of the companies of the sector)\n",
+    });
+
+    for sector in summary {
+        let (ticker, total) = &sector.most_shorted;
+        message.push_str(&match lang_code {
+            "es" => format!(
+                "\n• <b>{}</b>: {:.2}% ({} empresas). Mayor: {ticker} ({total:.2}%)",
+                _sector_name(&sector.sector, lang_code),
+                sector.average,
+                sector.companies,
+            ),
+            _ => format!(
+                "\n• <b>{}</b>: {:.2}% ({} companies). Top: {ticker} ({total:.2}%)",
+                _sector_name(&sector.sector, lang_code),
+                sector.average,
+                sector.companies,
+            ),
+        });
+    }
+
+    message
+}
+
+/// Get the localized name of a sector. Unknown keys are shown as they are.
+fn _sector_name<'a>(key: &'a str, lang_code: &str) -> &'a str {
+    match (key, lang_code) {
+        ("banking", "es") => "Banca",
+        ("banking", _) => "Banking",
+        ("insurance", "es") => "Seguros",
+        ("insurance", _) => "Insurance",
+        ("energy", "es") => "Energía",
+        ("energy", _) => "Energy",
+        ("utilities", "es") => "Eléctricas y gas",
+        ("utilities", _) => "Utilities",
+        ("telecom", "es") => "Telecomunicaciones",
+        ("telecom", _) => "Telecom",
+        ("construction", "es") => "Construcción",
+        ("construction", _) => "Construction",
+        ("industrials", "es") => "Industria",
+        ("industrials", _) => "Industrials",
+        ("materials", "es") => "Materiales básicos",
+        ("materials", _) => "Materials",
+        ("real_estate", "es") => "Inmobiliario",
+        ("real_estate", _) => "Real estate",
+        ("consumer", "es") => "Consumo",
+        ("consumer", _) => "Consumer",
+        ("healthcare", "es") => "Salud",
+        ("healthcare", _) => "Healthcare",
+        ("technology", "es") => "Tecnología",
+        ("technology", _) => "Technology",
+        ("travel", "es") => "Turismo y viajes",
+        ("travel", _) => "Travel",
+        ("other", "es") => "Otros",
+        ("other", _) => "Other",
+        (key, _) => key,
+    }
+}
@@ -14,19 +14,32 @@
 
 //! Handler for the /start command.
 
-use crate::HandlerResult;
+use crate::finance::{Ibex35Market, ShortCache};
+use crate::telemetry::{redact, CorrelationId};
+use crate::templates::Templates;
+use crate::{HandlerResult, ThrottledBot};
+use std::sync::Arc;
 use teloxide::prelude::*;
 use tracing::{debug, info};
 
 /// Start handler.
 #[tracing::instrument(
     name = "Start handler",
-    skip(bot, msg, update),
+    skip(bot, msg, update, templates, stock_market, cache, cid),
     fields(
-        chat_id = %msg.chat.id,
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
     )
 )]
-pub async fn start(bot: Bot, msg: Message, update: Update) -> HandlerResult {
+pub async fn start(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    templates: Arc<Templates>,
+    stock_market: Arc<Ibex35Market>,
+    cache: Arc<ShortCache>,
+    cid: CorrelationId,
+) -> HandlerResult {
     info!("Command /start requested");
 
     let client_name = get_client_name(&msg);
@@ -39,13 +52,9 @@ pub async fn start(bot: Bot, msg: Message, update: Update) -> HandlerResult {
 
     debug!("The user's language code is: {:?}", lang_code);
 
-    let message = match lang_code {
-        Some(lang_code) => match lang_code.as_str() {
-            "es" => _start_es(&client_name),
-            _ => _start_en(&client_name),
-        },
-        _ => _start_en(&client_name),
-    };
+    let lang_code = lang_code.as_deref().unwrap_or("en");
+    let highlight = market_highlight(&stock_market, &cache, lang_code);
+    let message = templates.welcome(lang_code, &client_name, &highlight);
 
     bot.send_message(msg.chat.id, message).await?;
 
@@ -53,7 +62,7 @@ pub async fn start(bot: Bot, msg: Message, update: Update) -> HandlerResult {
 }
 
 /// Get a human-friendly identifier for the client of the chat.
-fn get_client_name(msg: &Message) -> String {
+pub(crate) fn get_client_name(msg: &Message) -> String {
     if let Some(name) = msg.chat.first_name() {
         String::from(name)
     } else {
@@ -65,18 +74,37 @@ fn get_client_name(msg: &Message) -> String {
     }
 }
 
-/// Start handler (English version).
-fn _start_en(username: &str) -> String {
-    format!(
-        include_str!("../../data/templates/welcome_en.txt"),
-        username,
-    )
-}
+/// Build a sentence about the most shorted company of the market, for the templates.
+///
+/// The sentence is plain text: the templates escape it when they are sent as HTML.
+///
+/// ## Returns
+///
+/// An empty string when no short positions are known yet.
+pub(crate) fn market_highlight(
+    market: &Ibex35Market,
+    cache: &ShortCache,
+    lang_code: &str,
+) -> String {
+    let snapshot = cache.snapshot();
+    let most_shorted = market
+        .get_companies()
+        .into_iter()
+        .filter_map(|stock| Some((stock, snapshot.get(stock.ticker())?.total)))
+        .filter(|(_, total)| *total > 0.0)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
 
-/// Start handler (Spanish version).
-fn _start_es(username: &str) -> String {
-    format!(
-        include_str!("../../data/templates/welcome_es.txt"),
-        username,
-    )
+    match (most_shorted, lang_code) {
+        (Some((stock, total)), "es") => format!(
+            "📉 La empresa del {} con más posiciones en corto ahora mismo es {} ({total:.2}%).",
+            market.market_name(),
+            stock.name()
+        ),
+        (Some((stock, total)), _) => format!(
+            "📉 The most shorted company of the {} right now is {} ({total:.2}%).",
+            market.market_name(),
+            stock.name()
+        ),
+        (None, _) => String::new(),
+    }
 }
@@ -14,19 +14,32 @@
 
 //! Handler for the /support command.
 
-use crate::HandlerResult;
-use teloxide::{prelude::*, types::ParseMode};
-use tracing::{debug, info};
+use crate::configuration::SupportSettings;
+use crate::telemetry::{redact, CorrelationId};
+use crate::{HandlerResult, ThrottledBot};
+use std::sync::Arc;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode},
+};
+use tracing::{debug, info, warn};
 
 /// Support handler.
 #[tracing::instrument(
     name = "Support handler",
-    skip(bot, msg, update),
+    skip(bot, msg, update, support, cid),
     fields(
-        chat_id = %msg.chat.id,
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
     )
 )]
-pub async fn support(bot: Bot, msg: Message, update: Update) -> HandlerResult {
+pub async fn support(
+    bot: ThrottledBot,
+    msg: Message,
+    update: Update,
+    support: Arc<SupportSettings>,
+    cid: CorrelationId,
+) -> HandlerResult {
     info!("Command /support requested");
 
     // First, try to retrieve the user of the chat.
@@ -37,28 +50,80 @@ pub async fn support(bot: Bot, msg: Message, update: Update) -> HandlerResult {
 
     debug!("The user's language code is: {:?}", lang_code);
 
+    let lang_code = match lang_code.as_deref().unwrap_or("en") {
+        "es" => "es",
+        _ => "en",
+    };
+
     let message = match lang_code {
-        Some(lang_code) => match lang_code.as_str() {
-            "es" => _support_es(),
-            _ => _support_en(),
-        },
-        _ => _support_en(),
+        "es" => _support_es(&support),
+        _ => _support_en(&support),
     };
 
     bot.send_message(msg.chat.id, message)
         .parse_mode(ParseMode::Html)
         .disable_web_page_preview(true)
+        .reply_markup(_support_keyboard(&support, lang_code))
         .await?;
 
     Ok(())
 }
 
 /// Support handler (English version).
-fn _support_en() -> String {
-    include_str!("../../data/templates/support_en.txt").to_string()
+fn _support_en(support: &SupportSettings) -> String {
+    let mut message = include_str!("../../data/templates/support_en.txt").to_string();
+
+    if let Some(email) = &support.email {
+        message.push_str(&format!("\n\n✉️ Contact: {email}"));
+    }
+    if !support.contributors.is_empty() {
+        message.push_str(&format!(
+            "\n👥 Contributors: {}",
+            support.contributors.join(", ")
+        ));
+    }
+    message.push_str(&format!("\n🤖 ShortBot v{}", env!("CARGO_PKG_VERSION")));
+
+    message
 }
 
 /// Support handler (Spanish version).
-fn _support_es() -> String {
-    include_str!("../../data/templates/support_es.txt").to_string()
+fn _support_es(support: &SupportSettings) -> String {
+    let mut message = include_str!("../../data/templates/support_es.txt").to_string();
+
+    if let Some(email) = &support.email {
+        message.push_str(&format!("\n\n✉️ Contacto: {email}"));
+    }
+    if !support.contributors.is_empty() {
+        message.push_str(&format!(
+            "\n👥 Colaboradores: {}",
+            support.contributors.join(", ")
+        ));
+    }
+    message.push_str(&format!("\n🤖 ShortBot v{}", env!("CARGO_PKG_VERSION")));
+
+    message
+}
+
+/// Buttons with the links of the project. Wrong links are skipped.
+fn _support_keyboard(support: &SupportSettings, lang_code: &str) -> InlineKeyboardMarkup {
+    let (repository, donation) = match lang_code {
+        "es" => ("⭐ Código fuente", "☕ Invítame a un café"),
+        _ => ("⭐ Source code", "☕ Buy me a coffee"),
+    };
+
+    let buttons = [
+        (repository, support.repository.as_deref()),
+        (donation, support.donation_url.as_deref()),
+    ]
+    .into_iter()
+    .filter_map(|(text, url)| match reqwest::Url::parse(url?) {
+        Ok(url) => Some(InlineKeyboardButton::url(text, url)),
+        Err(e) => {
+            warn!("Wrong link in the support settings: {e}");
+            None
+        }
+    });
+
+    InlineKeyboardMarkup::new(buttons.map(|button| vec![button]))
 }
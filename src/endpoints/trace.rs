@@ -0,0 +1,90 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Handler for the /trace command of the administrators.
+//!
+//! # Description
+//!
+//! `/trace USER_ID` shows every log of a user regardless of the tracing level, which
+//! helps to debug the issues of a single user in production. `/trace off` stops it.
+
+use crate::telemetry::{redact, trace_user, CorrelationId};
+use crate::{HandlerResult, ThrottledBot};
+use std::num::ParseIntError;
+use teloxide::{prelude::*, types::ParseMode};
+use tracing::{info, warn};
+
+/// Trace handler.
+#[tracing::instrument(
+    name = "Trace handler",
+    skip(bot, msg, args, cid),
+    fields(
+        chat_id = %redact(msg.chat.id),
+        correlation_id = %cid,
+    )
+)]
+pub async fn trace(
+    bot: ThrottledBot,
+    msg: Message,
+    args: String,
+    cid: CorrelationId,
+) -> HandlerResult {
+    info!("Command /trace requested");
+
+    let message = match _parse_args(&args) {
+        Ok(Some(user_id)) => {
+            trace_user(Some(user_id));
+            warn!("Tracing the user {}", redact(user_id));
+            format!("Every log of the user <code>{user_id}</code> is shown now.")
+        }
+        Ok(None) => {
+            trace_user(None);
+            warn!("Tracing of users disabled");
+            String::from("No user is traced now.")
+        }
+        Err(e) => {
+            info!("Wrong arguments for /trace: {e}");
+            String::from("Usage: <code>/trace USER_ID</code> or <code>/trace off</code>")
+        }
+    };
+
+    bot.send_message(msg.chat.id, message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+
+    Ok(())
+}
+
+/// Parse the argument of the command: the ID of a user, or `off`.
+fn _parse_args(args: &str) -> Result<Option<i64>, ParseIntError> {
+    match args.trim() {
+        "off" => Ok(None),
+        user_id => user_id.parse().map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("123456789", Some(Some(123456789)))]
+    #[case(" off ", Some(None))]
+    #[case("", None)]
+    #[case("@someone", None)]
+    fn parse_args(#[case] args: &str, #[case] expected: Option<Option<i64>>) {
+        assert_eq!(_parse_args(args).ok(), expected);
+    }
+}
@@ -17,11 +17,14 @@
 //! Module that includes logic related to the extraction of data from the web page
 //! of the Spanish _Comisión Nacional de Mercado de Valores (CNMV)_.
 
-use crate::finance::IbexCompany;
-use crate::finance::{AliveShortPositions, ShortPosition};
+use crate::finance::{
+    parse_position_date, AliveShortPositions, ShortPosition, ShortPositionHistory,
+};
+use crate::finance::{IbexCompany, ScrapeCoordinator};
 use date::Date;
-use reqwest;
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html, Selector};
+use std::sync::Arc;
+use thiserror::Error;
 use tracing::{debug, trace};
 
 /// `enum` to handle what endpoints of the CNMV's API are supported by this module.
@@ -29,6 +32,9 @@ enum EndpointSel {
     /// EP -> `Consultas a registros oficiales>Entidades emisoras: Información
     /// regulada>Posiciones cortas>Notificaciones de posiciones cortas`
     ShortEP,
+    /// EP -> `Consultas a registros oficiales>Entidades emisoras: Información
+    /// regulada>Posiciones cortas>Serie histórica`
+    HistoricEP,
 }
 
 /// Data type that checks whether a response for a short position request succeeded or not.
@@ -65,6 +71,7 @@ impl AsRef<str> for ShortResponse {
 ///
 /// The current list of supported features is:
 /// - Extraction of the active short positions of a company (`Consultas a registros oficiales>Entidades emisoras: Información regulada>Posiciones cortas>Notificaciones de posiciones cortas`).
+/// - Extraction of the historical series of short positions of a company (`Serie histórica`).
 ///
 /// The endpoint of the web page expects a formal ID, thus using tickers or regular names
 /// is not allowed. To avoid handling such type of information, this object works with
@@ -73,11 +80,21 @@ impl AsRef<str> for ShortResponse {
 ///
 /// The methods of this object are asynchronous which means they must be executed inside
 /// a thread executer such as [Tokio](https://docs.rs/tokio/latest/tokio/).
+///
+/// Requests are sent using a [reqwest::Client], which keeps a pool of connections, and
+/// are throttled by a [ScrapeCoordinator] to avoid hammering the CNMV's web page. Use
+/// [CNMVProvider::with_client] to share both objects among all the features of the bot.
 pub struct CNMVProvider {
+    /// HTTP client used for all the requests.
+    client: reqwest::Client,
+    /// Rate limiter of the requests.
+    coordinator: Arc<ScrapeCoordinator>,
     /// The main path of the URL.
     base_url: String,
     /// Path extension for the _PosicionesCortas_ endpoint.
     short_ext: String,
+    /// Path extension for the _Serie histórica_ endpoint.
+    historic_ext: String,
 }
 
 impl Default for CNMVProvider {
@@ -89,14 +106,31 @@ impl Default for CNMVProvider {
 
 impl CNMVProvider {
     /// Class constructor.
+    ///
+    /// # Description
+    ///
+    /// The provider builds its own HTTP client using the default settings of reqwest, and
+    /// its own [ScrapeCoordinator].
     pub fn new() -> CNMVProvider {
+        CNMVProvider::with_client(reqwest::Client::new(), Arc::default())
+    }
+
+    /// Class constructor that uses an external HTTP client and rate limiter.
+    pub fn with_client(
+        client: reqwest::Client,
+        coordinator: Arc<ScrapeCoordinator>,
+    ) -> CNMVProvider {
         CNMVProvider {
+            client,
+            coordinator,
             base_url: String::from("https://www.cnmv.es"),
             short_ext: String::from("Portal/Consultas/EE/PosicionesCortas.aspx?nif="),
+            historic_ext: String::from("Portal/Consultas/EE/PosicionesCortasHist.aspx?nif="),
         }
     }
 
     /// Internal method that executes a GET to the CNMV's web page endpoints.
+    #[tracing::instrument(name = "CNMV request", skip(self, endpoint))]
     async fn collect_data(
         &self,
         endpoint: EndpointSel,
@@ -105,11 +139,22 @@ impl CNMVProvider {
         // Select the endpoint that shall be used for the requested GET.
         let endpoint = match endpoint {
             EndpointSel::ShortEP => &self.short_ext[..],
+            EndpointSel::HistoricEP => &self.historic_ext[..],
         };
 
         debug!("GET requested for the CNMV endpoint: {endpoint}");
 
-        let resp = reqwest::get(format!("{}/{}{stock_id}", self.base_url, endpoint))
+        let url = format!("{}/{}{stock_id}", self.base_url, endpoint);
+        let _permit = self
+            .coordinator
+            .acquire(&url)
+            .await
+            .map_err(|_| CNMVError::Busy)?;
+
+        let resp = self
+            .client
+            .get(url)
+            .send()
             .await
             .map_err(|e| CNMVError::ExternalError(e.to_string()))?;
         if resp.status().as_u16() != 200 {
@@ -125,6 +170,28 @@ impl CNMVProvider {
         }
     }
 
+    /// Check whether the CNMV's web page is reachable.
+    pub async fn ping(&self) -> Result<(), CNMVError> {
+        let _permit = self
+            .coordinator
+            .acquire(&self.base_url)
+            .await
+            .map_err(|_| CNMVError::Busy)?;
+
+        let resp = self
+            .client
+            .get(&self.base_url)
+            .send()
+            .await
+            .map_err(|e| CNMVError::ExternalError(e.to_string()))?;
+
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(CNMVError::ExternalError(resp.status().as_str().to_string()))
+        }
+    }
+
     /// Method that checks alive short positions of a stock.
     ///
     /// # Description
@@ -155,73 +222,270 @@ impl CNMVProvider {
 
         let raw_data = self.collect_data(EndpointSel::ShortEP, id).await?;
 
-        let document = Html::parse_document(raw_data.as_ref());
-        let selector_td = Selector::parse("td").unwrap();
-        let selector_tr = Selector::parse("tr").unwrap();
-
-        let mut positions = Vec::new();
-
-        for element_tr in document.select(&selector_tr) {
-            let mut owner: String = String::from("dummy");
-            let mut weight: f32 = 0.0;
-            let mut date: String = String::from("nodate");
-            for td in element_tr.select(&selector_td) {
-                if let Some(x) = td.attr("class") {
-                    if x == "Izquierda" {
-                        owner = String::from(td.text().next().unwrap().trim());
-                    }
-                } else if let Some(x) = td.attr("data-th") {
-                    if x == "% sobre el capital" {
-                        weight = td
-                            .text()
-                            .next()
-                            .unwrap()
-                            .replace(',', ".")
-                            .parse::<f32>()
-                            .unwrap();
-                    } else if x == "Fecha de la posición" {
-                        date = String::from(td.text().next().unwrap());
-                    }
-                }
+        parse_alive_positions(raw_data.as_ref())
+    }
+
+    /// Method that retrieves the historical series of short positions of a stock.
+    ///
+    /// # Description
+    ///
+    /// This method checks the _Serie histórica_ page of the CNMV, which lists all the
+    /// notifications of short positions against a stock, including the positions that
+    /// are no longer alive. It is meant to backfill the history of a company.
+    ///
+    /// ## Returns
+    ///
+    /// A [ShortPositionHistory] with the notifications sorted by date (oldest first).
+    pub async fn historical_positions(
+        &self,
+        stock: &IbexCompany,
+    ) -> Result<ShortPositionHistory, CNMVError> {
+        let id = match stock.extra_id() {
+            Some(id) => id,
+            None => return Err(CNMVError::UnknownCompany),
+        };
+
+        let raw_data = self.collect_data(EndpointSel::HistoricEP, id).await?;
+
+        parse_historic_positions(raw_data.as_ref())
+    }
+}
+
+/// Columns of the table of short positions that are relevant for the scraper.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Column {
+    Owner,
+    Weight,
+    Date,
+}
+
+impl Column {
+    /// Map the label of a column (Spanish or English version of the web page) to a [Column].
+    fn from_label(label: &str) -> Option<Column> {
+        let label = label.trim().to_lowercase();
+
+        match label.as_str() {
+            "titular de la posición" | "tenedor de la posición" | "position holder" => {
+                Some(Column::Owner)
             }
-            if &owner[..] != "dummy" {
-                positions.push(ShortPosition {
-                    owner,
-                    weight,
-                    date,
-                });
+            "% sobre el capital" | "% of share capital" | "% on share capital" => {
+                Some(Column::Weight)
             }
+            "fecha de la posición" | "position date" => Some(Column::Date),
+            _ => None,
         }
+    }
+}
 
-        let mut total = 0.0;
-        positions
-            .iter()
-            .for_each(|position| total += position.weight);
-        let date = Date::today_utc();
+/// Entry of a table of short positions.
+struct TableRow {
+    /// Position of the entry among the data rows of its table, starting at 1.
+    row: usize,
+    owner: String,
+    weight: f32,
+    date: Option<String>,
+}
 
-        Ok(AliveShortPositions {
-            total,
-            positions,
-            date,
+/// Parse the weight of a short position, e.g. `1,10`, `1.10` or `1,10 %`.
+fn parse_weight(raw: &str) -> Option<f32> {
+    raw.trim()
+        .trim_end_matches('%')
+        .trim()
+        .replace(',', ".")
+        .parse::<f32>()
+        .ok()
+}
+
+/// Check whether `container` is the closest ancestor of `element` of its kind (e.g. table).
+fn belongs_to(element: &ElementRef, container: &ElementRef) -> bool {
+    let kind = container.value().name();
+
+    element
+        .ancestors()
+        .find(|node| node.value().as_element().map(|e| e.name()) == Some(kind))
+        .map(|node| node.id())
+        == Some(container.id())
+}
+
+/// Parse the tables of short positions from the content of the CNMV's web page.
+///
+/// # Description
+///
+/// Columns are identified by the `data-th` attribute of the cells or, when missing, by the
+/// headers of the table. Both the Spanish and the English versions of the web page are
+/// supported. The owner of a position is also recognized by the class `Izquierda`, which
+/// is used by the web page for that column.
+///
+/// ## Returns
+///
+/// A collection with all the positions found in the content. An empty collection is
+/// returned when no table is found. [CNMVError::MissingColumn] is returned when a table
+/// lists owners but not the weight of the positions, and [CNMVError::MalformedRow] when a
+/// value can't be parsed. Rows are counted from the first data row after the header.
+fn parse_positions_table(content: &str) -> Result<Vec<TableRow>, CNMVError> {
+    let document = Html::parse_document(content);
+    let selector_table = Selector::parse("table").unwrap();
+    let selector_th = Selector::parse("th").unwrap();
+    let selector_td = Selector::parse("td").unwrap();
+    let selector_tr = Selector::parse("tr").unwrap();
+
+    let mut positions = Vec::new();
+
+    for table in document.select(&selector_table) {
+        let headers: Vec<Option<Column>> = table
+            .select(&selector_th)
+            .filter(|th| belongs_to(th, &table))
+            .map(|th| Column::from_label(&th.text().collect::<String>()))
+            .collect();
+
+        // Rows of nested tables are processed along with their own table.
+        let rows = table
+            .select(&selector_tr)
+            .filter(|tr| belongs_to(tr, &table));
+
+        let mut row = 0;
+
+        for element_tr in rows {
+            let mut owner: Option<String> = None;
+            let mut weight: Option<String> = None;
+            let mut date: Option<String> = None;
+
+            let cells = element_tr
+                .select(&selector_td)
+                .filter(|td| belongs_to(td, &element_tr));
+
+            for (i, td) in cells.enumerate() {
+                let column = match td.attr("data-th") {
+                    Some(label) => Column::from_label(label),
+                    None => headers.get(i).copied().flatten(),
+                };
+                let column = match column {
+                    Some(column) => Some(column),
+                    None if td.attr("class") == Some("Izquierda") => Some(Column::Owner),
+                    None => None,
+                };
+                let text = td.text().collect::<String>().trim().to_string();
+
+                match column {
+                    Some(Column::Owner) => owner = Some(text),
+                    Some(Column::Weight) => weight = Some(text),
+                    Some(Column::Date) => date = Some(text),
+                    None => (),
+                }
+            }
+
+            // Rows with no owner are headers or layout rows.
+            let owner = match owner {
+                Some(owner) if !owner.is_empty() => owner,
+                _ => continue,
+            };
+            row += 1;
+
+            let weight = match weight {
+                Some(weight) => parse_weight(&weight).ok_or_else(|| CNMVError::MalformedRow {
+                    row,
+                    reason: format!("invalid weight `{weight}`"),
+                })?,
+                None => return Err(CNMVError::MissingColumn("weight")),
+            };
+
+            positions.push(TableRow {
+                row,
+                owner,
+                weight,
+                date,
+            });
+        }
+    }
+
+    Ok(positions)
+}
+
+/// Parse the table of alive short positions from the content of the CNMV's web page.
+///
+/// See [parse_positions_table] for the details about the supported layouts.
+pub fn parse_alive_positions(content: &str) -> Result<AliveShortPositions, CNMVError> {
+    let positions: Vec<ShortPosition> = parse_positions_table(content)?
+        .into_iter()
+        .map(|row| ShortPosition {
+            owner: row.owner,
+            weight: row.weight,
+            date: row.date.unwrap_or_else(|| String::from("-")),
         })
+        .collect();
+
+    let total = positions.iter().map(|position| position.weight).sum();
+    let date = Date::today_utc();
+
+    Ok(AliveShortPositions {
+        total,
+        positions,
+        date,
+    })
+}
+
+/// Parse the table of the historical series from the content of the CNMV's web page.
+///
+/// # Description
+///
+/// The table of the _Serie histórica_ has the same layout as the table of alive positions,
+/// but in this case the date of each notification is mandatory, as the entries are sorted
+/// by it.
+pub fn parse_historic_positions(content: &str) -> Result<ShortPositionHistory, CNMVError> {
+    let mut entries = Vec::new();
+
+    for row in parse_positions_table(content)? {
+        let raw_date = row.date.ok_or(CNMVError::MissingColumn("date"))?;
+        let date = parse_position_date(&raw_date).ok_or_else(|| CNMVError::MalformedRow {
+            row: row.row,
+            reason: format!("invalid date `{raw_date}`"),
+        })?;
+        entries.push((
+            date,
+            ShortPosition {
+                owner: row.owner,
+                weight: row.weight,
+                date: raw_date,
+            },
+        ));
     }
+
+    // The web page lists the newest notifications first.
+    entries.sort_by_key(|(date, _)| *date);
+
+    Ok(ShortPositionHistory {
+        positions: entries.into_iter().map(|(_, position)| position).collect(),
+    })
 }
 
 /// Error types for the CNMV handler.
-#[derive(Debug)]
+#[derive(Debug, Error)]
 pub enum CNMVError {
     /// Error given when the passed company is not recognized by the CNMV' API.
+    #[error("the company is not recognized by the CNMV")]
     UnknownCompany,
     /// Error from the external API (CNMV).
+    #[error("error from the CNMV's web page: {0}")]
     ExternalError(String),
     /// Error for the internal methods.
+    #[error("internal error: {0}")]
     InternalError(String),
+    /// Too many requests are waiting for the CNMV's web page.
+    #[error("too many requests are waiting for the CNMV's web page")]
+    Busy,
+    /// The table of short positions lacks a mandatory column.
+    #[error("the table of short positions has no column for the {0}")]
+    MissingColumn(&'static str),
+    /// A row of the table of short positions has an unexpected content. Rows are counted
+    /// from the first data row after the header, starting at 1.
+    #[error("unexpected content at the row {row} of the table: {reason}")]
+    MalformedRow { row: usize, reason: String },
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::finance::IbexCompany;
+    use crate::finance::{IbexCompany, Ticker};
     use rstest::{fixture, rstest};
 
     #[fixture]
@@ -229,7 +493,7 @@ mod tests {
         IbexCompany::new(
             Some("Grifols"),
             "GRIFOLS",
-            "GRF",
+            Ticker::new("GRF").unwrap(),
             "ES0171996087",
             Some("A-58389123"),
         )
@@ -240,13 +504,170 @@ mod tests {
         IbexCompany::new(
             Some("Not A Company"),
             "NoCompany",
-            "NOC",
+            Ticker::new("NOC").unwrap(),
             "0",
             Some("A44901010"),
         )
     }
 
+    fn fixture(name: &str) -> String {
+        std::fs::read_to_string(format!(
+            "{}/tests/fixtures/cnmv/{name}",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap()
+    }
+
+    #[rstest]
+    fn parse_response_with_positions() {
+        assert!(ShortResponse::parse(fixture("alive_positions.html")).is_ok());
+    }
+
+    #[rstest]
+    fn parse_response_without_positions() {
+        assert!(ShortResponse::parse(fixture("no_positions.html")).is_ok());
+    }
+
+    #[rstest]
+    fn parse_response_unknown_company() {
+        assert!(matches!(
+            ShortResponse::parse(fixture("unknown_company.html")),
+            Err(CNMVError::UnknownCompany)
+        ));
+    }
+
+    #[rstest]
+    #[case("alive_positions.html")]
+    #[case("english_layout.html")]
+    fn parse_positions(#[case] file: &str) {
+        let shorts = parse_alive_positions(&fixture(file)).unwrap();
+
+        assert_eq!(shorts.positions.len(), 3);
+        assert_eq!(shorts.positions[0].owner, "MARSHALL WACE LLP");
+        assert_eq!(shorts.positions[0].weight, 1.1);
+        assert_eq!(shorts.positions[0].date, "12/06/2024");
+        assert_eq!(shorts.positions[2].owner, "WORLDQUANT LLC");
+        assert_eq!(shorts.positions[2].weight, 0.52);
+        assert!((shorts.total - 2.27).abs() < 1e-5);
+    }
+
+    #[rstest]
+    fn parse_no_positions() {
+        let shorts = parse_alive_positions(&fixture("no_positions.html")).unwrap();
+
+        assert!(shorts.positions.is_empty());
+        assert_eq!(shorts.total, 0.0);
+    }
+
+    #[rstest]
+    fn parse_missing_date_column() {
+        let shorts = parse_alive_positions(&fixture("missing_date_column.html")).unwrap();
+
+        assert_eq!(shorts.positions.len(), 2);
+        assert_eq!(shorts.positions[0].date, "-");
+    }
+
+    #[rstest]
+    fn parse_missing_weight_column() {
+        assert!(matches!(
+            parse_alive_positions(&fixture("missing_weight_column.html")),
+            Err(CNMVError::MissingColumn("weight"))
+        ));
+    }
+
+    #[rstest]
+    fn parse_malformed_date() {
+        match parse_historic_positions(&fixture("malformed_date.html")) {
+            Err(CNMVError::MalformedRow { row, reason }) => {
+                // Same index as the malformed weight of the same row.
+                assert_eq!(row, 2);
+                assert!(reason.contains("31/02/2024"));
+            }
+            other => panic!("Unexpected result: {other:?}"),
+        }
+    }
+
+    #[rstest]
+    fn parse_malformed_weight() {
+        match parse_alive_positions(&fixture("malformed_weight.html")) {
+            Err(CNMVError::MalformedRow { row, reason }) => {
+                assert_eq!(row, 2);
+                assert!(reason.contains("n/d"));
+            }
+            other => panic!("Unexpected result: {other:?}"),
+        }
+    }
+
+    #[rstest]
+    #[case("1,10", Some(1.1))]
+    #[case("0.52", Some(0.52))]
+    #[case(" 0,65 % ", Some(0.65))]
+    #[case("n/d", None)]
+    fn weights(#[case] raw: &str, #[case] expected: Option<f32>) {
+        assert_eq!(parse_weight(raw), expected);
+    }
+
+    #[rstest]
+    fn parse_history() {
+        let history = parse_historic_positions(&fixture("historic_positions.html")).unwrap();
+
+        assert_eq!(history.positions.len(), 5);
+        // Oldest notifications first.
+        assert_eq!(history.positions[0].owner, "WORLDQUANT LLC");
+        assert_eq!(history.positions[0].date, "18/04/2024");
+        assert_eq!(history.positions[4].owner, "MARSHALL WACE LLP");
+        assert_eq!(history.positions[4].weight, 1.1);
+    }
+
+    #[rstest]
+    #[case(Date::new(2024, 4, 1), vec![])]
+    #[case(Date::new(2024, 5, 1), vec![("WORLDQUANT LLC", 0.52)])]
+    // Worldquant dropped below the disclosure threshold.
+    #[case(Date::new(2024, 5, 30), vec![("MARSHALL WACE LLP", 0.95)])]
+    #[case(Date::new(2024, 6, 11), vec![("MARSHALL WACE LLP", 0.95), ("AQR CAPITAL MANAGEMENT LLC", 0.65)])]
+    #[case(Date::new(2024, 6, 12), vec![("MARSHALL WACE LLP", 1.1), ("AQR CAPITAL MANAGEMENT LLC", 0.65)])]
+    fn history_as_of(#[case] date: Date, #[case] expected: Vec<(&str, f32)>) {
+        let history = parse_historic_positions(&fixture("historic_positions.html")).unwrap();
+
+        let positions = history.as_of(date);
+
+        assert_eq!(
+            positions
+                .positions
+                .iter()
+                .map(|p| (p.owner.as_str(), p.weight))
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(positions.date, date);
+    }
+
+    #[rstest]
+    fn parse_history_without_dates() {
+        assert!(matches!(
+            parse_historic_positions(&fixture("missing_date_column.html")),
+            Err(CNMVError::MissingColumn("date"))
+        ));
+    }
+
+    #[rstest]
+    #[ignore = "requires access to the CNMV's web page"]
+    fn historical_positions_valid_company(a_company: IbexCompany) {
+        let provider = CNMVProvider::new();
+
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let history = provider.historical_positions(&a_company).await;
+                assert!(history.is_ok());
+            })
+    }
+
     #[rstest]
+    #[ignore = "requires access to the CNMV's web page"]
     fn collect_data_existing_company(a_company: IbexCompany) {
         // Prepare the test
         let provider = CNMVProvider::new();
@@ -266,6 +687,7 @@ mod tests {
     }
 
     #[rstest]
+    #[ignore = "requires access to the CNMV's web page"]
     fn collect_data_non_existing_company(not_a_company: IbexCompany) {
         // Prepare the test
         let provider = CNMVProvider::new();
@@ -286,6 +708,7 @@ mod tests {
     }
 
     #[rstest]
+    #[ignore = "requires access to the CNMV's web page"]
     fn short_position_valid_company(a_company: IbexCompany) {
         // Prepare the test
         let provider = CNMVProvider::new();
@@ -308,6 +731,7 @@ mod tests {
     }
 
     #[rstest]
+    #[ignore = "requires access to the CNMV's web page"]
     fn short_position_non_valid_company(not_a_company: IbexCompany) {
         // Prepare the test
         let provider = CNMVProvider::new();
@@ -0,0 +1,468 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! esma_registry.rs
+//!
+//! Module that ingests the bulk files of net short positions published by the
+//! _European Securities and Markets Authority (ESMA)_ and the CNMV.
+
+use crate::finance::ScrapeCoordinator;
+use crate::finance::DISCLOSURE_THRESHOLD;
+use crate::finance::{parse_position_date, AliveShortPositions, IbexCompany, ShortPosition};
+use date::Date;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tracing::{debug, trace};
+
+/// Accepted headers for each column of a registry file.
+///
+/// ESMA's files use English headers, whereas CNMV's exports use Spanish headers.
+const HOLDER_HEADERS: &[&str] = &["position holder", "tenedor de la posición"];
+const ISIN_HEADERS: &[&str] = &["isin"];
+const WEIGHT_HEADERS: &[&str] = &["net short position (%)", "% sobre el capital"];
+const DATE_HEADERS: &[&str] = &["position date", "fecha de la posición"];
+
+/// Default time after which the registry file is read again.
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+
+/// Alive positions of each company of a registry file, indexed by ISIN. Companies with
+/// invalid entries keep the error of the first one.
+pub type Registry = HashMap<String, Result<AliveShortPositions, RegistryError>>;
+
+/// Latest notification of each holder of a company.
+type Holders = HashMap<String, (Date, ShortPosition)>;
+
+/// Registry file parsed at some moment.
+struct CachedRegistry {
+    fetched: Instant,
+    registry: Arc<Registry>,
+}
+
+/// Handler to extract short positions from the registry files of ESMA or the CNMV.
+///
+/// # Description
+///
+/// Regulators publish the public net short positions as bulk files. This provider
+/// reads such a file (CSV format) either from a local path or from an URL, and
+/// builds an [AliveShortPositions] for a company identified by its ISIN.
+///
+/// Files published as XLSX shall be exported to CSV before feeding them to this
+/// provider. Both `,` and `;` separators, and decimal commas are supported.
+///
+/// The registry files include old notifications, thus only the latest entry of each
+/// holder is considered, and positions below the disclosure threshold are dropped.
+///
+/// The whole file is parsed at once and kept in memory for a while (see
+/// [RegistryProvider::with_ttl]), so the lookups of the companies of the index don't
+/// read the file again.
+pub struct RegistryProvider {
+    /// HTTP client used when the registry file is an URL.
+    client: reqwest::Client,
+    /// Rate limiter of the requests.
+    coordinator: Arc<ScrapeCoordinator>,
+    /// Path or URL of the registry file.
+    source: String,
+    /// Time after which the registry file is read again.
+    ttl: Duration,
+    /// Last parsed registry file.
+    cache: Mutex<Option<CachedRegistry>>,
+}
+
+impl RegistryProvider {
+    /// Class constructor.
+    ///
+    /// ## Arguments
+    ///
+    /// - _source_: a local path or an URL (http/https) that points to a registry file.
+    pub fn new(source: &str) -> RegistryProvider {
+        RegistryProvider::with_client(source, reqwest::Client::new(), Arc::default())
+    }
+
+    /// Class constructor that uses an external HTTP client and rate limiter.
+    pub fn with_client(
+        source: &str,
+        client: reqwest::Client,
+        coordinator: Arc<ScrapeCoordinator>,
+    ) -> RegistryProvider {
+        RegistryProvider {
+            client,
+            coordinator,
+            source: String::from(source),
+            ttl: DEFAULT_TTL,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Set the time after which the registry file is read again. It's 1 hour by default.
+    pub fn with_ttl(mut self, ttl: Duration) -> RegistryProvider {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Internal method that retrieves the content of the registry file.
+    #[tracing::instrument(name = "Registry request", skip(self), fields(source = %self.source))]
+    async fn collect_data(&self) -> Result<String, RegistryError> {
+        debug!("Reading the registry file from {}", self.source);
+
+        if self.source.starts_with("http://") || self.source.starts_with("https://") {
+            let _permit = self
+                .coordinator
+                .acquire(&self.source)
+                .await
+                .map_err(|e| RegistryError::Source(e.to_string()))?;
+            let resp = self
+                .client
+                .get(&self.source)
+                .send()
+                .await
+                .map_err(|e| RegistryError::Source(e.to_string()))?;
+            if resp.status().as_u16() != 200 {
+                return Err(RegistryError::Source(resp.status().as_str().to_string()));
+            }
+            resp.text()
+                .await
+                .map_err(|e| RegistryError::Source(e.to_string()))
+        } else {
+            tokio::fs::read_to_string(&self.source)
+                .await
+                .map_err(|e| RegistryError::Source(e.to_string()))
+        }
+    }
+
+    /// Internal method that gets the parsed registry file, reading it again when the
+    /// cached one is older than the TTL.
+    async fn registry(&self) -> Result<Arc<Registry>, RegistryError> {
+        // The lock is held while the file is read, so concurrent lookups wait for a single
+        // read instead of starting their own.
+        let mut cache = self.cache.lock().await;
+
+        if let Some(cached) = cache.as_ref().filter(|c| c.fetched.elapsed() < self.ttl) {
+            return Ok(Arc::clone(&cached.registry));
+        }
+
+        let content = self.collect_data().await?;
+        let registry = Arc::new(parse_registry_file(&content)?);
+        *cache = Some(CachedRegistry {
+            fetched: Instant::now(),
+            registry: Arc::clone(&registry),
+        });
+
+        Ok(registry)
+    }
+
+    /// Method that checks alive short positions of a stock.
+    ///
+    /// # Description
+    ///
+    /// This method offers the same interface as
+    /// [CNMVProvider::short_positions](crate::finance::CNMVProvider::short_positions),
+    /// which allows using it as a fallback when the CNMV's web page is not available.
+    pub async fn short_positions(
+        &self,
+        stock: &IbexCompany,
+    ) -> Result<AliveShortPositions, RegistryError> {
+        let registry = self.registry().await?;
+
+        registry
+            .get(stock.isin())
+            .cloned()
+            .unwrap_or_else(|| Ok(_no_positions()))
+    }
+}
+
+/// Parse the content of a registry file and extract the alive positions of `isin`.
+pub fn parse_registry(content: &str, isin: &str) -> Result<AliveShortPositions, RegistryError> {
+    parse_registry_file(content)?
+        .remove(isin)
+        .unwrap_or_else(|| Ok(_no_positions()))
+}
+
+/// Positions of a company that is not included in a registry file.
+fn _no_positions() -> AliveShortPositions {
+    AliveShortPositions {
+        total: 0.0,
+        positions: Vec::new(),
+        date: Date::today_utc(),
+    }
+}
+
+/// Parse the content of a registry file and extract the alive positions of every company.
+pub fn parse_registry_file(content: &str) -> Result<Registry, RegistryError> {
+    let separator = match content.lines().next() {
+        Some(header) if header.matches(';').count() > header.matches(',').count() => b';',
+        Some(_) => b',',
+        None => return Err(RegistryError::MissingColumn("header")),
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(separator)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let headers = reader
+        .headers()
+        .map_err(|e| RegistryError::Format(e.to_string()))?
+        .clone();
+
+    let column = |names: &[&str], id: &'static str| {
+        headers
+            .iter()
+            .position(|h| names.contains(&h.to_lowercase().as_str()))
+            .ok_or(RegistryError::MissingColumn(id))
+    };
+
+    let holder_col = column(HOLDER_HEADERS, "position holder")?;
+    let isin_col = column(ISIN_HEADERS, "isin")?;
+    let weight_col = column(WEIGHT_HEADERS, "net short position")?;
+    let date_col = column(DATE_HEADERS, "position date")?;
+
+    // Only the latest notification of each holder is kept.
+    let mut latest: HashMap<String, Result<Holders, RegistryError>> = HashMap::new();
+
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| RegistryError::Format(e.to_string()))?;
+
+        let Some(isin) = record.get(isin_col).filter(|isin| !isin.is_empty()) else {
+            continue;
+        };
+        let entry = latest
+            .entry(isin.to_string())
+            .or_insert_with(|| Ok(HashMap::new()));
+        // Only the first invalid entry of a company is reported.
+        let Ok(holders) = entry else {
+            continue;
+        };
+        trace!("Registry entry: {:?}", record);
+
+        let field = |col: usize| {
+            record
+                .get(col)
+                .filter(|s| !s.is_empty())
+                .ok_or(RegistryError::InvalidRecord(line + 2))
+        };
+        let parsed = field(holder_col).and_then(|owner| {
+            let weight = field(weight_col)?
+                .replace(',', ".")
+                .parse::<f32>()
+                .map_err(|_| RegistryError::InvalidRecord(line + 2))?;
+            let date = parse_position_date(field(date_col)?)
+                .ok_or(RegistryError::InvalidRecord(line + 2))?;
+
+            Ok((owner.to_string(), weight, date))
+        });
+        let (owner, weight, parsed_date) = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                *entry = Err(e);
+                continue;
+            }
+        };
+
+        let is_newer = holders
+            .get(&owner)
+            .is_none_or(|(current, _)| parsed_date >= *current);
+
+        if is_newer {
+            holders.insert(
+                owner.clone(),
+                (
+                    parsed_date,
+                    ShortPosition {
+                        owner,
+                        weight,
+                        date: parsed_date.format("%d/%m/%Y").to_string(),
+                    },
+                ),
+            );
+        }
+    }
+
+    Ok(latest
+        .into_iter()
+        .map(|(isin, holders)| (isin, holders.map(_alive_positions)))
+        .collect())
+}
+
+/// Build the alive positions of a company from the latest notification of each holder.
+fn _alive_positions(holders: Holders) -> AliveShortPositions {
+    let mut positions: Vec<ShortPosition> = holders
+        .into_values()
+        .map(|(_, position)| position)
+        .filter(|position| position.weight >= DISCLOSURE_THRESHOLD)
+        .collect();
+    positions.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+
+    let total = positions.iter().map(|position| position.weight).sum();
+
+    AliveShortPositions {
+        total,
+        positions,
+        date: Date::today_utc(),
+    }
+}
+
+/// Error types for the registry provider.
+#[derive(Clone, Debug, Error)]
+pub enum RegistryError {
+    /// The registry file could not be retrieved.
+    #[error("the registry file is not available: {0}")]
+    Source(String),
+    /// The registry file is not a valid CSV file.
+    #[error("wrong format of the registry file: {0}")]
+    Format(String),
+    /// A mandatory column is not present in the registry file.
+    #[error("the registry file has no column for the {0}")]
+    MissingColumn(&'static str),
+    /// A line of the registry file has missing or wrong values.
+    #[error("invalid entry at line {0} of the registry file")]
+    InvalidRecord(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finance::Ticker;
+    use pretty_assertions::assert_eq;
+    use rstest::{fixture, rstest};
+
+    #[fixture]
+    fn a_company() -> IbexCompany {
+        IbexCompany::new(
+            Some("Grifols"),
+            "GRIFOLS",
+            Ticker::new("GRF").unwrap(),
+            "ES0171996087",
+            Some("A-58389123"),
+        )
+    }
+
+    fn fixture_path(name: &str) -> String {
+        format!(
+            "{}/tests/fixtures/registry/{name}",
+            env!("CARGO_MANIFEST_DIR")
+        )
+    }
+
+    #[rstest]
+    #[case("esma.csv")]
+    #[case("cnmv.csv")]
+    fn short_positions_from_file(a_company: IbexCompany, #[case] file: &str) {
+        let provider = RegistryProvider::new(&fixture_path(file));
+
+        let shorts = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(provider.short_positions(&a_company))
+            .unwrap();
+
+        // Old notifications and positions below the threshold are dropped.
+        assert_eq!(shorts.positions.len(), 2);
+        assert_eq!(shorts.positions[0].owner, "MARSHALL WACE LLP");
+        assert_eq!(shorts.positions[0].weight, 1.1);
+        assert_eq!(shorts.positions[0].date, "12/06/2024");
+        assert_eq!(shorts.positions[1].owner, "AQR CAPITAL MANAGEMENT LLC");
+        assert!((shorts.total - 1.75).abs() < 1e-5);
+    }
+
+    #[rstest]
+    fn unknown_isin() {
+        let content = std::fs::read_to_string(fixture_path("esma.csv")).unwrap();
+        let shorts = parse_registry(&content, "ES0000000000").unwrap();
+
+        assert!(shorts.positions.is_empty());
+        assert_eq!(shorts.total, 0.0);
+    }
+
+    #[rstest]
+    fn missing_column() {
+        let content = "Position Holder,ISIN,Position Date\nA,ES0171996087,2024-06-12\n";
+
+        assert!(matches!(
+            parse_registry(content, "ES0171996087"),
+            Err(RegistryError::MissingColumn("net short position"))
+        ));
+    }
+
+    #[rstest]
+    fn invalid_weight() {
+        let content = "Position Holder,ISIN,Net Short Position (%),Position Date\n\
+                       A,ES0171996087,abc,2024-06-12\n";
+
+        assert!(matches!(
+            parse_registry(content, "ES0171996087"),
+            Err(RegistryError::InvalidRecord(2))
+        ));
+    }
+
+    #[rstest]
+    fn invalid_entry_of_other_company() {
+        let content = "Position Holder,ISIN,Net Short Position (%),Position Date\n\
+                       A,ES0000000000,abc,2024-06-12\n\
+                       B,ES0171996087,0.75,2024-06-12\n";
+        let registry = parse_registry_file(content).unwrap();
+
+        assert!(matches!(
+            registry["ES0000000000"],
+            Err(RegistryError::InvalidRecord(2))
+        ));
+        assert_eq!(parse_registry(content, "ES0171996087").unwrap().total, 0.75);
+    }
+
+    #[rstest]
+    #[case(Duration::from_secs(3600), 1.1)]
+    #[case(Duration::ZERO, 2.0)]
+    fn cached_registry(a_company: IbexCompany, #[case] ttl: Duration, #[case] expected: f32) {
+        let path = std::env::temp_dir().join(format!(
+            "registry_{}_{}.csv",
+            ttl.as_secs(),
+            std::process::id()
+        ));
+        let entry = |weight: &str| {
+            format!("Position Holder,ISIN,Net Short Position (%),Position Date\nA,ES0171996087,{weight},2024-06-12\n")
+        };
+        std::fs::write(&path, entry("1.1")).unwrap();
+        let provider = RegistryProvider::new(&path.to_string_lossy()).with_ttl(ttl);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        runtime
+            .block_on(provider.short_positions(&a_company))
+            .unwrap();
+        std::fs::write(&path, entry("2.0")).unwrap();
+        let shorts = runtime
+            .block_on(provider.short_positions(&a_company))
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // The file is only read again once the TTL expires.
+        assert_eq!(shorts.total, expected);
+    }
+
+    #[rstest]
+    fn missing_file(a_company: IbexCompany) {
+        let provider = RegistryProvider::new(&fixture_path("does_not_exist.csv"));
+
+        let result = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(provider.short_positions(&a_company));
+
+        assert!(matches!(result, Err(RegistryError::Source(_))));
+    }
+}
@@ -12,9 +12,12 @@
 //    See the License for the specific language governing permissions and
 //    limitations under the License.
 
-use crate::finance::IbexCompany;
+use crate::finance::{IbexCompany, Ticker};
+use crate::text::search_key;
+use serde::Deserialize;
 use std::fs::read_to_string;
 use std::{collections::HashMap, fmt};
+use thiserror::Error;
 use toml::Table;
 use tracing::{debug, info};
 
@@ -34,7 +37,7 @@ pub struct Ibex35Market {
     open_time: String,
     close_time: String,
     currency: String,
-    company_map: HashMap<String, IbexCompany>,
+    company_map: HashMap<Ticker, IbexCompany>,
 }
 
 /// The Market trait object only allows reading data once is built.
@@ -57,7 +60,7 @@ impl Ibex35Market {
     /// be input at instantiation time, and external logic must ensure an instantiation
     /// of this object complies with the invariant (for example, if there's a change in
     /// the composition of the index).
-    pub fn new(company_map: HashMap<String, IbexCompany>) -> Self {
+    pub fn new(company_map: HashMap<Ticker, IbexCompany>) -> Self {
         Ibex35Market {
             name: String::from("BME Ibex35 Index"),
             open_time: String::from("08:00:00"),
@@ -82,7 +85,7 @@ impl Ibex35Market {
     /// ## Returns
     ///
     /// A vector with references to the tickers.
-    pub fn list_tickers(&self) -> Vec<&String> {
+    pub fn list_tickers(&self) -> Vec<&Ticker> {
         let mut tickers = Vec::new();
         self.company_map.keys().for_each(|c| tickers.push(c));
 
@@ -94,7 +97,8 @@ impl Ibex35Market {
     /// # Description
     ///
     /// This method searches for stocks identified by `name` in the market. The given
-    /// name is applied in a regular expression. This means that if the `name` is too
+    /// name is applied in a regular expression, ignoring the case, the accents and the
+    /// extra whitespace. This means that if the `name` is too
     /// ambiguous, multiple stocks might match it. For example, if **Bank** is given as
     /// `name`, multiple stocks might match such string.
     ///
@@ -105,10 +109,10 @@ impl Ibex35Market {
     /// stocks have been found matching `name` with their respective names.
     pub fn stock_by_name(&self, name: &str) -> Option<Vec<&IbexCompany>> {
         let mut stocks = Vec::new();
+        let name = search_key(name);
 
         for stock in self.company_map.values() {
-            let stock_lowercase = stock.name().to_ascii_lowercase();
-            if stock_lowercase.contains(&name.to_ascii_lowercase()) {
+            if search_key(stock.name()).contains(&name) {
                 stocks.push(stock);
             }
         }
@@ -126,7 +130,8 @@ impl Ibex35Market {
     ///
     /// This method searches for a stock whose ticker is equal to `ticker`. An
     /// exhaustive match is applied between `ticker` and the ticker of a Company.
-    /// This means that partial tickers won't produce a match.
+    /// This means that partial tickers won't produce a match. The case of `ticker` is
+    /// ignored, see [Ticker].
     ///
     /// ## Returns
     ///
@@ -134,11 +139,9 @@ impl Ibex35Market {
     /// return a wrapped reference to an object that implements the `Company` trait
     /// whose ticker is equal to `ticker`, otherwise `None` will be returned.
     pub fn stock_by_ticker(&self, ticker: &str) -> Option<&IbexCompany> {
-        if let Some(stock) = self.company_map.get(ticker) {
-            Some(stock)
-        } else {
-            None
-        }
+        let ticker = Ticker::new(ticker).ok()?;
+
+        self.company_map.get(&ticker)
     }
 
     /// Get the open time of the market (UTC).
@@ -205,6 +208,36 @@ impl fmt::Debug for Ibex35Market {
     }
 }
 
+/// Error types for the listing loader.
+#[derive(Debug, Error)]
+pub enum ListingError {
+    /// The listing file could not be read.
+    #[error("error opening the listing file {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The content of the file is not a valid TOML table.
+    #[error("could not parse the listing file as a TOML table: {0}")]
+    Syntax(#[from] toml::de::Error),
+    /// A company descriptor is missing a mandatory field or has a wrong value.
+    #[error("malformed descriptor for the company {company}: {reason}")]
+    MalformedCompany { company: String, reason: String },
+}
+
+/// Descriptor of a company as written in the listing file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CompanyDescriptor {
+    full_name: Option<String>,
+    name: String,
+    isin: String,
+    ticker: String,
+    extra_id: Option<String>,
+    sector: Option<String>,
+}
+
 /// Helper function to build an [Ibex35Market] object from a file.
 ///
 /// # Description
@@ -222,45 +255,83 @@ impl fmt::Debug for Ibex35Market {
 /// isin = <ISIN>
 /// ticker = <BME TICKER>
 /// extra_id = <NIF>
+/// sector = <Sector of the company, e.g. banking>
 /// ```
 ///
+/// The fields `full_name`, `extra_id` and `sector` are optional. An empty `extra_id` is
+/// considered as missing, which is the usual case for companies registered outside
+/// of Spain.
+///
 /// ## Arguments
 ///
 /// - _path_: a string that points to the TOML file.
 ///
 /// ## Returns
 ///
-/// An `enum` `Result<T, &str>` in which `T` implements the Market trait, and
-/// the `str` indicates an error message.
-pub fn load_ibex35_companies(path: &str) -> Result<Ibex35Market, &'static str> {
+/// An `enum` `Result<T, ListingError>` in which `T` is an [Ibex35Market]. When a
+/// descriptor is malformed, the error points to the company and the offending field.
+pub fn load_ibex35_companies(path: &str) -> Result<Ibex35Market, ListingError> {
+    Ok(Ibex35Market::new(load_companies(path)?))
+}
+
+/// Parse a listing file into a collection of companies identified by their ticker.
+///
+/// # Description
+///
+/// See [load_ibex35_companies] for the format of the file.
+pub(crate) fn load_companies(path: &str) -> Result<HashMap<Ticker, IbexCompany>, ListingError> {
     info!("File {path} will be parsed to find stock descriptors.");
 
-    let toml_parsed = match read_to_string(path) {
-        Ok(data) => data,
-        Err(_) => return Err("Error opening the input file"),
-    };
+    let toml_parsed = read_to_string(path).map_err(|e| ListingError::Io {
+        path: String::from(path),
+        source: e,
+    })?;
 
-    let table = match toml_parsed.parse::<Table>() {
-        Ok(data) => data,
-        Err(_) => return Err("Could not parse the file as a TOML table"),
-    };
+    let table = toml_parsed.parse::<Table>()?;
 
-    let mut map: HashMap<String, IbexCompany> = HashMap::new();
+    let mut map: HashMap<Ticker, IbexCompany> = HashMap::new();
 
-    for key in table.keys() {
+    for (key, value) in table {
         debug!("Found company descriptor for {key}");
-        let fname = table[key]["full_name"].as_str().unwrap();
-        let sname = table[key]["full_name"].as_str().unwrap();
-        let ticker = table[key]["ticker"].as_str().unwrap();
-        let isin = table[key]["isin"].as_str().unwrap();
-        let nif = table[key]["extra_id"].as_str().unwrap();
 
-        let company = IbexCompany::new(Some(fname), sname, ticker, isin, Some(nif));
+        let descriptor: CompanyDescriptor =
+            value
+                .try_into()
+                .map_err(|e: toml::de::Error| ListingError::MalformedCompany {
+                    company: key.clone(),
+                    reason: e.message().to_string(),
+                })?;
+
+        let ticker =
+            Ticker::new(&descriptor.ticker).map_err(|e| ListingError::MalformedCompany {
+                company: key.clone(),
+                reason: e.to_string(),
+            })?;
+
+        // Entries and tickers are compared once normalized, e.g. `grf` matches `GRF`.
+        if Ticker::new(&key).ok().as_ref() != Some(&ticker) {
+            return Err(ListingError::MalformedCompany {
+                company: key,
+                reason: format!("ticker `{}` does not match the entry", descriptor.ticker),
+            });
+        }
 
-        map.insert(String::from(ticker), company);
+        let mut company = IbexCompany::new(
+            descriptor.full_name.as_deref(),
+            &descriptor.name,
+            ticker.clone(),
+            &descriptor.isin,
+            descriptor.extra_id.as_deref().filter(|id| !id.is_empty()),
+        );
+
+        if let Some(sector) = descriptor.sector.as_deref().filter(|s| !s.is_empty()) {
+            company = company.with_sector(sector);
+        }
+
+        map.insert(ticker, company);
     }
 
-    Ok(Ibex35Market::new(map))
+    Ok(map)
 }
 
 #[cfg(test)]
@@ -270,37 +341,37 @@ mod tests {
     use std::collections::HashMap;
 
     #[fixture]
-    fn ibex35_companies() -> HashMap<String, IbexCompany> {
-        let mut companies = HashMap::<String, IbexCompany>::new();
+    fn ibex35_companies() -> HashMap<Ticker, IbexCompany> {
+        let mut companies = HashMap::<Ticker, IbexCompany>::new();
 
         companies.insert(
-            String::from("AENA"),
+            Ticker::new("AENA").unwrap(),
             IbexCompany::new(
                 Some("AENA S.A."),
                 "AENA",
-                "AENA",
+                Ticker::new("AENA").unwrap(),
                 "ES0105046009",
                 Some("A86212420"),
             ),
         );
 
         companies.insert(
-            String::from("AMS"),
+            Ticker::new("AMS").unwrap(),
             IbexCompany::new(
                 Some("Amadeus IT Holding S.A."),
                 "AMADEUS",
-                "AMS",
+                Ticker::new("AMS").unwrap(),
                 "ES0109067019",
                 Some("A-84236934"),
             ),
         );
 
         companies.insert(
-            String::from("CLNX"),
+            Ticker::new("CLNX").unwrap(),
             IbexCompany::new(
                 Some("Cellnex Telecom S.A."),
                 "CELLNEX",
-                "CLNX",
+                Ticker::new("CLNX").unwrap(),
                 "ES0105066007",
                 Some("A64907306"),
             ),
@@ -311,7 +382,7 @@ mod tests {
 
     // Test case for the creation of a IbexMarket object.
     #[rstest]
-    fn new(ibex35_companies: HashMap<String, IbexCompany>) {
+    fn new(ibex35_companies: HashMap<Ticker, IbexCompany>) {
         let market = Ibex35Market::new(ibex35_companies);
 
         assert_eq!(market.get_companies().len(), 3);
@@ -319,7 +390,7 @@ mod tests {
 
     // Test case for the implementation of the Market trait.
     #[rstest]
-    fn interface(ibex35_companies: HashMap<String, IbexCompany>) {
+    fn interface(ibex35_companies: HashMap<Ticker, IbexCompany>) {
         let market = Ibex35Market::new(ibex35_companies);
 
         // Let's check that we get the same amount of companies using these methods:
@@ -331,6 +402,83 @@ mod tests {
         // Check for companies by ticker.
         assert!(market.stock_by_ticker("SAN").is_none());
         assert!(market.stock_by_ticker("AENA").is_some());
+        assert!(market.stock_by_ticker(" aena").is_some());
         assert!(market.stock_by_ticker("CLNX").is_some());
     }
+
+    fn fixture_path(name: &str) -> String {
+        format!(
+            "{}/tests/fixtures/listings/{name}",
+            env!("CARGO_MANIFEST_DIR")
+        )
+    }
+
+    // Test case for the bundled listing file.
+    #[rstest]
+    fn load_bundled_listing() {
+        let path = format!("{}/data/ibex35.toml", env!("CARGO_MANIFEST_DIR"));
+        let market = load_ibex35_companies(&path).unwrap();
+
+        assert_eq!(market.get_companies().len(), 35);
+        let grifols = market.stock_by_ticker("GRF").unwrap();
+        assert_eq!(grifols.name(), "GRIFOLS");
+        assert_eq!(grifols.extra_id().map(String::as_str), Some("A-58389123"));
+        // Foreign companies have no NIF.
+        assert!(market.stock_by_ticker("FER").unwrap().extra_id().is_none());
+    }
+
+    #[rstest]
+    fn load_optional_fields() {
+        let market = load_ibex35_companies(&fixture_path("optional_fields.toml")).unwrap();
+
+        let company = market.stock_by_ticker("FER").unwrap();
+        assert!(company.full_name().is_none());
+        assert!(company.extra_id().is_none());
+        assert!(company.sector().is_none());
+        assert!(market.stock_by_ticker("MTS").unwrap().extra_id().is_none());
+        assert_eq!(
+            market.stock_by_ticker("MTS").unwrap().sector(),
+            Some("materials")
+        );
+    }
+
+    #[rstest]
+    fn load_normalized_entries() {
+        let market = load_ibex35_companies(&fixture_path("lowercase_entries.toml")).unwrap();
+
+        assert_eq!(market.get_companies().len(), 2);
+        assert!(market.stock_by_ticker("GRF").is_some());
+        assert!(market.stock_by_ticker("SAN").is_some());
+    }
+
+    #[rstest]
+    #[case("missing_field.toml", "GRF", "isin")]
+    #[case("wrong_type.toml", "SAN", "string")]
+    #[case("ticker_mismatch.toml", "BBVA", "BVA")]
+    #[case("unknown_field.toml", "REP", "country")]
+    fn load_malformed_company(#[case] file: &str, #[case] company: &str, #[case] hint: &str) {
+        match load_ibex35_companies(&fixture_path(file)) {
+            Err(ListingError::MalformedCompany { company: c, reason }) => {
+                assert_eq!(c, company);
+                assert!(reason.contains(hint), "unexpected reason: {reason}");
+            }
+            other => panic!("Unexpected result: {other:?}"),
+        }
+    }
+
+    #[rstest]
+    fn load_bad_syntax() {
+        assert!(matches!(
+            load_ibex35_companies(&fixture_path("bad_syntax.toml")),
+            Err(ListingError::Syntax(_))
+        ));
+    }
+
+    #[rstest]
+    fn load_missing_file() {
+        assert!(matches!(
+            load_ibex35_companies(&fixture_path("does_not_exist.toml")),
+            Err(ListingError::Io { .. })
+        ));
+    }
 }
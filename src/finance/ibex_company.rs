@@ -24,6 +24,7 @@
 //! [financelib]: https://github.com/felipet/finance_api
 //! [ibexindexes]: https://www.bolsasymercados.es/bme-exchange/en/Indices/Ibex
 
+use crate::finance::Ticker;
 use std::fmt;
 
 /// An relaxed implementation of the [Company][company] trait for a company that
@@ -39,12 +40,14 @@ pub struct IbexCompany {
     /// of the _full name_.
     short_name: String,
     /// The identifier of the company in the market.
-    ticker: String,
+    ticker: Ticker,
     /// The _International Securities Identification Number_.
     isin: String,
     /// A local identifier for Spanish companies. This is optional as some companies,
     /// which are included in an Ibex index, might be registered in another country.
     nif: Option<String>,
+    /// The sector of the economy in which the company operates, e.g. `banking`. Optional.
+    sector: Option<String>,
 }
 
 impl IbexCompany {
@@ -57,7 +60,7 @@ impl IbexCompany {
     /// ## Arguments
     ///
     /// - _fname_: Optional full name of the company. Useful for companies with very long names,
-    ///            such as IAG (International Airlines Group).
+    ///   such as IAG (International Airlines Group).
     /// - _sname_: Short name. Usually part of the full name or the ticker.
     /// - _ticker_: The ticker of the company in the IBEX35 market.
     /// - _isin_: The ISIN number.
@@ -67,19 +70,31 @@ impl IbexCompany {
     pub fn new(
         fname: Option<&str>,
         sname: &str,
-        ticker: &str,
+        ticker: Ticker,
         isin: &str,
         nif: Option<&str>,
     ) -> IbexCompany {
         IbexCompany {
             full_name: fname.map(String::from),
             short_name: String::from(sname),
-            ticker: String::from(ticker),
+            ticker,
             isin: String::from(isin),
             nif: nif.map(String::from),
+            sector: None,
         }
     }
 
+    /// Set the sector of the company.
+    ///
+    /// # Description
+    ///
+    /// Sectors are identified by a lowercase key, such as `banking` or `energy`. The key
+    /// is not checked against a list of known sectors.
+    pub fn with_sector(mut self, sector: &str) -> IbexCompany {
+        self.sector = Some(String::from(sector));
+        self
+    }
+
     /// Get the most common name of the stock.
     pub fn name(&self) -> &str {
         &self.short_name
@@ -102,7 +117,7 @@ impl IbexCompany {
     }
 
     /// Get the ticker of a stock.
-    pub fn ticker(&self) -> &str {
+    pub fn ticker(&self) -> &Ticker {
         &self.ticker
     }
 
@@ -121,6 +136,15 @@ impl IbexCompany {
     pub fn extra_id(&self) -> Option<&String> {
         self.nif.as_ref()
     }
+
+    /// Get the sector of the company.
+    ///
+    /// ## Returns
+    ///
+    /// `None` when the sector of the company is unknown.
+    pub fn sector(&self) -> Option<&str> {
+        self.sector.as_deref()
+    }
 }
 
 impl fmt::Display for IbexCompany {
@@ -137,6 +161,7 @@ impl fmt::Debug for IbexCompany {
             .field(&self.ticker())
             .field(&self.isin())
             .field(&self.extra_id())
+            .field(&self.sector())
             .finish()
     }
 }
@@ -154,7 +179,7 @@ mod tests {
         IbexCompany::new(
             Some("Banco Santander"),
             "SANTANDER",
-            "SAN",
+            Ticker::new("SAN").unwrap(),
             "ES0113900J37",
             Some("A39000013"),
         )
@@ -167,7 +192,7 @@ mod tests {
         IbexCompany::new(
             Some("Ferrovial S.E."),
             "FERROVIAL",
-            "FER",
+            Ticker::new("FER").unwrap(),
             "NL0015001FS8",
             None,
         )
@@ -189,4 +214,11 @@ mod tests {
         println!("Company -> {foreign_company}");
         assert_eq!(None, foreign_company.extra_id());
     }
+
+    #[rstest]
+    fn company_sector(spanish_company: IbexCompany) {
+        assert_eq!(None, spanish_company.sector());
+        let spanish_company = spanish_company.with_sector("banking");
+        assert_eq!(Some("banking"), spanish_company.sector());
+    }
 }
@@ -0,0 +1,116 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! issuers.rs
+//!
+//! Module that keeps the registry of issuers of the continuous market, which are
+//! searched when a company is not part of the Ibex35.
+
+use crate::finance::ibex35::load_companies;
+use crate::finance::{IbexCompany, ListingError, Ticker};
+use crate::text::search_key;
+use std::collections::HashMap;
+
+/// Registry of issuers that are not part of the Ibex35.
+///
+/// # Description
+///
+/// Companies of the continuous market (_Mercado Continuo_) are not included in the
+/// index, but their short positions are also notified to the CNMV. This registry
+/// allows looking them up on demand. The coverage is best-effort: only the companies
+/// listed in the registry file are known.
+///
+/// The registry file uses the same format as the listing file of the Ibex35, see
+/// [load_ibex35_companies][super::load_ibex35_companies].
+#[derive(Debug, Default)]
+pub struct IssuerRegistry {
+    company_map: HashMap<Ticker, IbexCompany>,
+}
+
+impl IssuerRegistry {
+    /// Constructor of the [IssuerRegistry] object.
+    pub fn new(company_map: HashMap<Ticker, IbexCompany>) -> Self {
+        IssuerRegistry { company_map }
+    }
+
+    /// Build an [IssuerRegistry] from a file.
+    ///
+    /// ## Arguments
+    ///
+    /// - _path_: a string that points to the TOML file.
+    pub fn load(path: &str) -> Result<IssuerRegistry, ListingError> {
+        Ok(IssuerRegistry::new(load_companies(path)?))
+    }
+
+    /// Get the number of issuers of the registry.
+    pub fn len(&self) -> usize {
+        self.company_map.len()
+    }
+
+    /// Check whether the registry has no issuers.
+    pub fn is_empty(&self) -> bool {
+        self.company_map.is_empty()
+    }
+
+    /// Get an issuer by its ticker. The match is exhaustive, but the case is ignored.
+    pub fn stock_by_ticker(&self, ticker: &str) -> Option<&IbexCompany> {
+        self.company_map.get(&Ticker::new(ticker).ok()?)
+    }
+
+    /// Get an issuer by its ISIN.
+    pub fn stock_by_isin(&self, isin: &str) -> Option<&IbexCompany> {
+        self.company_map
+            .values()
+            .find(|stock| stock.isin().eq_ignore_ascii_case(isin))
+    }
+
+    /// Get the issuers whose name contains `name`. The search ignores the case, the
+    /// accents and the extra whitespace.
+    pub fn stock_by_name(&self, name: &str) -> Vec<&IbexCompany> {
+        let name = search_key(name);
+
+        self.company_map
+            .values()
+            .filter(|stock| search_key(stock.name()).contains(&name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::{fixture, rstest};
+
+    #[fixture]
+    fn registry() -> IssuerRegistry {
+        IssuerRegistry::load(&format!("{}/data/issuers.toml", env!("CARGO_MANIFEST_DIR")))
+            .expect("Failed to load the registry of issuers")
+    }
+
+    #[rstest]
+    fn load_registry(registry: IssuerRegistry) {
+        assert!(!registry.is_empty());
+
+        let viscofan = registry.stock_by_ticker("VIS").unwrap();
+        assert!(viscofan.extra_id().is_some());
+        assert_eq!(
+            registry.stock_by_isin("es0184262212").unwrap().ticker(),
+            "VIS"
+        );
+
+        let by_name = registry.stock_by_name("visco");
+        assert_eq!(by_name.len(), 1);
+        assert_eq!(by_name[0].ticker(), "VIS");
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! owners.rs
+//!
+//! Module that normalizes the names of the owners of short positions, so the positions
+//! of a fund are grouped regardless of how its name was written.
+
+use crate::text::fold_accents;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use thiserror::Error;
+use tracing::{debug, info};
+
+/// Legal forms that are written in several ways, and their short form.
+const LEGAL_FORMS: [(&str, &str); 4] = [
+    ("LIMITED", "LTD"),
+    ("INCORPORATED", "INC"),
+    ("CORPORATION", "CORP"),
+    ("COMPANY", "CO"),
+];
+
+/// Build the key that identifies an owner of short positions.
+///
+/// # Description
+///
+/// The names of the owners are not consistent across the notifications, e.g.
+/// _MARSHALL WACE LLP_ and _Marshall Wace, LLP_. The key ignores the case, the
+/// accents, the punctuation, the extra whitespace and the usual variants of the legal
+/// forms, so both names share the same key.
+pub fn owner_key(owner: &str) -> String {
+    let cleaned: String = fold_accents(owner)
+        .chars()
+        .filter(|c| !matches!(c, '.' | '\''))
+        .map(|c| match c {
+            '&' => ' ',
+            c if c.is_alphanumeric() => c,
+            _ => ' ',
+        })
+        .collect::<String>()
+        .to_uppercase();
+
+    cleaned
+        .split_whitespace()
+        .filter(|token| *token != "AND")
+        .map(|token| {
+            LEGAL_FORMS
+                .iter()
+                .find(|(long, _)| *long == token)
+                .map_or(token, |(_, short)| short)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Error types for the loader of the aliases of the owners.
+#[derive(Debug, Error)]
+pub enum OwnerAliasesError {
+    /// The file could not be read.
+    #[error("error opening the file of aliases {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The content of the file is not valid.
+    #[error("could not parse the file of aliases: {0}")]
+    Syntax(#[from] toml::de::Error),
+    /// An alias points to two different owners.
+    #[error("the alias {alias} is assigned to {first} and {second}")]
+    Duplicated {
+        alias: String,
+        first: String,
+        second: String,
+    },
+}
+
+/// Canonical names of the owners of short positions.
+///
+/// # Description
+///
+/// Some owners show up with names that the normalization of [owner_key] does not
+/// unify, e.g. when the regulator truncates the name. The aliases are written in a
+/// TOML file, in which each canonical name lists its known variants:
+///
+/// ```toml
+/// "<Canonical name>" = ["<Alias>", "<Alias>"]
+/// ```
+#[derive(Debug, Default)]
+pub struct OwnerAliases {
+    /// Canonical name of the owners, indexed by the key of their aliases.
+    canonical: HashMap<String, String>,
+}
+
+impl OwnerAliases {
+    /// Build the aliases out of a map of canonical names and their variants.
+    pub fn new(aliases: HashMap<String, Vec<String>>) -> Result<OwnerAliases, OwnerAliasesError> {
+        let mut canonical = HashMap::new();
+
+        for (name, variants) in aliases {
+            for alias in variants.iter().chain(std::iter::once(&name)) {
+                match canonical.insert(owner_key(alias), name.clone()) {
+                    Some(previous) if previous != name => {
+                        return Err(OwnerAliasesError::Duplicated {
+                            alias: alias.clone(),
+                            first: previous,
+                            second: name,
+                        })
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        debug!("Loaded {} aliases of owners", canonical.len());
+
+        Ok(OwnerAliases { canonical })
+    }
+
+    /// Build the aliases from a file.
+    ///
+    /// ## Arguments
+    ///
+    /// - _path_: a string that points to the TOML file.
+    pub fn load(path: &str) -> Result<OwnerAliases, OwnerAliasesError> {
+        info!("File {path} will be parsed to find aliases of owners.");
+
+        let content = read_to_string(path).map_err(|e| OwnerAliasesError::Io {
+            path: String::from(path),
+            source: e,
+        })?;
+
+        OwnerAliases::new(toml::from_str(&content)?)
+    }
+
+    /// Get the number of known aliases, including the canonical names.
+    pub fn len(&self) -> usize {
+        self.canonical.len()
+    }
+
+    /// Check whether there are no aliases.
+    pub fn is_empty(&self) -> bool {
+        self.canonical.is_empty()
+    }
+
+    /// Get the name used to present an owner.
+    ///
+    /// ## Returns
+    ///
+    /// The canonical name when the owner is a known alias, otherwise the given name
+    /// without extra whitespace.
+    pub fn canonical_name(&self, owner: &str) -> String {
+        match self.canonical.get(&owner_key(owner)) {
+            Some(name) => name.clone(),
+            None => owner.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+
+    /// Get the key that groups all the names of the same owner.
+    pub fn key(&self, owner: &str) -> String {
+        owner_key(&self.canonical_name(owner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("MARSHALL WACE LLP", "Marshall Wace, LLP")]
+    #[case(
+        "Qube Research & Technologies Limited",
+        "QUBE RESEARCH AND TECHNOLOGIES LTD"
+    )]
+    #[case("AQR Capital Management, L.L.C.", "AQR CAPITAL MANAGEMENT LLC")]
+    #[case("  BlackRock   Inc. ", "BLACKROCK INCORPORATED")]
+    fn same_owner(#[case] a: &str, #[case] b: &str) {
+        assert_eq!(owner_key(a), owner_key(b));
+    }
+
+    #[rstest]
+    fn different_owners() {
+        assert_ne!(
+            owner_key("Millennium Partners LP"),
+            owner_key("Millennium International Management LP")
+        );
+    }
+
+    #[rstest]
+    fn aliases() {
+        let aliases = OwnerAliases::new(HashMap::from([(
+            String::from("Citadel Advisors LLC"),
+            vec![String::from("CITADEL ADVISORS")],
+        )]))
+        .unwrap();
+
+        assert_eq!(
+            aliases.canonical_name("Citadel  Advisors"),
+            "Citadel Advisors LLC"
+        );
+        assert_eq!(
+            aliases.key("CITADEL ADVISORS"),
+            aliases.key("Citadel Advisors, LLC")
+        );
+        assert_eq!(aliases.canonical_name("Other  Fund"), "Other Fund");
+    }
+
+    #[rstest]
+    fn duplicated_alias() {
+        let aliases = OwnerAliases::new(HashMap::from([
+            (String::from("Fund A"), vec![String::from("FUND")]),
+            (String::from("Fund B"), vec![String::from("Fund")]),
+        ]));
+
+        assert!(matches!(aliases, Err(OwnerAliasesError::Duplicated { .. })));
+    }
+
+    #[rstest]
+    fn load_aliases() {
+        let aliases = OwnerAliases::load(&format!(
+            "{}/data/owner_aliases.toml",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .expect("Failed to load the aliases of the owners");
+
+        assert!(!aliases.is_empty());
+    }
+}
@@ -0,0 +1,376 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! providers.rs
+//!
+//! Module that gathers all the data providers of short positions, so they can be
+//! arranged as a chain of fallbacks.
+
+use crate::finance::{owner_key, AliveShortPositions, CNMVError, CNMVProvider, IbexCompany};
+use crate::finance::{RegistryError, RegistryProvider};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+/// Differences of weight below this value are not reported by the shadow mode.
+const SHADOW_TOLERANCE: f32 = 0.01;
+
+/// Number of answers compared with the shadow provider.
+static SHADOW_COMPARISONS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of answers of the shadow provider that differ from the ones shown to the users.
+static SHADOW_MISMATCHES: AtomicU64 = AtomicU64::new(0);
+
+/// Get the number of answers compared with the shadow provider since the start of the bot.
+pub fn shadow_comparisons() -> u64 {
+    SHADOW_COMPARISONS.load(Ordering::Relaxed)
+}
+
+/// Get the number of answers of the shadow provider that differed since the start of the
+/// bot.
+pub fn shadow_mismatches() -> u64 {
+    SHADOW_MISMATCHES.load(Ordering::Relaxed)
+}
+
+/// Supported sources of short positions.
+pub enum ShortProvider {
+    /// Scraper of the CNMV's web page.
+    Cnmv(CNMVProvider),
+    /// Bulk files published by ESMA or the CNMV.
+    Registry(RegistryProvider),
+}
+
+impl ShortProvider {
+    /// Get a human-friendly name of the provider.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShortProvider::Cnmv(_) => "CNMV web",
+            ShortProvider::Registry(_) => "Short positions registry",
+        }
+    }
+
+    /// Method that checks alive short positions of a stock using the wrapped provider.
+    pub async fn short_positions(
+        &self,
+        stock: &IbexCompany,
+    ) -> Result<AliveShortPositions, ProviderError> {
+        match self {
+            ShortProvider::Cnmv(p) => Ok(p.short_positions(stock).await?),
+            ShortProvider::Registry(p) => Ok(p.short_positions(stock).await?),
+        }
+    }
+}
+
+/// Ordered collection of providers.
+///
+/// # Description
+///
+/// Providers are checked in the same order as they were given to the constructor.
+/// The first one that succeeds gives the result, thus the preferred provider shall
+/// be the first one, and the rest act as fallbacks.
+///
+/// A shadow provider can be added to evaluate it before making it part of the chain.
+/// It is requested in the background after the successful answers of the chain, and
+/// the differences are logged and counted (see [shadow_mismatches]). Its answers are
+/// never returned. Only one request to the shadow provider runs at a time: answers of
+/// the chain given meanwhile are not compared, so the shadow mode never multiplies the
+/// load of the sources.
+pub struct ProviderChain {
+    providers: Vec<ShortProvider>,
+    shadow: Option<Arc<ShortProvider>>,
+    shadow_busy: Arc<AtomicBool>,
+}
+
+/// Mark of a running request to the shadow provider, released when dropped.
+struct ShadowSlot(Arc<AtomicBool>);
+
+impl ShadowSlot {
+    /// Take the slot, unless another request is running.
+    fn try_acquire(busy: &Arc<AtomicBool>) -> Option<ShadowSlot> {
+        busy.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| ShadowSlot(Arc::clone(busy)))
+    }
+}
+
+impl Drop for ShadowSlot {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl ProviderChain {
+    /// Class constructor.
+    pub fn new(providers: Vec<ShortProvider>) -> ProviderChain {
+        ProviderChain {
+            providers,
+            shadow: None,
+            shadow_busy: Arc::default(),
+        }
+    }
+
+    /// Add a shadow provider to the chain.
+    pub fn with_shadow(mut self, shadow: ShortProvider) -> ProviderChain {
+        info!("Using the provider {} in shadow mode", shadow.name());
+        self.shadow = Some(Arc::new(shadow));
+        self
+    }
+
+    /// Method that checks alive short positions of a stock.
+    ///
+    /// ## Returns
+    ///
+    /// The result of the first provider that succeeds. If all of them fail, the error
+    /// of the last provider is returned.
+    pub async fn short_positions(
+        &self,
+        stock: &IbexCompany,
+    ) -> Result<AliveShortPositions, ProviderError> {
+        let mut error = ProviderError::NoProvider;
+
+        for provider in self.providers.iter() {
+            debug!(
+                "Requesting short positions to the provider: {}",
+                provider.name()
+            );
+            match provider.short_positions(stock).await {
+                Ok(positions) => {
+                    self.compare_shadow(stock, &positions);
+                    return Ok(positions);
+                }
+                Err(e) => {
+                    warn!("The provider {} failed: {e}", provider.name());
+                    error = e;
+                }
+            }
+        }
+
+        Err(error)
+    }
+
+    /// Spawn the request to the shadow provider, if any, and compare its answer with
+    /// the given positions.
+    fn compare_shadow(&self, stock: &IbexCompany, positions: &AliveShortPositions) {
+        let Some(shadow) = self.shadow.as_ref().map(Arc::clone) else {
+            return;
+        };
+        let Some(slot) = ShadowSlot::try_acquire(&self.shadow_busy) else {
+            debug!("The shadow provider is busy, {stock} is not compared");
+            return;
+        };
+        let stock = stock.clone();
+        let expected = weights_by_owner(positions);
+
+        tokio::spawn(async move {
+            let _slot = slot;
+            let answer = match shadow.short_positions(&stock).await {
+                Ok(answer) => answer,
+                Err(e) => {
+                    warn!(
+                        "The shadow provider {} failed for {stock}: {e}",
+                        shadow.name()
+                    );
+                    return;
+                }
+            };
+
+            SHADOW_COMPARISONS.fetch_add(1, Ordering::Relaxed);
+            let differences = shadow_differences(&expected, &weights_by_owner(&answer));
+
+            if differences.is_empty() {
+                debug!("The shadow provider {} agrees for {stock}", shadow.name());
+            } else {
+                SHADOW_MISMATCHES.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "The shadow provider {} differs for {stock}: {}",
+                    shadow.name(),
+                    differences.join("; ")
+                );
+            }
+        });
+    }
+}
+
+/// Get the weight of the positions indexed by the key of their owner.
+fn weights_by_owner(positions: &AliveShortPositions) -> BTreeMap<String, f32> {
+    positions
+        .positions
+        .iter()
+        .map(|p| (owner_key(&p.owner), p.weight))
+        .collect()
+}
+
+/// Describe the differences between the answer shown to the users and the answer of the
+/// shadow provider.
+fn shadow_differences(
+    expected: &BTreeMap<String, f32>,
+    shadow: &BTreeMap<String, f32>,
+) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    for (owner, weight) in expected {
+        match shadow.get(owner) {
+            None => differences.push(format!("{owner} is missing")),
+            Some(other) if (weight - other).abs() >= SHADOW_TOLERANCE => {
+                differences.push(format!("{owner} has {other}% instead of {weight}%"))
+            }
+            Some(_) => (),
+        }
+    }
+
+    for owner in shadow.keys().filter(|owner| !expected.contains_key(*owner)) {
+        differences.push(format!("{owner} is not expected"));
+    }
+
+    differences
+}
+
+/// Error types for the chain of providers.
+#[derive(Debug, Error)]
+pub enum ProviderError {
+    #[error(transparent)]
+    Cnmv(#[from] CNMVError),
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+    /// The chain has no providers.
+    #[error("no provider of short positions is configured")]
+    NoProvider,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finance::Ticker;
+    use rstest::{fixture, rstest};
+
+    // A company without NIF can't be checked in the CNMV's web page.
+    #[fixture]
+    fn foreign_company() -> IbexCompany {
+        IbexCompany::new(
+            Some("Grifols"),
+            "GRIFOLS",
+            Ticker::new("GRF").unwrap(),
+            "ES0171996087",
+            None,
+        )
+    }
+
+    fn registry() -> RegistryProvider {
+        RegistryProvider::new(&format!(
+            "{}/tests/fixtures/registry/esma.csv",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[rstest]
+    fn fallback_provider(foreign_company: IbexCompany) {
+        let chain = ProviderChain::new(vec![
+            ShortProvider::Cnmv(CNMVProvider::new()),
+            ShortProvider::Registry(registry()),
+        ]);
+
+        let shorts = runtime().block_on(chain.short_positions(&foreign_company));
+
+        assert_eq!(shorts.unwrap().positions.len(), 2);
+    }
+
+    #[rstest]
+    fn all_providers_fail(foreign_company: IbexCompany) {
+        let chain = ProviderChain::new(vec![ShortProvider::Cnmv(CNMVProvider::new())]);
+
+        let shorts = runtime().block_on(chain.short_positions(&foreign_company));
+
+        assert!(matches!(
+            shorts,
+            Err(ProviderError::Cnmv(CNMVError::UnknownCompany))
+        ));
+    }
+
+    #[rstest]
+    fn shadow_provider(foreign_company: IbexCompany) {
+        // The CNMV's web page can't answer for a foreign company, so the answer comes
+        // from the registry, and the shadow provider agrees with it.
+        let chain = ProviderChain::new(vec![ShortProvider::Registry(registry())])
+            .with_shadow(ShortProvider::Registry(registry()));
+        let comparisons = shadow_comparisons();
+
+        let shorts = runtime().block_on(async {
+            let shorts = chain.short_positions(&foreign_company).await;
+            // Let the comparison finish.
+            for _ in 0..100 {
+                if shadow_comparisons() > comparisons {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            shorts
+        });
+
+        assert_eq!(shorts.unwrap().positions.len(), 2);
+        assert!(shadow_comparisons() > comparisons);
+    }
+
+    #[rstest]
+    fn shadow_slot() {
+        let busy = Arc::new(AtomicBool::new(false));
+
+        let slot = ShadowSlot::try_acquire(&busy);
+        assert!(slot.is_some());
+        assert!(ShadowSlot::try_acquire(&busy).is_none());
+        drop(slot);
+        assert!(ShadowSlot::try_acquire(&busy).is_some());
+    }
+
+    #[rstest]
+    fn shadow_comparison() {
+        let expected = BTreeMap::from([
+            (String::from("MARSHALL WACE LLP"), 1.1),
+            (String::from("AQR CAPITAL"), 0.65),
+            (String::from("WORLDQUANT LLC"), 0.5),
+        ]);
+        let shadow = BTreeMap::from([
+            (String::from("MARSHALL WACE LLP"), 1.105),
+            (String::from("AQR CAPITAL"), 0.7),
+            (String::from("CITADEL"), 0.5),
+        ]);
+
+        assert_eq!(
+            shadow_differences(&expected, &shadow),
+            [
+                "AQR CAPITAL has 0.7% instead of 0.65%",
+                "WORLDQUANT LLC is missing",
+                "CITADEL is not expected",
+            ]
+        );
+        assert!(shadow_differences(&expected, &expected).is_empty());
+    }
+
+    #[rstest]
+    fn empty_chain(foreign_company: IbexCompany) {
+        let chain = ProviderChain::new(Vec::new());
+
+        let shorts = runtime().block_on(chain.short_positions(&foreign_company));
+
+        assert!(matches!(shorts, Err(ProviderError::NoProvider)));
+    }
+}
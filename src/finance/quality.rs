@@ -0,0 +1,212 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! quality.rs
+//!
+//! Module that checks the short positions returned by the data providers.
+//!
+//! # Description
+//!
+//! The sources of data are web pages and files maintained by third parties, thus a
+//! change of layout or a mistake in a notification could show wrong data to the users.
+//! Every short position is checked before reaching the [ShortCache][super::ShortCache]:
+//!
+//! - The weight must be a percentage (0–100).
+//! - The date, when given, must be valid and not in the future.
+//! - The owner must not be empty.
+//!
+//! Suspicious positions are put in quarantine: they are not shown to the users, and
+//! they are reported to the administrators. The ticker needs no check, as the providers
+//! are only requested for the companies of the listing files.
+
+use crate::finance::{parse_position_date, AliveShortPositions, ShortPosition, Ticker};
+use date::Date;
+use std::fmt;
+
+/// Date of the positions whose source doesn't include dates.
+const NO_DATE: &str = "-";
+
+/// Reasons to put a short position in quarantine.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QualityIssue {
+    /// The weight is not a percentage.
+    WeightOutOfRange,
+    /// The date is later than today.
+    FutureDate,
+    /// The date can't be parsed.
+    InvalidDate,
+    /// The name of the owner is empty.
+    EmptyOwner,
+}
+
+impl fmt::Display for QualityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            QualityIssue::WeightOutOfRange => "weight out of range",
+            QualityIssue::FutureDate => "date in the future",
+            QualityIssue::InvalidDate => "invalid date",
+            QualityIssue::EmptyOwner => "empty owner",
+        })
+    }
+}
+
+/// Short position put in quarantine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RejectedPosition {
+    /// Ticker of the company.
+    pub ticker: Ticker,
+    /// The position, as given by the provider.
+    pub position: ShortPosition,
+    /// Reasons to reject the position.
+    pub issues: Vec<QualityIssue>,
+}
+
+impl fmt::Display for RejectedPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let issues: Vec<String> = self.issues.iter().map(|i| i.to_string()).collect();
+        write!(
+            f,
+            "{}: {} ({})",
+            self.ticker,
+            self.position,
+            issues.join(", ")
+        )
+    }
+}
+
+/// Check a short position.
+///
+/// ## Returns
+///
+/// The issues of the position, empty when it looks right.
+pub fn check_position(position: &ShortPosition, today: Date) -> Vec<QualityIssue> {
+    let mut issues = Vec::new();
+
+    if !(0.0..=100.0).contains(&position.weight) {
+        issues.push(QualityIssue::WeightOutOfRange);
+    }
+
+    if position.date.trim() != NO_DATE {
+        match parse_position_date(&position.date) {
+            Some(date) if date > today => issues.push(QualityIssue::FutureDate),
+            Some(_) => (),
+            None => issues.push(QualityIssue::InvalidDate),
+        }
+    }
+
+    if position.owner.trim().is_empty() {
+        issues.push(QualityIssue::EmptyOwner);
+    }
+
+    issues
+}
+
+/// Remove the suspicious positions of a company.
+///
+/// # Description
+///
+/// The total of the company is computed again using the accepted positions.
+///
+/// ## Returns
+///
+/// The accepted positions, and the rejected ones.
+pub fn validate_positions(
+    ticker: &Ticker,
+    mut positions: AliveShortPositions,
+    today: Date,
+) -> (AliveShortPositions, Vec<RejectedPosition>) {
+    let mut rejected = Vec::new();
+
+    positions.positions.retain(|position| {
+        let issues = check_position(position, today);
+
+        if issues.is_empty() {
+            true
+        } else {
+            rejected.push(RejectedPosition {
+                ticker: ticker.clone(),
+                position: position.clone(),
+                issues,
+            });
+            false
+        }
+    });
+
+    if !rejected.is_empty() {
+        positions.total = positions.positions.iter().map(|p| p.weight).sum();
+    }
+
+    (positions, rejected)
+}
+
+/// Build a report of the positions in quarantine, one per line.
+pub fn quality_report(rejected: &[RejectedPosition]) -> String {
+    rejected
+        .iter()
+        .map(|r| r.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn position(owner: &str, weight: f32, date: &str) -> ShortPosition {
+        ShortPosition {
+            owner: String::from(owner),
+            weight,
+            date: String::from(date),
+        }
+    }
+
+    #[rstest]
+    #[case(position("MARSHALL WACE LLP", 0.8, "12/06/2024"), vec![])]
+    #[case(position("MARSHALL WACE LLP", 0.8, "-"), vec![])]
+    #[case(position("MARSHALL WACE LLP", 0.8, "2024-06-01"), vec![])]
+    #[case(position("MARSHALL WACE LLP", 120.0, "12/06/2024"), vec![QualityIssue::WeightOutOfRange])]
+    #[case(position("MARSHALL WACE LLP", -0.5, "12/06/2024"), vec![QualityIssue::WeightOutOfRange])]
+    #[case(position("MARSHALL WACE LLP", f32::NAN, "12/06/2024"), vec![QualityIssue::WeightOutOfRange])]
+    #[case(position("MARSHALL WACE LLP", 0.8, "13/06/2024"), vec![QualityIssue::FutureDate])]
+    #[case(position("MARSHALL WACE LLP", 0.8, "yesterday"), vec![QualityIssue::InvalidDate])]
+    #[case(position(" ", 200.0, "12/06/2024"), vec![QualityIssue::WeightOutOfRange, QualityIssue::EmptyOwner])]
+    fn check(#[case] position: ShortPosition, #[case] expected: Vec<QualityIssue>) {
+        assert_eq!(check_position(&position, Date::new(2024, 6, 12)), expected);
+    }
+
+    #[rstest]
+    fn quarantine() {
+        let ticker = Ticker::new("GRF").unwrap();
+        let positions = AliveShortPositions {
+            total: 2.5,
+            positions: vec![
+                position("MARSHALL WACE LLP", 0.5, "10/06/2024"),
+                position("", 0.75, "11/06/2024"),
+                position("AQR CAPITAL", 1.25, "12/06/2024"),
+            ],
+            ..Default::default()
+        };
+
+        let (accepted, rejected) = validate_positions(&ticker, positions, Date::new(2024, 6, 12));
+
+        assert_eq!(accepted.positions.len(), 2);
+        assert_eq!(accepted.total, 1.75);
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(
+            quality_report(&rejected),
+            "GRF:  - 0.75 (11/06/2024) (empty owner)"
+        );
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! scrape_coordinator.rs
+//!
+//! Module that coordinates all the outbound requests to external web pages, so the bot
+//! behaves as a polite client regardless of the number of concurrent users.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+/// Default minimum delay between two requests to the same domain.
+const DEFAULT_DELAY: Duration = Duration::from_millis(1000);
+
+/// Default maximum number of requests waiting for their turn.
+const DEFAULT_MAX_QUEUE: usize = 64;
+
+/// Serializer and rate limiter of the requests to external domains.
+///
+/// # Description
+///
+/// Each domain has its own queue: requests to the same domain are sent one at a time,
+/// in arrival order, and at least a configured delay is kept between two consecutive
+/// requests. Requests to different domains don't block each other.
+///
+/// The amount of waiting requests is capped, when the cap is reached new requests are
+/// rejected with [CoordinatorError::QueueFull] rather than piling up.
+///
+/// A single instance shall be shared (using an [Arc]) by all the providers.
+pub struct ScrapeCoordinator {
+    /// Delay applied to domains without a specific setting.
+    default_delay: Duration,
+    /// Per-domain delays.
+    delays: HashMap<String, Duration>,
+    /// Maximum number of waiting requests (all domains).
+    max_queue: usize,
+    /// Number of requests waiting for their turn.
+    queued: AtomicUsize,
+    /// Time of the last request to each domain.
+    domains: Mutex<HashMap<String, Arc<AsyncMutex<Option<Instant>>>>>,
+}
+
+/// Place in the queue of waiting requests, released when dropped, e.g. when the future
+/// of the request is cancelled while it waits.
+struct QueueSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Permission to send a request to a domain.
+///
+/// The next request to the same domain is hold until this object is dropped.
+pub struct ScrapePermit {
+    _guard: OwnedMutexGuard<Option<Instant>>,
+}
+
+impl Default for ScrapeCoordinator {
+    /// Default implementation delegates to [ScrapeCoordinator::new] with the default values.
+    fn default() -> Self {
+        Self::new(DEFAULT_DELAY, HashMap::new(), DEFAULT_MAX_QUEUE)
+    }
+}
+
+impl ScrapeCoordinator {
+    /// Class constructor.
+    ///
+    /// ## Arguments
+    ///
+    /// - _default_delay_: minimum time between two requests to the same domain.
+    /// - _delays_: specific delays for some domains, e.g. `www.cnmv.es`.
+    /// - _max_queue_: maximum number of requests waiting for their turn.
+    pub fn new(
+        default_delay: Duration,
+        delays: HashMap<String, Duration>,
+        max_queue: usize,
+    ) -> ScrapeCoordinator {
+        ScrapeCoordinator {
+            default_delay,
+            delays,
+            max_queue,
+            queued: AtomicUsize::new(0),
+            domains: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait for the turn to send a request to `url`.
+    ///
+    /// # Description
+    ///
+    /// The returned [ScrapePermit] shall be kept alive until the response is received.
+    pub async fn acquire(&self, url: &str) -> Result<ScrapePermit, CoordinatorError> {
+        let domain = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .ok_or_else(|| CoordinatorError::InvalidUrl(String::from(url)))?;
+
+        let slot = {
+            let mut domains = self.domains.lock().unwrap();
+            Arc::clone(domains.entry(domain.clone()).or_default())
+        };
+
+        let queued = self.queued.fetch_add(1, Ordering::SeqCst);
+        let queue_slot = QueueSlot(&self.queued);
+        if queued >= self.max_queue {
+            warn!("Too many requests waiting for {domain}, rejecting the request");
+            return Err(CoordinatorError::QueueFull(domain));
+        }
+
+        // Tokio's mutex is fair, thus requests get their turn in arrival order.
+        let mut last_request = slot.lock_owned().await;
+        drop(queue_slot);
+
+        let delay = self
+            .delays
+            .get(&domain)
+            .copied()
+            .unwrap_or(self.default_delay);
+        if let Some(last) = *last_request {
+            let next = last + delay;
+            if next > Instant::now() {
+                debug!(
+                    "Waiting {:?} before requesting {domain}",
+                    next - Instant::now()
+                );
+                tokio::time::sleep_until(next).await;
+            }
+        }
+        *last_request = Some(Instant::now());
+
+        Ok(ScrapePermit {
+            _guard: last_request,
+        })
+    }
+}
+
+/// Error types for the coordinator.
+#[derive(Debug, Error)]
+pub enum CoordinatorError {
+    /// There are too many requests waiting for the domain.
+    #[error("too many requests waiting for {0}")]
+    QueueFull(String),
+    /// The URL has no domain.
+    #[error("the URL {0} is not valid")]
+    InvalidUrl(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[rstest]
+    fn delay_between_requests() {
+        let coordinator = Arc::new(ScrapeCoordinator::new(
+            Duration::from_millis(10),
+            HashMap::from([(String::from("www.cnmv.es"), Duration::from_millis(100))]),
+            8,
+        ));
+
+        runtime().block_on(async {
+            let start = Instant::now();
+            drop(coordinator.acquire("https://www.cnmv.es/a").await.unwrap());
+            drop(coordinator.acquire("https://www.cnmv.es/b").await.unwrap());
+            drop(coordinator.acquire("https://www.cnmv.es/c").await.unwrap());
+
+            assert!(start.elapsed() >= Duration::from_millis(200));
+        })
+    }
+
+    #[rstest]
+    fn domains_are_independent() {
+        let coordinator = ScrapeCoordinator::new(Duration::from_secs(60), HashMap::new(), 8);
+
+        runtime().block_on(async {
+            let start = Instant::now();
+            let _a = coordinator.acquire("https://www.cnmv.es/a").await.unwrap();
+            let _b = coordinator
+                .acquire("https://www.esma.europa.eu/b")
+                .await
+                .unwrap();
+
+            assert!(start.elapsed() < Duration::from_secs(1));
+        })
+    }
+
+    #[rstest]
+    fn full_queue() {
+        let coordinator = Arc::new(ScrapeCoordinator::new(
+            Duration::from_millis(10),
+            HashMap::new(),
+            1,
+        ));
+
+        runtime().block_on(async {
+            // Hold the turn of the domain, so the next request has to wait.
+            let permit = coordinator.acquire("https://www.cnmv.es/a").await.unwrap();
+
+            let waiting = tokio::spawn({
+                let coordinator = Arc::clone(&coordinator);
+                async move { coordinator.acquire("https://www.cnmv.es/b").await.is_ok() }
+            });
+            tokio::time::sleep(Duration::from_millis(50)).await;
+
+            assert!(matches!(
+                coordinator.acquire("https://www.cnmv.es/c").await,
+                Err(CoordinatorError::QueueFull(_))
+            ));
+
+            drop(permit);
+            assert!(waiting.await.unwrap());
+        })
+    }
+
+    #[rstest]
+    fn cancelled_requests_leave_the_queue() {
+        let coordinator = ScrapeCoordinator::new(Duration::from_millis(10), HashMap::new(), 1);
+
+        runtime().block_on(async {
+            let permit = coordinator.acquire("https://www.cnmv.es/a").await.unwrap();
+
+            // Requests that give up waiting don't keep their place in the queue.
+            for _ in 0..3 {
+                let waiting = coordinator.acquire("https://www.cnmv.es/b");
+                assert!(tokio::time::timeout(Duration::from_millis(20), waiting)
+                    .await
+                    .is_err());
+            }
+
+            drop(permit);
+            assert!(coordinator.acquire("https://www.cnmv.es/c").await.is_ok());
+        })
+    }
+
+    #[rstest]
+    fn invalid_url() {
+        let coordinator = ScrapeCoordinator::default();
+
+        assert!(matches!(
+            runtime().block_on(coordinator.acquire("./data/registry.csv")),
+            Err(CoordinatorError::InvalidUrl(_))
+        ));
+    }
+}
@@ -0,0 +1,774 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! short_cache.rs
+//!
+//! Module that keeps the latest short positions of the companies in memory.
+//!
+//! # Description
+//!
+//! The data providers are slow: every request to the CNMV's web page waits for its
+//! turn in the [ScrapeCoordinator][super::ScrapeCoordinator]. Short positions are
+//! published once per day, so the answers can be reused for a while. Moreover, some
+//! queries need the data of every company of the index (e.g. aggregations per sector),
+//! which is only affordable when such data is refreshed in the background.
+
+use crate::clock::{Clock, SystemClock};
+use crate::finance::{quality_report, validate_positions, RejectedPosition};
+use crate::finance::{AliveShortPositions, Ibex35Market, IbexCompany, OwnerAliases};
+use crate::finance::{ProviderChain, ProviderError, Ticker};
+use crate::ops_alerts::{OpsAlerts, Severity};
+use crate::watchdog::Heartbeat;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Key used for the companies whose sector is unknown.
+pub const UNKNOWN_SECTOR: &str = "other";
+
+/// Cached short positions of a company.
+struct CacheEntry {
+    fetched: Instant,
+    positions: Arc<AliveShortPositions>,
+}
+
+/// In-memory cache of the alive short positions of the companies.
+///
+/// # Description
+///
+/// Entries are identified by the ticker of the company. An entry older than the
+/// configured time to live is fetched again from the [ProviderChain] when requested.
+///
+/// The positions given by the providers are checked before being cached, and the
+/// suspicious ones are kept apart in a quarantine (see [quarantine][ShortCache::quarantine]).
+pub struct ShortCache {
+    providers: Arc<ProviderChain>,
+    ttl: Duration,
+    entries: RwLock<HashMap<Ticker, CacheEntry>>,
+    quarantine: RwLock<HashMap<Ticker, Vec<RejectedPosition>>>,
+    last_refresh: RwLock<Option<Instant>>,
+    clock: Arc<dyn Clock>,
+}
+
+/// Aggregated short interest of the companies of a sector.
+#[derive(Debug)]
+pub struct SectorSummary {
+    /// Key of the sector, e.g. `banking`.
+    pub sector: String,
+    /// Number of companies of the sector with data.
+    pub companies: usize,
+    /// Average of the total short position of the companies of the sector.
+    pub average: f32,
+    /// Ticker and total short position of the most shorted company of the sector.
+    pub most_shorted: (Ticker, f32),
+}
+
+/// Alive short positions of a fund across the companies of the market.
+#[derive(Debug, PartialEq)]
+pub struct FundSummary {
+    /// Canonical name of the fund.
+    pub name: String,
+    /// Key that groups all the names of the fund, see [owner_key][super::owner_key].
+    pub key: String,
+    /// Ticker of the companies and weight of the position, sorted by weight (highest first).
+    pub positions: Vec<(Ticker, f32)>,
+}
+
+impl FundSummary {
+    /// Get the summation of the weights of all the positions of the fund.
+    pub fn total_weight(&self) -> f32 {
+        self.positions.iter().map(|(_, weight)| weight).sum()
+    }
+}
+
+/// Position of a company in the index when sorted by short interest.
+#[derive(Debug, PartialEq)]
+pub struct Ranking {
+    /// Position of the company, starting at 1 for the most shorted one.
+    pub position: usize,
+    /// Number of companies with data.
+    pub companies: usize,
+    /// Percentage of the companies that have a lower short interest.
+    pub percentile: u8,
+}
+
+impl ShortCache {
+    /// Class constructor.
+    ///
+    /// ## Arguments
+    ///
+    /// - _providers_: the chain of providers used to fetch missing or stale entries.
+    /// - _ttl_: time after which an entry is considered stale.
+    pub fn new(providers: Arc<ProviderChain>, ttl: Duration) -> ShortCache {
+        ShortCache::with_clock(providers, ttl, Arc::new(SystemClock))
+    }
+
+    /// Class constructor that takes the [Clock] used to check the age of the entries.
+    pub fn with_clock(
+        providers: Arc<ProviderChain>,
+        ttl: Duration,
+        clock: Arc<dyn Clock>,
+    ) -> ShortCache {
+        ShortCache {
+            providers,
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+            quarantine: RwLock::new(HashMap::new()),
+            last_refresh: RwLock::new(None),
+            clock,
+        }
+    }
+
+    /// Get the [Clock] of the cache.
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Method that checks alive short positions of a stock.
+    ///
+    /// # Description
+    ///
+    /// The cached entry is returned when it is fresh, otherwise the providers are
+    /// requested and the entry is updated.
+    pub async fn short_positions(
+        &self,
+        stock: &IbexCompany,
+    ) -> Result<Arc<AliveShortPositions>, ProviderError> {
+        if let Some(positions) = self.fresh_entry(stock.ticker()) {
+            debug!("Cache hit for {}", stock.ticker());
+            return Ok(positions);
+        }
+
+        debug!("Cache miss for {}", stock.ticker());
+        self.fetch(stock).await
+    }
+
+    /// Fetch the short positions of every company of the market.
+    ///
+    /// # Description
+    ///
+    /// Companies whose providers fail keep their previous entry, if any.
+    ///
+    /// ## Returns
+    ///
+    /// The number of companies that were updated.
+    pub async fn refresh(&self, market: &Ibex35Market) -> usize {
+        let mut updated = 0;
+
+        for stock in market.get_companies() {
+            match self.fetch(stock).await {
+                Ok(_) => updated += 1,
+                Err(e) => warn!("Failed to refresh the short positions of {stock}: {e}"),
+            }
+        }
+
+        info!("Refreshed the short positions of {updated} companies");
+        *self
+            .last_refresh
+            .write()
+            .expect("Poisoned lock of the short cache") = Some(self.clock.now());
+
+        updated
+    }
+
+    /// Refresh the cache every `period`, forever.
+    ///
+    /// # Description
+    ///
+    /// This method is meant to be spawned as a background task at startup. An alert is
+    /// raised when none of the companies could be refreshed, when the providers answer
+    /// again, and when the positions in quarantine change. The `heartbeat` beats after
+    /// every refresh.
+    pub async fn refresh_periodically(
+        self: Arc<Self>,
+        market: Arc<Ibex35Market>,
+        period: Duration,
+        alerts: Arc<OpsAlerts>,
+        heartbeat: Heartbeat,
+    ) {
+        let mut interval = tokio::time::interval(period);
+        let companies = market.get_companies().len();
+        let mut down = false;
+        let mut quarantined = Vec::new();
+
+        loop {
+            interval.tick().await;
+            let updated = self.refresh(&market).await;
+            heartbeat.beat();
+
+            let rejected = self.quarantine();
+            if !rejected.is_empty() && rejected != quarantined {
+                alerts.alert(
+                    Severity::Warning,
+                    "data-quality",
+                    &format!(
+                        "{} suspicious short positions are in quarantine:\n{}",
+                        rejected.len(),
+                        quality_report(&rejected)
+                    ),
+                );
+            }
+            quarantined = rejected;
+
+            if updated == 0 && companies > 0 {
+                down = true;
+                alerts.alert(
+                    Severity::Critical,
+                    "providers-down",
+                    &format!(
+                        "No data provider answered for the {companies} companies of the Ibex35"
+                    ),
+                );
+            } else if down {
+                down = false;
+                alerts.alert(
+                    Severity::Info,
+                    "providers-up",
+                    &format!("The data providers answer again ({updated}/{companies} companies refreshed)"),
+                );
+            }
+        }
+    }
+
+    /// Get the short positions in quarantine, sorted by ticker.
+    ///
+    /// # Description
+    ///
+    /// The quarantine of a company is replaced every time its positions are fetched, so
+    /// only the rejected positions of the latest answer of the providers are kept.
+    pub fn quarantine(&self) -> Vec<RejectedPosition> {
+        let quarantine = self
+            .quarantine
+            .read()
+            .expect("Poisoned lock of the short cache");
+        let mut tickers: Vec<&Ticker> = quarantine.keys().collect();
+        tickers.sort();
+
+        tickers
+            .into_iter()
+            .flat_map(|ticker| quarantine[ticker].iter().cloned())
+            .collect()
+    }
+
+    /// Get the moment of the last refresh of the whole market, if any.
+    pub fn last_refresh(&self) -> Option<Instant> {
+        *self
+            .last_refresh
+            .read()
+            .expect("Poisoned lock of the short cache")
+    }
+
+    /// Get the latest known short positions of every company, regardless of their age.
+    pub fn snapshot(&self) -> HashMap<Ticker, Arc<AliveShortPositions>> {
+        self.entries
+            .read()
+            .expect("Poisoned lock of the short cache")
+            .iter()
+            .map(|(ticker, entry)| (ticker.clone(), Arc::clone(&entry.positions)))
+            .collect()
+    }
+
+    /// Aggregate the latest known short positions per sector.
+    ///
+    /// ## Returns
+    ///
+    /// A summary per sector, sorted by the average short position (highest first).
+    /// Sectors without data are not included.
+    pub fn sectors(&self, market: &Ibex35Market) -> Vec<SectorSummary> {
+        summarize_sectors(market, &self.snapshot())
+    }
+
+    /// Group the latest known short positions per fund.
+    ///
+    /// # Description
+    ///
+    /// The names of the owners are resolved using the given aliases, so the positions
+    /// of a fund are grouped regardless of how its name was written.
+    ///
+    /// ## Returns
+    ///
+    /// A summary per fund, sorted by the number of positions (highest first).
+    pub fn funds(&self, market: &Ibex35Market, aliases: &OwnerAliases) -> Vec<FundSummary> {
+        summarize_funds(market, &self.snapshot(), aliases)
+    }
+
+    /// Get the position of a company in the index when sorted by short interest.
+    ///
+    /// # Description
+    ///
+    /// The ranking is computed using the latest known short positions of the companies.
+    /// Companies with the same short interest share the same position. Companies
+    /// outside the market are not considered.
+    ///
+    /// ## Returns
+    ///
+    /// `None` when there is no data for the company.
+    pub fn ranking(&self, market: &Ibex35Market, ticker: &Ticker) -> Option<Ranking> {
+        let mut snapshot = self.snapshot();
+        snapshot.retain(|ticker, _| market.stock_by_ticker(ticker.as_str()).is_some());
+
+        rank(ticker, &snapshot)
+    }
+
+    fn fresh_entry(&self, ticker: &Ticker) -> Option<Arc<AliveShortPositions>> {
+        self.entries
+            .read()
+            .expect("Poisoned lock of the short cache")
+            .get(ticker)
+            .filter(|entry| self.clock.now().duration_since(entry.fetched) < self.ttl)
+            .map(|entry| Arc::clone(&entry.positions))
+    }
+
+    async fn fetch(&self, stock: &IbexCompany) -> Result<Arc<AliveShortPositions>, ProviderError> {
+        let (mut positions, rejected) = validate_positions(
+            stock.ticker(),
+            self.providers.short_positions(stock).await?,
+            self.clock.today(),
+        );
+        let duplicated = positions.dedup_owners();
+        if duplicated > 0 {
+            warn!("Removed {duplicated} duplicated short positions of {stock}");
+        }
+        let positions = Arc::new(positions);
+
+        let mut quarantine = self
+            .quarantine
+            .write()
+            .expect("Poisoned lock of the short cache");
+        if rejected.is_empty() {
+            quarantine.remove(stock.ticker());
+        } else {
+            for r in &rejected {
+                warn!("Short position put in quarantine: {r}");
+            }
+            quarantine.insert(stock.ticker().clone(), rejected);
+        }
+        drop(quarantine);
+
+        self.entries
+            .write()
+            .expect("Poisoned lock of the short cache")
+            .insert(
+                stock.ticker().clone(),
+                CacheEntry {
+                    fetched: self.clock.now(),
+                    positions: Arc::clone(&positions),
+                },
+            );
+
+        Ok(positions)
+    }
+}
+
+fn summarize_sectors(
+    market: &Ibex35Market,
+    snapshot: &HashMap<Ticker, Arc<AliveShortPositions>>,
+) -> Vec<SectorSummary> {
+    let mut sectors: HashMap<&str, SectorSummary> = HashMap::new();
+
+    for stock in market.get_companies() {
+        let Some(positions) = snapshot.get(stock.ticker()) else {
+            continue;
+        };
+        let sector = stock.sector().unwrap_or(UNKNOWN_SECTOR);
+
+        let summary = sectors.entry(sector).or_insert_with(|| SectorSummary {
+            sector: String::from(sector),
+            companies: 0,
+            average: 0.0,
+            most_shorted: (stock.ticker().clone(), positions.total),
+        });
+
+        // The average is accumulated as a summation until all the companies are seen.
+        summary.companies += 1;
+        summary.average += positions.total;
+        if positions.total > summary.most_shorted.1 {
+            summary.most_shorted = (stock.ticker().clone(), positions.total);
+        }
+    }
+
+    let mut sectors: Vec<SectorSummary> = sectors
+        .into_values()
+        .map(|mut summary| {
+            summary.average /= summary.companies as f32;
+            summary
+        })
+        .collect();
+
+    sectors.sort_by(|a, b| b.average.total_cmp(&a.average));
+
+    sectors
+}
+
+fn summarize_funds(
+    market: &Ibex35Market,
+    snapshot: &HashMap<Ticker, Arc<AliveShortPositions>>,
+    aliases: &OwnerAliases,
+) -> Vec<FundSummary> {
+    let mut funds: HashMap<String, FundSummary> = HashMap::new();
+
+    for stock in market.get_companies() {
+        let Some(positions) = snapshot.get(stock.ticker()) else {
+            continue;
+        };
+
+        for position in &positions.positions {
+            let key = aliases.key(&position.owner);
+            let fund = funds.entry(key.clone()).or_insert_with(|| FundSummary {
+                name: aliases.canonical_name(&position.owner),
+                key,
+                positions: Vec::new(),
+            });
+            fund.positions
+                .push((stock.ticker().clone(), position.weight));
+        }
+    }
+
+    let mut funds: Vec<FundSummary> = funds
+        .into_values()
+        .map(|mut fund| {
+            fund.positions
+                .sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+            fund
+        })
+        .collect();
+
+    funds.sort_by(|a, b| {
+        b.positions
+            .len()
+            .cmp(&a.positions.len())
+            .then(a.name.cmp(&b.name))
+    });
+
+    funds
+}
+
+fn rank(ticker: &Ticker, snapshot: &HashMap<Ticker, Arc<AliveShortPositions>>) -> Option<Ranking> {
+    let total = snapshot.get(ticker)?.total;
+
+    let higher = snapshot.values().filter(|p| p.total > total).count();
+    let lower = snapshot.values().filter(|p| p.total < total).count();
+
+    Some(Ranking {
+        position: higher + 1,
+        companies: snapshot.len(),
+        percentile: (100 * lower / snapshot.len()) as u8,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::finance::{QualityIssue, RegistryProvider, ShortPosition, ShortProvider};
+    use date::Date;
+    use pretty_assertions::assert_eq;
+    use rstest::{fixture, rstest};
+
+    #[fixture]
+    fn market() -> Ibex35Market {
+        let companies = [
+            IbexCompany::new(
+                None,
+                "GRIFOLS",
+                Ticker::new("GRF").unwrap(),
+                "ES0171996087",
+                None,
+            )
+            .with_sector("healthcare"),
+            IbexCompany::new(
+                None,
+                "ROVI",
+                Ticker::new("ROVI").unwrap(),
+                "ES0157261019",
+                None,
+            )
+            .with_sector("healthcare"),
+            IbexCompany::new(
+                None,
+                "BANCO SANTANDER",
+                Ticker::new("SAN").unwrap(),
+                "ES0113900J37",
+                None,
+            )
+            .with_sector("banking"),
+            IbexCompany::new(
+                None,
+                "BBVA",
+                Ticker::new("BBVA").unwrap(),
+                "ES0113211835",
+                None,
+            )
+            .with_sector("banking"),
+            IbexCompany::new(
+                None,
+                "SOLARIA",
+                Ticker::new("SLR").unwrap(),
+                "ES0165386014",
+                None,
+            ),
+        ];
+
+        Ibex35Market::new(
+            companies
+                .into_iter()
+                .map(|c| (c.ticker().clone(), c))
+                .collect(),
+        )
+    }
+
+    #[fixture]
+    fn clock() -> Arc<ManualClock> {
+        Arc::new(ManualClock::new(Date::new(2024, 6, 12)))
+    }
+
+    #[fixture]
+    fn cache(clock: Arc<ManualClock>) -> ShortCache {
+        let registry = RegistryProvider::new(&format!(
+            "{}/tests/fixtures/registry/esma.csv",
+            env!("CARGO_MANIFEST_DIR")
+        ));
+
+        ShortCache::with_clock(
+            Arc::new(ProviderChain::new(vec![ShortProvider::Registry(registry)])),
+            Duration::from_secs(60),
+            clock,
+        )
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    fn positions(total: f32) -> Arc<AliveShortPositions> {
+        Arc::new(AliveShortPositions {
+            total,
+            ..Default::default()
+        })
+    }
+
+    #[rstest]
+    fn cached_positions(cache: ShortCache, market: Ibex35Market) {
+        let grifols = market.stock_by_ticker("GRF").unwrap();
+
+        let first = runtime().block_on(cache.short_positions(grifols)).unwrap();
+        let second = runtime().block_on(cache.short_positions(grifols)).unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.positions.len(), 2);
+    }
+
+    #[rstest]
+    fn stale_positions(clock: Arc<ManualClock>, market: Ibex35Market) {
+        let cache = cache(Arc::clone(&clock));
+        let grifols = market.stock_by_ticker("GRF").unwrap();
+
+        let first = runtime().block_on(cache.short_positions(grifols)).unwrap();
+        clock.advance(Duration::from_secs(59));
+        let second = runtime().block_on(cache.short_positions(grifols)).unwrap();
+        clock.advance(Duration::from_secs(1));
+        let third = runtime().block_on(cache.short_positions(grifols)).unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&second, &third));
+    }
+
+    #[rstest]
+    fn quarantined_positions(clock: Arc<ManualClock>, market: Ibex35Market) {
+        // The latest position of Marshall Wace in Grifols is dated tomorrow.
+        clock.set_today(Date::new(2024, 6, 11));
+        let cache = cache(Arc::clone(&clock));
+        let grifols = market.stock_by_ticker("GRF").unwrap();
+
+        let positions = runtime().block_on(cache.short_positions(grifols)).unwrap();
+
+        assert_eq!(positions.positions.len(), 1);
+        assert_eq!(positions.total, 0.65);
+        let quarantine = cache.quarantine();
+        assert_eq!(quarantine.len(), 1);
+        assert_eq!(quarantine[0].position.owner, "MARSHALL WACE LLP");
+        assert_eq!(quarantine[0].issues, [QualityIssue::FutureDate]);
+
+        // The quarantine is cleared when the provider answers right.
+        clock.set_today(Date::new(2024, 6, 12));
+        clock.advance(Duration::from_secs(60));
+        runtime().block_on(cache.short_positions(grifols)).unwrap();
+
+        assert!(cache.quarantine().is_empty());
+    }
+
+    fn short(owner: &str, weight: f32, date: &str) -> ShortPosition {
+        ShortPosition {
+            owner: String::from(owner),
+            weight,
+            date: String::from(date),
+        }
+    }
+
+    #[rstest]
+    // A harvest published twice.
+    #[case(
+        vec![short("MARSHALL WACE LLP", 0.5, "10/06/2024"), short("MARSHALL WACE LLP", 0.5, "10/06/2024")],
+        vec![0.5],
+    )]
+    // Only the latest notification of the owner counts, whatever its name looks like.
+    #[case(
+        vec![short("Marshall Wace, LLP", 0.75, "03/06/2024"), short("AQR CAPITAL", 0.5, "04/06/2024"), short("MARSHALL WACE LLP", 1.25, "10/06/2024")],
+        vec![0.5, 1.25],
+    )]
+    #[case(
+        vec![short("MARSHALL WACE LLP", 1.25, "10/06/2024"), short("MARSHALL WACE LLP", 0.75, "03/06/2024")],
+        vec![1.25],
+    )]
+    // Without dates, the first one is kept.
+    #[case(
+        vec![short("MARSHALL WACE LLP", 0.5, "-"), short("MARSHALL WACE LLP", 0.75, "-")],
+        vec![0.5],
+    )]
+    #[case(
+        vec![short("MARSHALL WACE LLP", 0.5, "10/06/2024"), short("AQR CAPITAL", 0.75, "10/06/2024")],
+        vec![0.5, 0.75],
+    )]
+    fn dedup_positions(#[case] rows: Vec<ShortPosition>, #[case] expected: Vec<f32>) {
+        let mut positions = AliveShortPositions {
+            total: rows.iter().map(|p| p.weight).sum(),
+            positions: rows,
+            ..Default::default()
+        };
+        let count = positions.positions.len();
+
+        let removed = positions.dedup_owners();
+
+        assert_eq!(removed, count - expected.len());
+        assert_eq!(
+            positions
+                .positions
+                .iter()
+                .map(|p| p.weight)
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(positions.total, expected.iter().sum::<f32>());
+    }
+
+    #[rstest]
+    fn refresh_market(cache: ShortCache, market: Ibex35Market) {
+        let updated = runtime().block_on(cache.refresh(&market));
+
+        assert_eq!(updated, market.get_companies().len());
+        assert_eq!(cache.last_refresh(), Some(cache.clock().now()));
+        assert_eq!(cache.snapshot().len(), market.get_companies().len());
+    }
+
+    #[rstest]
+    #[case("SLR", Some((1, 75)))]
+    #[case("GRF", Some((2, 50)))]
+    #[case("ROVI", Some((3, 0)))]
+    #[case("SAN", Some((3, 0)))]
+    #[case("BBVA", None)]
+    fn company_ranking(#[case] ticker: &str, #[case] expected: Option<(usize, u8)>) {
+        let snapshot = HashMap::from([
+            (Ticker::new("GRF").unwrap(), positions(2.25)),
+            (Ticker::new("ROVI").unwrap(), positions(0.5)),
+            (Ticker::new("SAN").unwrap(), positions(0.5)),
+            (Ticker::new("SLR").unwrap(), positions(4.0)),
+        ]);
+
+        let expected = expected.map(|(position, percentile)| Ranking {
+            position,
+            companies: 4,
+            percentile,
+        });
+
+        assert_eq!(rank(&Ticker::new(ticker).unwrap(), &snapshot), expected);
+    }
+
+    #[rstest]
+    fn sector_summary(market: Ibex35Market) {
+        let snapshot = HashMap::from([
+            (Ticker::new("GRF").unwrap(), positions(2.25)),
+            (Ticker::new("ROVI").unwrap(), positions(0.75)),
+            (Ticker::new("SAN").unwrap(), positions(0.5)),
+            (Ticker::new("SLR").unwrap(), positions(4.0)),
+        ]);
+
+        let sectors = summarize_sectors(&market, &snapshot);
+
+        assert_eq!(sectors.len(), 3);
+        assert_eq!(sectors[0].sector, UNKNOWN_SECTOR);
+        assert_eq!(sectors[1].sector, "healthcare");
+        assert_eq!(sectors[1].companies, 2);
+        assert_eq!(sectors[1].average, 1.5);
+        assert_eq!(sectors[1].most_shorted, (Ticker::new("GRF").unwrap(), 2.25));
+        // BBVA has no data, so it doesn't count for the average.
+        assert_eq!(sectors[2].sector, "banking");
+        assert_eq!(sectors[2].companies, 1);
+    }
+
+    #[rstest]
+    fn fund_summary(market: Ibex35Market) {
+        let with_owners = |owners: &[(&str, f32)]| {
+            Arc::new(AliveShortPositions {
+                total: owners.iter().map(|(_, weight)| weight).sum(),
+                positions: owners
+                    .iter()
+                    .map(|(owner, weight)| ShortPosition {
+                        owner: String::from(*owner),
+                        weight: *weight,
+                        date: String::from("12/06/2024"),
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+        };
+        let snapshot = HashMap::from([
+            (
+                Ticker::new("GRF").unwrap(),
+                with_owners(&[("MARSHALL WACE LLP", 1.5), ("AQR Capital", 0.75)]),
+            ),
+            (
+                Ticker::new("SAN").unwrap(),
+                with_owners(&[("Marshall Wace, LLP", 0.5)]),
+            ),
+            (
+                Ticker::new("SLR").unwrap(),
+                with_owners(&[("MARSHALL WACE", 0.6)]),
+            ),
+        ]);
+        let aliases = OwnerAliases::new(HashMap::from([(
+            String::from("Marshall Wace LLP"),
+            vec![String::from("MARSHALL WACE")],
+        )]))
+        .unwrap();
+
+        let funds = summarize_funds(&market, &snapshot, &aliases);
+
+        assert_eq!(funds.len(), 2);
+        assert_eq!(funds[0].name, "Marshall Wace LLP");
+        assert_eq!(
+            funds[0].positions,
+            vec![
+                (Ticker::new("GRF").unwrap(), 1.5),
+                (Ticker::new("SLR").unwrap(), 0.6),
+                (Ticker::new("SAN").unwrap(), 0.5)
+            ]
+        );
+        assert!((funds[0].total_weight() - 2.6).abs() < 1e-6);
+        assert_eq!(funds[1].name, "AQR Capital");
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! ticker.rs
+//!
+//! Module that defines the identifier of the companies in the market, validated and
+//! normalized at the boundaries of the bot (listing files, commands and buttons).
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Maximum length of a ticker.
+const MAX_LEN: usize = 12;
+
+/// Error types for the parsing of tickers.
+#[derive(Debug, Error, PartialEq)]
+pub enum TickerError {
+    /// The ticker is empty.
+    #[error("the ticker is empty")]
+    Empty,
+    /// The ticker is too long.
+    #[error("the ticker {0} is longer than {MAX_LEN} characters")]
+    TooLong(String),
+    /// The ticker includes characters other than letters and digits.
+    #[error("the ticker {0} includes invalid characters")]
+    InvalidCharacters(String),
+}
+
+/// Identifier of a company in the market, e.g. `SAN`.
+///
+/// # Description
+///
+/// Tickers are made of ASCII letters and digits, and they are normalized to uppercase
+/// when parsed. So, tickers typed by the users (e.g. `san`) match the ones of the
+/// listing files.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Ticker(String);
+
+impl Ticker {
+    /// Parse a ticker.
+    ///
+    /// # Description
+    ///
+    /// The surrounding whitespace is ignored, and the letters are converted to
+    /// uppercase.
+    pub fn new(ticker: &str) -> Result<Ticker, TickerError> {
+        let ticker = ticker.trim();
+
+        if ticker.is_empty() {
+            return Err(TickerError::Empty);
+        }
+
+        if ticker.len() > MAX_LEN {
+            return Err(TickerError::TooLong(String::from(ticker)));
+        }
+
+        if !ticker.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(TickerError::InvalidCharacters(String::from(ticker)));
+        }
+
+        Ok(Ticker(ticker.to_ascii_uppercase()))
+    }
+
+    /// Get the ticker as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for Ticker {
+    type Err = TickerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ticker::new(s)
+    }
+}
+
+impl TryFrom<String> for Ticker {
+    type Error = TickerError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Ticker::new(&value)
+    }
+}
+
+impl fmt::Display for Ticker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for Ticker {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Ticker {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for Ticker {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Ticker {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("SAN", "SAN")]
+    #[case("san", "SAN")]
+    #[case(" A3m\n", "A3M")]
+    fn valid_tickers(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(Ticker::new(input).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("", TickerError::Empty)]
+    #[case("   ", TickerError::Empty)]
+    #[case("SANTANDERBANK", TickerError::TooLong(String::from("SANTANDERBANK")))]
+    #[case("SAN BBVA", TickerError::InvalidCharacters(String::from("SAN BBVA")))]
+    #[case("ÑAN", TickerError::InvalidCharacters(String::from("ÑAN")))]
+    fn invalid_tickers(#[case] input: &str, #[case] expected: TickerError) {
+        assert_eq!(Ticker::new(input), Err(expected));
+    }
+
+    #[rstest]
+    fn serde() {
+        let ticker: Ticker = serde_json::from_str("\"grf\"").unwrap();
+
+        assert_eq!(ticker, "GRF");
+        assert_eq!(serde_json::to_string(&ticker).unwrap(), "\"GRF\"");
+        assert!(serde_json::from_str::<Ticker>("\"G-RF\"").is_err());
+    }
+}
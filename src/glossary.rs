@@ -0,0 +1,204 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Glossary of short selling concepts.
+//!
+//! # Description
+//!
+//! The glossary is loaded from a TOML file in the data path. Each entry is identified
+//! by a key, and includes the name and the explanation of the concept in all the
+//! supported languages:
+//!
+//! ```toml
+//! [<key>]
+//! en.term = <Name of the concept in English>
+//! en.definition = <Explanation in English>
+//! es.term = <Name of the concept in Spanish>
+//! es.definition = <Explanation in Spanish>
+//! ```
+
+use crate::text::search_key;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use thiserror::Error;
+use tracing::info;
+
+/// Name of the data file that contains the glossary.
+pub const GLOSSARY_FILE: &str = "glossary.toml";
+
+/// Name and explanation of a concept in a language.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Definition {
+    pub term: String,
+    pub definition: String,
+}
+
+/// Entry of the glossary.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GlossaryEntry {
+    pub en: Definition,
+    pub es: Definition,
+}
+
+impl GlossaryEntry {
+    /// Get the definition of the concept in the language of the user.
+    ///
+    /// # Description
+    ///
+    /// English is used for unsupported languages.
+    pub fn localized(&self, lang_code: &str) -> &Definition {
+        match lang_code {
+            "es" => &self.es,
+            _ => &self.en,
+        }
+    }
+}
+
+/// Collection of concepts, sorted by their key.
+#[derive(Debug)]
+pub struct Glossary {
+    entries: BTreeMap<String, GlossaryEntry>,
+}
+
+/// Error types for the glossary loader.
+#[derive(Debug, Error)]
+pub enum GlossaryError {
+    /// The glossary file could not be read.
+    #[error("error opening the glossary file {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The content of the file is not valid.
+    #[error("could not parse the glossary file: {0}")]
+    Syntax(#[from] toml::de::Error),
+}
+
+impl Glossary {
+    /// Build a [Glossary] from a file.
+    ///
+    /// ## Arguments
+    ///
+    /// - _path_: a string that points to the TOML file.
+    pub fn load(path: &str) -> Result<Glossary, GlossaryError> {
+        info!("File {path} will be parsed to find glossary entries.");
+
+        let content = read_to_string(path).map_err(|e| GlossaryError::Io {
+            path: String::from(path),
+            source: e,
+        })?;
+
+        Ok(Glossary {
+            entries: toml::from_str(&content)?,
+        })
+    }
+
+    /// Get an entry of the glossary by its key.
+    pub fn entry(&self, key: &str) -> Option<&GlossaryEntry> {
+        self.entries.get(key)
+    }
+
+    /// Iterate over the entries of the glossary, sorted by their key.
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &GlossaryEntry)> {
+        self.entries.iter()
+    }
+
+    /// Search a concept in the glossary.
+    ///
+    /// # Description
+    ///
+    /// The search ignores the case, the accents and the extra whitespace, and it checks
+    /// the keys and the names of the concepts in all the languages. Exact matches are preferred over partial ones.
+    ///
+    /// ## Returns
+    ///
+    /// The key and the entry of the first match, or `None` when nothing matches.
+    pub fn search(&self, query: &str) -> Option<(&String, &GlossaryEntry)> {
+        let query = search_key(query).replace(' ', "_");
+
+        if query.is_empty() {
+            return None;
+        }
+
+        let names = |key: &str, entry: &GlossaryEntry| {
+            [
+                key.to_lowercase(),
+                search_key(&entry.en.term).replace(' ', "_"),
+                search_key(&entry.es.term).replace(' ', "_"),
+            ]
+        };
+
+        self.entries
+            .iter()
+            .find(|(key, entry)| names(key, entry).contains(&query))
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .find(|(key, entry)| names(key, entry).iter().any(|name| name.contains(&query)))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::{fixture, rstest};
+
+    #[fixture]
+    fn glossary() -> Glossary {
+        Glossary::load(&format!(
+            "{}/data/{GLOSSARY_FILE}",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .expect("Failed to load the glossary")
+    }
+
+    #[rstest]
+    fn load_glossary(glossary: Glossary) {
+        assert!(glossary.entries().count() > 0);
+        let entry = glossary.entry("isin").unwrap();
+        assert_eq!(entry.localized("es").term, "ISIN");
+        assert_eq!(entry.localized("fr").definition, entry.en.definition);
+    }
+
+    #[rstest]
+    #[case("isin", Some("isin"))]
+    #[case("ISIN", Some("isin"))]
+    #[case("net short position", Some("net_short_position"))]
+    #[case("posición corta neta", Some("net_short_position"))]
+    #[case(" POSICION  corta neta", Some("net_short_position"))]
+    #[case("umbral", Some("disclosure_threshold"))]
+    #[case("selling", Some("short_selling"))]
+    #[case("dividend", None)]
+    #[case("  ", None)]
+    fn search_term(glossary: Glossary, #[case] query: &str, #[case] key: Option<&str>) {
+        assert_eq!(
+            glossary.search(query).map(|(k, _)| k.as_str()),
+            key,
+            "query: {query}"
+        );
+    }
+
+    #[rstest]
+    fn missing_file() {
+        assert!(matches!(
+            Glossary::load("/nonexistent/glossary.toml"),
+            Err(GlossaryError::Io { .. })
+        ));
+    }
+}
@@ -23,7 +23,11 @@
 //! All valid combinations of Messages and States shall be contemplated in the implementation
 //! of this handler.
 
-use crate::{endpoints::*, CommandEng, CommandSpa, State};
+use crate::finance::{Ibex35Market, IssuerRegistry};
+use crate::intent::{short_intent, MIN_CONFIDENCE};
+use crate::{endpoints::*, telemetry::CorrelationId, CommandEng, CommandSpa, State};
+use crate::{AdminChat, AdminCommand};
+use std::sync::Arc;
 use teloxide::{
     dispatching::{dialogue, dialogue::InMemStorage, UpdateHandler},
     prelude::*,
@@ -37,28 +41,110 @@ pub fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'stat
         case![State::Start]
             .branch(case![CommandEng::Start].endpoint(start))
             .branch(case![CommandEng::Help].endpoint(help))
-            .branch(case![CommandEng::Short].endpoint(list_stocks))
-            .branch(case![CommandEng::Support].endpoint(support)),
+            .branch(
+                case![CommandEng::Short(args)]
+                    .branch(
+                        dptree::filter(|args: String| args.trim().is_empty()).endpoint(list_stocks),
+                    )
+                    .endpoint(short_lookup),
+            )
+            .branch(case![CommandEng::Again].endpoint(again))
+            .branch(case![CommandEng::Sectors].endpoint(sectors))
+            .branch(case![CommandEng::Exposure(args)].endpoint(exposure))
+            .branch(case![CommandEng::Glossary(term)].endpoint(glossary))
+            .branch(case![CommandEng::Fundstats(query)].endpoint(fund_stats))
+            .branch(case![CommandEng::History(args)].endpoint(history))
+            .branch(case![CommandEng::Support].endpoint(support))
+            .branch(case![CommandEng::About].endpoint(about)),
     );
 
     let command_handler_spa = teloxide::filter_command::<CommandSpa, _>().branch(
         case![State::Start]
             .branch(case![CommandSpa::Inicio].endpoint(start))
             .branch(case![CommandSpa::Ayuda].endpoint(help))
-            .branch(case![CommandSpa::Short].endpoint(list_stocks))
-            .branch(case![CommandSpa::Apoyo].endpoint(support)),
+            .branch(
+                case![CommandSpa::Short(args)]
+                    .branch(
+                        dptree::filter(|args: String| args.trim().is_empty()).endpoint(list_stocks),
+                    )
+                    .endpoint(short_lookup),
+            )
+            .branch(case![CommandSpa::Repetir].endpoint(again))
+            .branch(case![CommandSpa::Sectores].endpoint(sectors))
+            .branch(case![CommandSpa::Exposicion(args)].endpoint(exposure))
+            .branch(case![CommandSpa::Glosario(term)].endpoint(glossary))
+            .branch(case![CommandSpa::Fondo(query)].endpoint(fund_stats))
+            .branch(case![CommandSpa::Historico(args)].endpoint(history))
+            .branch(case![CommandSpa::Apoyo].endpoint(support))
+            .branch(case![CommandSpa::Acerca].endpoint(about)),
     );
 
+    // Commands of the administrators are ignored in other chats.
+    let admin_handler = teloxide::filter_command::<AdminCommand, _>()
+        .filter(|msg: Message, admin: AdminChat| admin.0 == Some(msg.chat.id))
+        .branch(case![AdminCommand::Trace(args)].endpoint(trace));
+
+    // When the user edits a command to fix a typo in its arguments, the lookup runs again.
+    let edited_handler_eng = teloxide::filter_command::<CommandEng, _>()
+        .branch(
+            case![CommandEng::Short(args)]
+                .filter(|args: String| !args.trim().is_empty())
+                .endpoint(short_lookup),
+        )
+        .branch(case![CommandEng::Exposure(args)].endpoint(exposure))
+        .branch(case![CommandEng::Glossary(term)].endpoint(glossary))
+        .branch(case![CommandEng::Fundstats(query)].endpoint(fund_stats))
+        .branch(case![CommandEng::History(args)].endpoint(history));
+
+    let edited_handler_spa = teloxide::filter_command::<CommandSpa, _>()
+        .branch(
+            case![CommandSpa::Short(args)]
+                .filter(|args: String| !args.trim().is_empty())
+                .endpoint(short_lookup),
+        )
+        .branch(case![CommandSpa::Exposicion(args)].endpoint(exposure))
+        .branch(case![CommandSpa::Glosario(term)].endpoint(glossary))
+        .branch(case![CommandSpa::Fondo(query)].endpoint(fund_stats))
+        .branch(case![CommandSpa::Historico(args)].endpoint(history));
+
+    // Plain messages such as "cortos santander" are handled as /short.
+    let intent_handler = dptree::filter_map(
+        |msg: Message, market: Arc<Ibex35Market>, issuers: Arc<IssuerRegistry>| {
+            short_intent(msg.text()?, &market, &issuers)
+                .filter(|intent| intent.confidence >= MIN_CONFIDENCE)
+                .map(|intent| intent.query)
+        },
+    )
+    .endpoint(short_lookup);
+
+    let edited_message_handler = Update::filter_edited_message()
+        .branch(edited_handler_eng)
+        .branch(edited_handler_spa)
+        .branch(intent_handler.clone());
+
     let message_handler = Update::filter_message()
+        .branch(admin_handler)
         .branch(command_handler_eng)
         .branch(command_handler_spa)
         .branch(case![State::ListStocks].endpoint(list_stocks))
+        .branch(intent_handler)
         .endpoint(default);
 
-    let query_handler =
-        Update::filter_callback_query().branch(case![State::ReceiveStock].endpoint(receive_stock));
+    // The buttons of the glossary work regardless of the state of the dialogue.
+    let query_handler = Update::filter_callback_query()
+        .branch(
+            dptree::filter(|q: CallbackQuery| {
+                q.data
+                    .as_deref()
+                    .is_some_and(|data| data.starts_with(GLOSSARY_CALLBACK))
+            })
+            .endpoint(glossary_term),
+        )
+        .branch(case![State::ReceiveStock].endpoint(receive_stock));
 
     dialogue::enter::<Update, InMemStorage<State>, State, _>()
+        .map(|update: Update| CorrelationId::new(&update))
         .branch(message_handler)
+        .branch(edited_message_handler)
         .branch(query_handler)
 }
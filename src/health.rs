@@ -0,0 +1,178 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Health of the ShortBot.
+//!
+//! # Description
+//!
+//! In webhook mode, the [axum] server answers [HEALTH_ROUTE] with `200 OK` while the bot
+//! is ready to receive updates, i.e. the webhook is registered in Telegram, and with
+//! `503 Service Unavailable` otherwise.
+//!
+//! Run the binary with [HEALTHCHECK_FLAG] to request that route to the local server and
+//! exit with a non-zero code when the bot is not ready. So, container images can declare
+//! a `HEALTHCHECK` without shipping other tools. In long polling mode there is no server,
+//! so the check asks Telegram for the bot instead, which fails when the API can't be
+//! reached or the token is rejected.
+
+use crate::configuration::Settings;
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use secrecy::ExposeSecret;
+use serde_json::{json, Value};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use teloxide::{requests::Requester, Bot};
+
+/// Route of the health check.
+pub const HEALTH_ROUTE: &str = "/healthz";
+
+/// Command line flag that checks the health of a running bot instead of starting one.
+pub const HEALTHCHECK_FLAG: &str = "--healthcheck";
+
+/// Time to wait for the answer of the local server.
+const HEALTHCHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether the bot is ready to receive updates.
+///
+/// # Description
+///
+/// Clones share the same state, and a new [Readiness] is not ready.
+#[derive(Clone, Debug, Default)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    /// Mark the bot as ready, or not.
+    pub fn set_ready(&self, ready: bool) {
+        self.0.store(ready, Ordering::Relaxed);
+    }
+
+    /// Check whether the bot is ready.
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Build the [Router] that serves the health check.
+pub fn router(readiness: Readiness) -> Router {
+    Router::new()
+        .route(HEALTH_ROUTE, get(health_handler))
+        .with_state(readiness)
+}
+
+async fn health_handler(State(readiness): State<Readiness>) -> (StatusCode, Json<Value>) {
+    if readiness.is_ready() {
+        (StatusCode::OK, Json(json!({"status": "ready"})))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({"status": "not ready"})),
+        )
+    }
+}
+
+/// Request the health check to the server of the local bot.
+///
+/// # Description
+///
+/// The address of the server is taken from the settings of the webhook mode. When it
+/// listens on all the interfaces, the loopback interface is used. In long polling mode,
+/// the bot is requested to the Telegram API instead.
+///
+/// ## Returns
+///
+/// `Err` with the reason when the bot is not ready, or it can't be reached.
+pub async fn healthcheck(settings: &Settings) -> Result<(), String> {
+    let Some(webhook) = settings.webhook.as_ref() else {
+        return polling_healthcheck(settings).await;
+    };
+    let address: SocketAddr = webhook
+        .address
+        .parse()
+        .map_err(|e| format!("wrong address {}: {e}", webhook.address))?;
+
+    let response = reqwest::Client::new()
+        .get(health_url(address))
+        .timeout(HEALTHCHECK_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("the bot answered {}", response.status()))
+    }
+}
+
+/// Check that the Telegram API accepts the token of the bot.
+async fn polling_healthcheck(settings: &Settings) -> Result<(), String> {
+    let bot = Bot::new(settings.application.api_token.expose_secret());
+
+    match tokio::time::timeout(HEALTHCHECK_TIMEOUT, bot.get_me()).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(format!("the Telegram API answered: {e}")),
+        Err(_) => Err(String::from("the Telegram API didn't answer in time")),
+    }
+}
+
+/// Build the URL of the health check of a server listening on `address`.
+fn health_url(mut address: SocketAddr) -> String {
+    match address.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => address.set_ip(Ipv4Addr::LOCALHOST.into()),
+        IpAddr::V6(ip) if ip.is_unspecified() => address.set_ip(Ipv6Addr::LOCALHOST.into()),
+        _ => (),
+    }
+
+    format!("http://{address}{HEALTH_ROUTE}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use rstest::rstest;
+    use tower::ServiceExt;
+
+    #[rstest]
+    #[case("0.0.0.0:8443", "http://127.0.0.1:8443/healthz")]
+    #[case("[::]:8443", "http://[::1]:8443/healthz")]
+    #[case("10.0.0.2:80", "http://10.0.0.2:80/healthz")]
+    fn url(#[case] address: &str, #[case] expected: &str) {
+        assert_eq!(health_url(address.parse().unwrap()), expected);
+    }
+
+    #[rstest]
+    fn readiness_gating() {
+        let readiness = Readiness::default();
+        let router = router(readiness.clone());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let status = |router: Router| {
+            runtime
+                .block_on(router.oneshot(Request::get(HEALTH_ROUTE).body(Body::empty()).unwrap()))
+                .unwrap()
+                .status()
+        };
+
+        assert_eq!(status(router.clone()), StatusCode::SERVICE_UNAVAILABLE);
+        readiness.set_ready(true);
+        assert_eq!(status(router.clone()), StatusCode::OK);
+        readiness.set_ready(false);
+        assert_eq!(status(router), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
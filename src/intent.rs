@@ -0,0 +1,241 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Shortcuts for plain messages that ask for a short report.
+//!
+//! # Description
+//!
+//! Some users write what they want instead of using the commands, e.g. _cortos
+//! santander_ or _shorts on BBVA_. The matcher looks for the words about short selling
+//! (in Spanish and English) and for the companies mentioned in the message, and scores
+//! how likely it is that the user wants the report of those companies. Messages whose
+//! score reaches [MIN_CONFIDENCE] are handled as `/short <companies>`.
+
+use crate::finance::{Ibex35Market, IssuerRegistry};
+use crate::text::search_key;
+
+/// Lowest confidence to handle a message as a request of a short report.
+pub const MIN_CONFIDENCE: f32 = 0.6;
+
+/// Words that ask for short positions.
+const KEYWORDS: [&str; 8] = [
+    "short", "shorts", "shorted", "corto", "cortos", "corta", "cortas", "bajistas",
+];
+
+/// Words that carry no information about the companies.
+const FILLERS: [&str; 38] = [
+    "a",
+    "about",
+    "an",
+    "and",
+    "are",
+    "for",
+    "in",
+    "is",
+    "me",
+    "of",
+    "on",
+    "please",
+    "position",
+    "positions",
+    "show",
+    "the",
+    "what",
+    "whats",
+    "al",
+    "como",
+    "dame",
+    "de",
+    "del",
+    "el",
+    "en",
+    "esta",
+    "hay",
+    "la",
+    "las",
+    "los",
+    "muestra",
+    "por",
+    "favor",
+    "posicion",
+    "posiciones",
+    "que",
+    "sobre",
+    "y",
+];
+
+/// A plain message that asks for the short report of some companies.
+#[derive(Debug, PartialEq)]
+pub struct ShortIntent {
+    /// The companies mentioned in the message, as accepted by `/short`.
+    pub query: String,
+    /// How likely it is that the user asked for the report, from 0 to 1.
+    pub confidence: f32,
+}
+
+/// Check whether a message talks about short positions.
+pub fn mentions_shorts(text: &str) -> bool {
+    words(text)
+        .iter()
+        .any(|word| KEYWORDS.contains(&word.as_str()))
+}
+
+/// Find out whether a plain message asks for a short report.
+///
+/// # Description
+///
+/// The confidence is high when the message includes a word about short positions and
+/// the rest of the message names known companies. A message that only names a company
+/// (e.g. _BBVA_) gets a lower confidence, and it must match the ticker or the name of
+/// the company exactly.
+///
+/// ## Returns
+///
+/// `None` when the message doesn't name any company.
+pub fn short_intent(
+    text: &str,
+    market: &Ibex35Market,
+    issuers: &IssuerRegistry,
+) -> Option<ShortIntent> {
+    let words = words(text);
+    let keyword = words.iter().any(|word| KEYWORDS.contains(&word.as_str()));
+    let companies: Vec<&str> = words
+        .iter()
+        .map(String::as_str)
+        .filter(|word| !KEYWORDS.contains(word) && !FILLERS.contains(word))
+        .collect();
+
+    if companies.is_empty() {
+        return None;
+    }
+
+    let query = companies.join(" ");
+    let is_ticker = |word: &str| {
+        market.stock_by_ticker(word).is_some() || issuers.stock_by_ticker(word).is_some()
+    };
+    let is_name = |name: &str| {
+        market
+            .stock_by_name(name)
+            .is_some_and(|stocks| stocks.iter().any(|s| search_key(s.name()) == name))
+            || issuers
+                .stock_by_name(name)
+                .iter()
+                .any(|s| search_key(s.name()) == name)
+    };
+    let mentioned = |name: &str| {
+        market.stock_by_name(name).is_some() || !issuers.stock_by_name(name).is_empty()
+    };
+
+    let confidence = if is_ticker(&query) || is_name(&query) {
+        if keyword {
+            0.9
+        } else {
+            0.6
+        }
+    } else if companies.iter().all(|word| is_ticker(word)) {
+        if keyword {
+            0.8
+        } else {
+            0.4
+        }
+    } else if keyword && mentioned(&query) {
+        0.6
+    } else if keyword {
+        0.3
+    } else {
+        return None;
+    };
+
+    Some(ShortIntent { query, confidence })
+}
+
+/// Split a message into normalized words, without punctuation.
+fn words(text: &str) -> Vec<String> {
+    search_key(text)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finance::{IbexCompany, Ticker};
+    use rstest::{fixture, rstest};
+    use std::collections::HashMap;
+
+    fn companies(stocks: &[(&str, &str)]) -> HashMap<Ticker, IbexCompany> {
+        stocks
+            .iter()
+            .map(|(name, ticker)| {
+                let ticker = Ticker::new(ticker).unwrap();
+                (
+                    ticker.clone(),
+                    IbexCompany::new(None, name, ticker, "ES0000000000", None),
+                )
+            })
+            .collect()
+    }
+
+    #[fixture]
+    fn market() -> Ibex35Market {
+        Ibex35Market::new(companies(&[
+            ("BANCO SANTANDER", "SAN"),
+            ("BANCO SABADELL", "SAB"),
+            ("BBVA", "BBVA"),
+            ("TELEFONICA", "TEF"),
+        ]))
+    }
+
+    #[fixture]
+    fn issuers() -> IssuerRegistry {
+        IssuerRegistry::new(companies(&[("VISCOFAN", "VIS")]))
+    }
+
+    #[rstest]
+    #[case("cortos santander", Some(("santander", 0.6)))]
+    #[case("Shorts on BBVA?", Some(("bbva", 0.9)))]
+    #[case("¿Qué posiciones cortas hay en Telefónica?", Some(("telefonica", 0.9)))]
+    #[case("cortos san bbva", Some(("san bbva", 0.8)))]
+    #[case("cortos de banco", Some(("banco", 0.6)))]
+    #[case("viscofan", Some(("viscofan", 0.6)))]
+    #[case("san bbva", Some(("san bbva", 0.4)))]
+    #[case("shorts on acme", Some(("acme", 0.3)))]
+    #[case("hola", None)]
+    #[case("show me the shorts", None)]
+    #[case("", None)]
+    fn intents(
+        market: Ibex35Market,
+        issuers: IssuerRegistry,
+        #[case] text: &str,
+        #[case] expected: Option<(&str, f32)>,
+    ) {
+        assert_eq!(
+            short_intent(text, &market, &issuers),
+            expected.map(|(query, confidence)| ShortIntent {
+                query: String::from(query),
+                confidence,
+            })
+        );
+    }
+
+    #[rstest]
+    #[case("show me the shorts", true)]
+    #[case("POSICIONES CORTAS", true)]
+    #[case("hello there", false)]
+    fn mentions(#[case] text: &str, #[case] expected: bool) {
+        assert_eq!(mentions_shorts(text), expected);
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Latency of the handlers.
+//!
+//! # Description
+//!
+//! Every handler runs inside a tracing span whose name ends with `handler`, and every
+//! request to a data provider inside a span whose name ends with `request`. The
+//! [LatencyLayer] measures the lifetime of those spans: the time of each handler is
+//! added to a histogram per handler, and the handlers slower than a threshold are
+//! logged along with the time spent in each backend request.
+//!
+//! Only the spans enabled by the tracing level are measured, so the level shall be
+//! `info` or more verbose.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::span::{Attributes, Id};
+use tracing::{warn, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Upper bounds (milliseconds) of the buckets of the histograms. Slower handlers fall in
+/// an extra bucket.
+pub const BUCKETS_MS: [u64; 8] = [50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// Histograms of the latency of each handler, indexed by the name of its span.
+static HISTOGRAMS: Mutex<BTreeMap<&'static str, Histogram>> = Mutex::new(BTreeMap::new());
+
+/// Get the histograms of the latency of each handler since the start of the bot.
+pub fn latency_histograms() -> BTreeMap<&'static str, Histogram> {
+    HISTOGRAMS
+        .lock()
+        .expect("Poisoned lock of the latency histograms")
+        .clone()
+}
+
+/// Histogram of the latency of a handler.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Histogram {
+    /// Number of calls per bucket of [BUCKETS_MS], plus the calls slower than the last one.
+    pub buckets: [u64; BUCKETS_MS.len() + 1],
+    /// Number of calls.
+    pub count: u64,
+    /// Summation of the latency of all the calls.
+    pub total: Duration,
+}
+
+impl Histogram {
+    /// Add a call to the histogram.
+    pub fn record(&mut self, latency: Duration) {
+        let ms = latency.as_millis();
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|bound| ms <= u128::from(*bound))
+            .unwrap_or(BUCKETS_MS.len());
+
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total += latency;
+    }
+
+    /// Get the average latency of the calls.
+    pub fn mean(&self) -> Option<Duration> {
+        u32::try_from(self.count)
+            .ok()
+            .filter(|count| *count > 0)
+            .map(|count| self.total / count)
+    }
+}
+
+/// Timing of a measured span, kept in the extensions of the span.
+struct Timing {
+    start: Instant,
+    /// Time spent in the backend requests of a handler.
+    backend: Vec<(&'static str, Duration)>,
+}
+
+fn is_handler(name: &str) -> bool {
+    name.ends_with("handler")
+}
+
+fn is_backend(name: &str) -> bool {
+    name.ends_with("request")
+}
+
+/// [Layer] that measures the latency of the handlers.
+pub struct LatencyLayer {
+    slow_threshold: Duration,
+}
+
+impl LatencyLayer {
+    /// Class constructor.
+    ///
+    /// ## Arguments
+    ///
+    /// - _slow_threshold_: handlers that take longer are logged as slow.
+    pub fn new(slow_threshold: Duration) -> LatencyLayer {
+        LatencyLayer { slow_threshold }
+    }
+}
+
+impl<S> Layer<S> for LatencyLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let name = attrs.metadata().name();
+
+        if !(is_handler(name) || is_backend(name)) {
+            return;
+        }
+
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Timing {
+                start: Instant::now(),
+                backend: Vec::new(),
+            });
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(timing) = span.extensions_mut().remove::<Timing>() else {
+            return;
+        };
+        let name = span.name();
+        let elapsed = timing.start.elapsed();
+
+        if is_backend(name) {
+            // Charge the request to the handler that made it.
+            if let Some(handler) = span.scope().skip(1).find(|s| is_handler(s.name())) {
+                if let Some(parent) = handler.extensions_mut().get_mut::<Timing>() {
+                    parent.backend.push((name, elapsed));
+                }
+            }
+            return;
+        }
+
+        HISTOGRAMS
+            .lock()
+            .expect("Poisoned lock of the latency histograms")
+            .entry(name)
+            .or_default()
+            .record(elapsed);
+
+        if elapsed > self.slow_threshold {
+            let backend_ms: u128 = timing.backend.iter().map(|(_, t)| t.as_millis()).sum();
+            warn!(
+                handler = name,
+                total_ms = elapsed.as_millis() as u64,
+                backend_ms = backend_ms as u64,
+                backend = ?timing.backend,
+                "Slow handler"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use tracing::info_span;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[rstest]
+    fn histogram() {
+        let mut histogram = Histogram::default();
+
+        assert_eq!(histogram.mean(), None);
+
+        for ms in [9, 50, 51, 3000, 20000] {
+            histogram.record(Duration::from_millis(ms));
+        }
+
+        assert_eq!(histogram.buckets, [2, 1, 0, 0, 0, 0, 1, 0, 1]);
+        assert_eq!(histogram.count, 5);
+        assert_eq!(histogram.mean(), Some(Duration::from_millis(4622)));
+    }
+
+    #[rstest]
+    fn measure_handlers() {
+        let subscriber = tracing_subscriber::registry().with(LatencyLayer::new(Duration::ZERO));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let handler = info_span!("Test latency handler");
+            let _guard = handler.enter();
+            info_span!("Test request").in_scope(|| ());
+            info_span!("Unrelated span").in_scope(|| ());
+        });
+
+        let histograms = latency_histograms();
+        assert_eq!(histograms["Test latency handler"].count, 1);
+        assert!(!histograms.contains_key("Test request"));
+        assert!(!histograms.contains_key("Unrelated span"));
+    }
+}
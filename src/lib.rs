@@ -15,31 +15,71 @@
 //! Library of the ShortBot crate.
 
 use teloxide::{
+    adaptors::Throttle,
     dispatching::dialogue::{Dialogue, InMemStorage},
+    prelude::*,
     utils::command::BotCommands,
 };
 
+pub mod clock;
+pub mod commands;
 pub mod configuration;
+pub mod deadletter;
+pub mod glossary;
+pub mod health;
+pub mod intent;
+pub mod latency;
+pub mod lockout;
+pub mod ops_alerts;
+pub mod public_stats;
+pub mod recent;
+pub mod selfcheck;
 pub mod telemetry;
+pub mod templates;
+pub mod text;
+pub mod watchdog;
+pub mod webhook;
 
 /// Name of the data file that contains the descriptors for the Ibex35 companies.
 pub const IBEX35_STOCK_DESCRIPTORS: &str = "ibex35.toml";
 
+/// Name of the data file that contains the descriptors for the issuers outside the Ibex35.
+pub const ISSUER_DESCRIPTORS: &str = "issuers.toml";
+
+/// Name of the data file that contains the aliases of the owners of short positions.
+pub const OWNER_ALIASES: &str = "owner_aliases.toml";
+
 // Bring all the endpoints to the main context.
 pub mod endpoints {
+    mod about;
     mod default;
+    mod errors;
+    mod exposure;
+    mod fundstats;
+    mod glossary;
     mod help;
+    mod history;
     mod liststocks;
     mod receivestock;
+    mod sectors;
     mod start;
     mod support;
+    mod trace;
 
+    pub use about::about;
     pub use default::default;
+    pub use errors::{error_message, reply_error, UserError};
+    pub use exposure::exposure;
+    pub use fundstats::fund_stats;
+    pub use glossary::{glossary, glossary_button, glossary_term, GLOSSARY_CALLBACK};
     pub use help::help;
-    pub use liststocks::list_stocks;
-    pub use receivestock::receive_stock;
+    pub use history::history;
+    pub use liststocks::{list_stocks, stocks_keyboard};
+    pub use receivestock::{again, receive_stock, short_lookup};
+    pub use sectors::sectors;
     pub use start::start;
     pub use support::support;
+    pub use trace::trace;
 }
 
 // Bring all the handlers to the main context.
@@ -53,6 +93,18 @@ type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
 type ShortBotDialogue = Dialogue<State, InMemStorage<State>>;
 
+/// Moment in which the bot started, used to report the uptime.
+#[derive(Clone, Copy, Debug)]
+pub struct StartTime(pub std::time::Instant);
+
+/// Chat of the administrators, if any, where the [AdminCommand]s are accepted.
+#[derive(Clone, Copy, Debug)]
+pub struct AdminChat(pub Option<ChatId>);
+
+/// Client of the Telegram API used by the handlers. Messages are throttled to respect
+/// the limits of Telegram.
+pub type ThrottledBot = Throttle<Bot>;
+
 /// State machine
 ///
 /// # Description
@@ -77,10 +129,26 @@ pub enum CommandEng {
     Start,
     #[command(description = "Display help message")]
     Help,
-    #[command(description = "Check short position of a stock")]
-    Short,
+    #[command(description = "Check short position of a stock, optionally: TICKER")]
+    Short(String),
+    #[command(description = "Check again the last stock")]
+    Again,
+    #[command(description = "Show short interest per sector")]
+    Sectors,
+    #[command(
+        description = "Relate your shares of a stock with its short interest: TICKER SHARES"
+    )]
+    Exposure(String),
+    #[command(description = "Explain a concept of short selling")]
+    Glossary(String),
+    #[command(description = "Show the open short positions of a fund: NAME")]
+    Fundstats(String),
+    #[command(description = "Show the short positions of a stock on a past date: TICKER DATE")]
+    History(String),
     #[command(description = "Show support information")]
     Support,
+    #[command(description = "Show the version and the status of the bot")]
+    About,
 }
 
 /// User commands in Spanish language
@@ -94,10 +162,41 @@ pub enum CommandSpa {
     Inicio,
     #[command(description = "Mostrar la ayuda")]
     Ayuda,
-    #[command(description = "Consultar posiciones de una acción")]
-    Short,
+    #[command(description = "Consultar posiciones de una acción, opcionalmente: TICKER")]
+    Short(String),
+    #[command(description = "Consultar de nuevo la última acción")]
+    Repetir,
+    #[command(description = "Mostrar posiciones en corto por sector")]
+    Sectores,
+    #[command(
+        description = "Relacionar tus acciones de una empresa con sus posiciones en corto: TICKER ACCIONES"
+    )]
+    Exposicion(String),
+    #[command(description = "Explicar un concepto de las ventas en corto")]
+    Glosario(String),
+    #[command(description = "Mostrar las posiciones en corto abiertas de un fondo: NOMBRE")]
+    Fondo(String),
+    #[command(
+        description = "Mostrar las posiciones en corto de una acción en una fecha pasada: TICKER FECHA"
+    )]
+    Historico(String),
     #[command(description = "Mostrar información de apoyo")]
     Apoyo,
+    #[command(description = "Mostrar la versión y el estado del bot")]
+    Acerca,
+}
+
+/// Commands of the administrators
+///
+/// # Description
+///
+/// These commands are only accepted in the [AdminChat], and they are not registered in
+/// Telegram, so users don't see them.
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum AdminCommand {
+    /// Show every log of a user: USER_ID, or "off".
+    Trace(String),
 }
 
 /// Finance module.
@@ -107,19 +206,81 @@ pub enum CommandSpa {
 /// This module includes all the logic related to extract and process financial data.
 pub mod finance {
     mod cnmv_scrapper;
+    mod esma_registry;
     mod ibex35;
     mod ibex_company;
+    mod issuers;
+    mod owners;
+    mod providers;
+    mod quality;
+    mod scrape_coordinator;
+    mod short_cache;
+    mod ticker;
 
     use core::fmt;
 
-    pub use cnmv_scrapper::CNMVProvider;
-    pub use ibex35::{load_ibex35_companies, Ibex35Market};
+    pub use cnmv_scrapper::{
+        parse_alive_positions, parse_historic_positions, CNMVError, CNMVProvider,
+    };
+    pub use esma_registry::{
+        parse_registry, parse_registry_file, Registry, RegistryError, RegistryProvider,
+    };
+    pub use ibex35::{load_ibex35_companies, Ibex35Market, ListingError};
     pub use ibex_company::IbexCompany;
+    pub use issuers::IssuerRegistry;
+    pub use owners::{owner_key, OwnerAliases, OwnerAliasesError};
+    pub use providers::{
+        shadow_comparisons, shadow_mismatches, ProviderChain, ProviderError, ShortProvider,
+    };
+    pub use quality::{
+        check_position, quality_report, validate_positions, QualityIssue, RejectedPosition,
+    };
+    pub use scrape_coordinator::{CoordinatorError, ScrapeCoordinator, ScrapePermit};
+    pub use short_cache::{FundSummary, Ranking, SectorSummary, ShortCache, UNKNOWN_SECTOR};
+    pub use ticker::{Ticker, TickerError};
 
     use date::Date;
+    use std::collections::{HashMap, HashSet};
+
+    /// Minimum weight (%) of a net short position that must be disclosed to the public.
+    pub const DISCLOSURE_THRESHOLD: f32 = 0.5;
+
+    /// Parse the date of a short position.
+    ///
+    /// # Description
+    ///
+    /// Regulators use several formats for the dates. This function accepts the Spanish
+    /// format (_dd/mm/yyyy_) and the ISO 8601 format (_yyyy-mm-dd_).
+    ///
+    /// Dates that don't exist (e.g. _31/02/2024_) are rejected.
+    pub fn parse_position_date(date: &str) -> Option<Date> {
+        let date = date.trim();
+        let fields = |separator: char| {
+            let fields: Vec<&str> = date.split(separator).collect();
+            let digits = fields
+                .iter()
+                .all(|f| !f.is_empty() && f.bytes().all(|b| b.is_ascii_digit()));
+            (fields.len() == 3 && digits).then_some(fields)
+        };
+
+        let (year, month, day) = match (fields('/'), fields('-')) {
+            (Some(f), None) if f[2].len() == 4 => (f[2], f[1], f[0]),
+            (None, Some(f)) if f[0].len() == 4 => (f[0], f[1], f[2]),
+            _ => return None,
+        };
+        let (year, month, day) = (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+
+        // Date panics with days out of the month, so invalid days are checked here.
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return None;
+        }
+        let parsed = Date::overflowing_new(year, month, day);
+
+        (parsed.month() == month && parsed.day() == day).then_some(parsed)
+    }
 
     /// Short position descriptor.
-    #[derive(Debug)]
+    #[derive(Clone, Debug, PartialEq)]
     pub struct ShortPosition {
         /// This is the name of the investment fund that owns the short position.
         pub owner: String,
@@ -146,7 +307,7 @@ pub mod finance {
     ///
     /// Short positions are stated once per day, no later than 15:30. Thus a full timestamp
     /// is not really useful. Only the date is kept for the entries.
-    #[derive(Debug)]
+    #[derive(Clone, Debug)]
     pub struct AliveShortPositions {
         /// Summation of all the active [ShortPosition::weight] of the company.
         pub total: f32,
@@ -165,6 +326,49 @@ pub mod finance {
                 date: Date::today_utc(),
             }
         }
+
+        /// Keep only the latest position of each owner.
+        ///
+        /// # Description
+        ///
+        /// Sources may list a position more than once (e.g. the same notification
+        /// published twice), which would inflate the total. Owners are compared using
+        /// [owner_key], and the positions are compared by date. When the dates are
+        /// equal or unknown, the first position is kept. The total is computed again
+        /// using the remaining positions.
+        ///
+        /// ## Returns
+        ///
+        /// The number of removed positions.
+        pub fn dedup_owners(&mut self) -> usize {
+            let mut latest: HashMap<String, usize> = HashMap::new();
+
+            for (i, position) in self.positions.iter().enumerate() {
+                let date = parse_position_date(&position.date);
+                latest
+                    .entry(owner_key(&position.owner))
+                    .and_modify(|kept| {
+                        if date > parse_position_date(&self.positions[*kept].date) {
+                            *kept = i;
+                        }
+                    })
+                    .or_insert(i);
+            }
+
+            let removed = self.positions.len() - latest.len();
+
+            if removed > 0 {
+                let kept: HashSet<usize> = latest.into_values().collect();
+                let mut i = 0;
+                self.positions.retain(|_| {
+                    i += 1;
+                    kept.contains(&(i - 1))
+                });
+                self.total = self.positions.iter().map(|p| p.weight).sum();
+            }
+
+            removed
+        }
     }
 
     impl Default for AliveShortPositions {
@@ -173,6 +377,66 @@ pub mod finance {
         }
     }
 
+    /// Historical series of short positions of a company.
+    ///
+    /// # Description
+    ///
+    /// This `struct` gathers all the notifications of short positions of a company, including
+    /// those that are no longer alive. Each notification is kept as a [ShortPosition], and
+    /// the collection is sorted by date (oldest first).
+    #[derive(Debug, Default)]
+    pub struct ShortPositionHistory {
+        /// Collection of notified [ShortPosition] for a company.
+        pub positions: Vec<ShortPosition>,
+    }
+
+    impl ShortPositionHistory {
+        /// Get the short positions that were alive on a past date.
+        ///
+        /// # Description
+        ///
+        /// The latest notification of each owner up to `date` (included) gives its
+        /// position. Positions below the [DISCLOSURE_THRESHOLD] were no longer public,
+        /// so they are not included. Notifications with an invalid date are ignored.
+        ///
+        /// ## Returns
+        ///
+        /// The positions sorted by weight (highest first), dated `date`.
+        pub fn as_of(&self, date: Date) -> AliveShortPositions {
+            let mut latest: HashMap<String, (Date, &ShortPosition)> = HashMap::new();
+
+            for position in &self.positions {
+                let Some(notified) = parse_position_date(&position.date) else {
+                    continue;
+                };
+                if notified > date {
+                    continue;
+                }
+                latest
+                    .entry(owner_key(&position.owner))
+                    .and_modify(|current| {
+                        if notified >= current.0 {
+                            *current = (notified, position);
+                        }
+                    })
+                    .or_insert((notified, position));
+            }
+
+            let mut positions: Vec<ShortPosition> = latest
+                .into_values()
+                .map(|(_, position)| position.clone())
+                .filter(|position| position.weight >= DISCLOSURE_THRESHOLD)
+                .collect();
+            positions.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+
+            AliveShortPositions {
+                total: positions.iter().map(|p| p.weight).sum(),
+                positions,
+                date,
+            }
+        }
+    }
+
     impl fmt::Display for AliveShortPositions {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             for position in self.positions.iter() {
@@ -0,0 +1,314 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Protection of the webhook against the guessing of its secret token.
+//!
+//! # Description
+//!
+//! Requests rejected because of a wrong secret token are counted per source IP. When an
+//! IP fails too many times within the lockout period, its requests are rejected with the
+//! status `429 Too Many Requests` and a `Retry-After` header until the lockout expires. A
+//! request with the right token clears the failures of its IP. Failures older than the
+//! lockout period are forgotten.
+//!
+//! Behind a reverse proxy, all the requests come from the IP of the proxy, so an attacker
+//! would lock Telegram out as well. The IPs of the proxies shall be declared as trusted:
+//! the source of their requests is taken from the `X-Forwarded-For` header instead.
+//!
+//! The failures are kept in memory, so they are lost when the bot restarts.
+
+use crate::clock::{Clock, SystemClock};
+use axum::http::HeaderMap;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Stale entries are evicted when the number of tracked IPs reaches this value, or twice
+/// the number of IPs left after the previous eviction.
+const MIN_EVICTION_SIZE: usize = 1024;
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    first_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Failures {
+    /// Check whether the failures can be forgotten: the lockout expired, or the IP was
+    /// not locked out within the period.
+    fn is_stale(&self, now: Instant, period: Duration) -> bool {
+        match self.locked_until {
+            Some(until) => until <= now,
+            None => now.duration_since(self.first_failure) >= period,
+        }
+    }
+}
+
+/// Failed authentications per source IP.
+pub struct AuthLockout {
+    max_failures: u32,
+    period: Duration,
+    clock: Arc<dyn Clock>,
+    failures: Mutex<HashMap<IpAddr, Failures>>,
+    eviction_size: AtomicUsize,
+    trusted_proxies: Vec<IpAddr>,
+    lockouts: AtomicU64,
+}
+
+impl AuthLockout {
+    /// Class constructor.
+    ///
+    /// ## Arguments
+    ///
+    /// - _max_failures_: failed authentications allowed before locking an IP out.
+    /// - _period_: how long an IP stays locked out, and how long its failures are kept.
+    pub fn new(max_failures: u32, period: Duration) -> AuthLockout {
+        AuthLockout::with_clock(max_failures, period, Arc::new(SystemClock))
+    }
+
+    /// Class constructor that takes the [Clock] used to expire the lockouts.
+    pub fn with_clock(max_failures: u32, period: Duration, clock: Arc<dyn Clock>) -> AuthLockout {
+        AuthLockout {
+            max_failures,
+            period,
+            clock,
+            failures: Mutex::new(HashMap::new()),
+            eviction_size: AtomicUsize::new(MIN_EVICTION_SIZE),
+            trusted_proxies: Vec::new(),
+            lockouts: AtomicU64::new(0),
+        }
+    }
+
+    /// Set the IPs of the reverse proxies, whose requests are attributed to the source
+    /// given in the `X-Forwarded-For` header.
+    pub fn with_trusted_proxies(mut self, proxies: Vec<IpAddr>) -> AuthLockout {
+        self.trusted_proxies = proxies;
+        self
+    }
+
+    /// Get the IP of the client that sent a request.
+    ///
+    /// # Description
+    ///
+    /// Requests of untrusted peers come from the peer itself. For trusted proxies, the
+    /// `X-Forwarded-For` header is read from right to left, and the first IP that is not
+    /// a trusted proxy is the client. The peer is returned when there is no such IP.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.trusted_proxies.contains(&peer) {
+            return peer;
+        }
+
+        headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .map_while(|ip| ip.trim().parse::<IpAddr>().ok())
+            .find(|ip| !self.trusted_proxies.contains(ip))
+            .unwrap_or(peer)
+    }
+
+    /// Get the number of lockouts since the start of the bot.
+    pub fn lockouts(&self) -> u64 {
+        self.lockouts.load(Ordering::Relaxed)
+    }
+
+    /// Check whether an IP is locked out.
+    ///
+    /// ## Returns
+    ///
+    /// The time left until the lockout expires, or `None` when the IP is allowed.
+    pub fn locked(&self, ip: IpAddr) -> Option<Duration> {
+        let now = self.clock.now();
+        let mut failures = self.failures.lock().expect("Poisoned lock of the lockouts");
+
+        match failures.get(&ip).and_then(|f| f.locked_until) {
+            Some(until) if until > now => Some(until - now),
+            Some(_) => {
+                failures.remove(&ip);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Record a failed authentication of an IP.
+    ///
+    /// ## Returns
+    ///
+    /// `true` when the failure locks the IP out.
+    pub fn record_failure(&self, ip: IpAddr) -> bool {
+        let now = self.clock.now();
+        let mut failures = self.failures.lock().expect("Poisoned lock of the lockouts");
+        self.evict_stale(&mut failures, now);
+
+        let entry = failures.entry(ip).or_insert(Failures {
+            count: 0,
+            first_failure: now,
+            locked_until: None,
+        });
+        if entry.is_stale(now, self.period) {
+            *entry = Failures {
+                count: 0,
+                first_failure: now,
+                locked_until: None,
+            };
+        }
+
+        entry.count += 1;
+
+        if entry.count < self.max_failures {
+            return false;
+        }
+
+        entry.locked_until = Some(now + self.period);
+        let lockouts = self.lockouts.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(
+            "Locked out {ip} for {}s after {} failed authentications ({lockouts} lockouts so far)",
+            self.period.as_secs(),
+            entry.count
+        );
+
+        true
+    }
+
+    /// Forget the stale failures once the map grows over the eviction size. The size
+    /// doubles the number of remaining IPs, so the cost of the evictions is amortized.
+    fn evict_stale(&self, failures: &mut HashMap<IpAddr, Failures>, now: Instant) {
+        if failures.len() < self.eviction_size.load(Ordering::Relaxed) {
+            return;
+        }
+
+        failures.retain(|_, f| !f.is_stale(now, self.period));
+        self.eviction_size
+            .store(MIN_EVICTION_SIZE.max(2 * failures.len()), Ordering::Relaxed);
+    }
+
+    /// Get the number of IPs with failures being tracked.
+    pub fn tracked_ips(&self) -> usize {
+        self.failures
+            .lock()
+            .expect("Poisoned lock of the lockouts")
+            .len()
+    }
+
+    /// Forget the failures of an IP after a successful authentication.
+    pub fn clear(&self, ip: IpAddr) {
+        self.failures
+            .lock()
+            .expect("Poisoned lock of the lockouts")
+            .remove(&ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use date::Date;
+    use rstest::rstest;
+
+    #[rstest]
+    fn lockout() {
+        let clock = Arc::new(ManualClock::new(Date::new(2024, 6, 12)));
+        let lockout = AuthLockout::with_clock(3, Duration::from_secs(60), clock.clone());
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        assert!(!lockout.record_failure(ip));
+        assert!(!lockout.record_failure(ip));
+        assert_eq!(lockout.locked(ip), None);
+        assert!(lockout.record_failure(ip));
+        assert_eq!(lockout.locked(ip), Some(Duration::from_secs(60)));
+        assert_eq!(lockout.locked(other), None);
+        assert_eq!(lockout.lockouts(), 1);
+
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(lockout.locked(ip), Some(Duration::from_secs(15)));
+
+        clock.advance(Duration::from_secs(15));
+        assert_eq!(lockout.locked(ip), None);
+        assert!(!lockout.record_failure(ip));
+    }
+
+    #[rstest]
+    fn failure_window() {
+        let clock = Arc::new(ManualClock::new(Date::new(2024, 6, 12)));
+        let lockout = AuthLockout::with_clock(2, Duration::from_secs(60), clock.clone());
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        assert!(!lockout.record_failure(ip));
+        clock.advance(Duration::from_secs(60));
+        // The first failure is out of the window.
+        assert!(!lockout.record_failure(ip));
+        assert!(lockout.record_failure(ip));
+    }
+
+    #[rstest]
+    fn stale_entries_evicted() {
+        let clock = Arc::new(ManualClock::new(Date::new(2024, 6, 12)));
+        let lockout = AuthLockout::with_clock(5, Duration::from_secs(60), clock.clone());
+
+        for i in 0..MIN_EVICTION_SIZE as u32 {
+            lockout.record_failure(IpAddr::from(i.to_be_bytes()));
+        }
+        assert_eq!(lockout.tracked_ips(), MIN_EVICTION_SIZE);
+
+        clock.advance(Duration::from_secs(60));
+        lockout.record_failure("10.0.0.1".parse().unwrap());
+        assert_eq!(lockout.tracked_ips(), 1);
+    }
+
+    #[rstest]
+    #[case("10.0.0.9", None, "10.0.0.9")]
+    #[case("10.0.0.9", Some("203.0.113.7"), "10.0.0.9")]
+    #[case("127.0.0.1", Some("203.0.113.7"), "203.0.113.7")]
+    #[case("127.0.0.1", Some("198.51.100.1, 203.0.113.7"), "203.0.113.7")]
+    #[case("127.0.0.1", Some("203.0.113.7, 10.0.0.1"), "203.0.113.7")]
+    #[case("127.0.0.1", Some("garbage, 10.0.0.1"), "127.0.0.1")]
+    #[case("127.0.0.1", None, "127.0.0.1")]
+    fn client_ip(#[case] peer: &str, #[case] forwarded: Option<&str>, #[case] expected: &str) {
+        let lockout = AuthLockout::new(5, Duration::from_secs(60)).with_trusted_proxies(vec![
+            "127.0.0.1".parse().unwrap(),
+            "10.0.0.1".parse().unwrap(),
+        ]);
+        let mut headers = HeaderMap::new();
+        if let Some(forwarded) = forwarded {
+            headers.insert("x-forwarded-for", forwarded.parse().unwrap());
+        }
+
+        assert_eq!(
+            lockout.client_ip(peer.parse().unwrap(), &headers),
+            expected.parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[rstest]
+    fn clear_failures() {
+        let lockout = AuthLockout::new(2, Duration::from_secs(60));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        assert!(!lockout.record_failure(ip));
+        lockout.clear(ip);
+        assert!(!lockout.record_failure(ip));
+        assert_eq!(lockout.locked(ip), None);
+    }
+}
@@ -15,59 +15,255 @@
 //! Main file of the Shortbot
 
 use secrecy::ExposeSecret;
-use shortbot::finance::load_ibex35_companies;
+use shortbot::finance::{
+    load_ibex35_companies, CNMVProvider, IssuerRegistry, OwnerAliases, ProviderChain,
+    RegistryProvider, ShortCache, ShortProvider,
+};
 use shortbot::{
+    commands,
     configuration::Settings,
+    deadletter::DeadLetters,
+    glossary::{Glossary, GLOSSARY_FILE},
     handlers,
-    telemetry::{get_subscriber, init_subscriber},
-    State, IBEX35_STOCK_DESCRIPTORS,
+    health::{self, Readiness},
+    ops_alerts::OpsAlerts,
+    public_stats,
+    recent::RecentTickers,
+    selfcheck,
+    telemetry::{get_subscriber, init_redaction, init_subscriber},
+    templates::Templates,
+    watchdog::{self, Watchdog},
+    webhook, AdminChat, StartTime, State, IBEX35_STOCK_DESCRIPTORS, ISSUER_DESCRIPTORS,
+    OWNER_ALIASES,
 };
-use shortbot::{CommandEng, CommandSpa};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use teloxide::adaptors::Throttle;
 use teloxide::dispatching::dialogue::InMemStorage;
-use teloxide::payloads::SetMyCommandsSetters;
 use teloxide::prelude::*;
-use teloxide::utils::command::BotCommands;
 use tracing::{debug, info};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let start_time = StartTime(Instant::now());
+
     // Load the settings.
     let settings = Settings::new().expect("Failed to parse configuration files.");
 
     // Initialize the tracing subsystem.
-    let subscriber = get_subscriber(settings.tracing_level.as_str());
+    let subscriber = get_subscriber(
+        settings.tracing_level.as_str(),
+        Duration::from_millis(settings.application.slow_handler_ms),
+    );
     init_subscriber(subscriber);
+    init_redaction(
+        settings.log_redaction,
+        settings
+            .log_redaction_key
+            .as_ref()
+            .map(|key| key.expose_secret().as_str()),
+    );
+
+    if std::env::args().any(|arg| arg == selfcheck::CHECK_FLAG) {
+        let results = selfcheck::run_checks(&settings).await;
+        let passed = selfcheck::print_results(&results);
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    if std::env::args().any(|arg| arg == health::HEALTHCHECK_FLAG) {
+        match health::healthcheck(&settings).await {
+            Ok(()) => std::process::exit(0),
+            Err(e) => {
+                eprintln!("The bot is not healthy: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if std::env::args().any(|arg| arg == commands::SYNC_FLAG) {
+        let bot = Bot::new(settings.application.api_token.expose_secret());
+        let updated = commands::sync_commands(&bot, true).await?;
+        info!("Registered {updated} lists of commands");
+        return Ok(());
+    }
 
-    let ibexdata_path = std::path::PathBuf::from(settings.data_path).join(IBEX35_STOCK_DESCRIPTORS);
+    let ibexdata_path =
+        std::path::PathBuf::from(&settings.data_path).join(IBEX35_STOCK_DESCRIPTORS);
 
     let ibex35 = load_ibex35_companies(ibexdata_path.as_os_str().to_str().unwrap())
         .expect("Failed to parse IBEX35 companies.");
     let ibex35 = Arc::new(ibex35);
 
+    let glossary_path = std::path::PathBuf::from(&settings.data_path).join(GLOSSARY_FILE);
+    let glossary = Glossary::load(glossary_path.as_os_str().to_str().unwrap())
+        .expect("Failed to parse the glossary.");
+    let glossary = Arc::new(glossary);
+
+    let templates = Templates::load(&settings.templates, &settings.data_path)
+        .expect("Failed to load the templates.");
+    let templates = Arc::new(templates);
+
+    // The registry of issuers outside the index is optional.
+    let issuers_path = std::path::PathBuf::from(&settings.data_path).join(ISSUER_DESCRIPTORS);
+    let issuers = if issuers_path.exists() {
+        IssuerRegistry::load(issuers_path.as_os_str().to_str().unwrap())
+            .expect("Failed to parse the registry of issuers.")
+    } else {
+        info!("No registry of issuers found, only the Ibex35 is supported");
+        IssuerRegistry::default()
+    };
+    let issuers = Arc::new(issuers);
+
+    // The aliases of the owners are optional as well.
+    let aliases_path = std::path::PathBuf::from(&settings.data_path).join(OWNER_ALIASES);
+    let aliases = if aliases_path.exists() {
+        OwnerAliases::load(aliases_path.as_os_str().to_str().unwrap())
+            .expect("Failed to parse the aliases of the owners.")
+    } else {
+        OwnerAliases::default()
+    };
+    let aliases = Arc::new(aliases);
+
+    // All the providers share the same pool of connections.
+    let http_client = settings
+        .providers
+        .http
+        .build_client()
+        .expect("Failed to build the HTTP client.");
+
+    let coordinator = Arc::new(settings.providers.scraping.build_coordinator());
+
+    // Registry files are parsed once and kept in memory for a while.
+    let registry_ttl = Duration::from_secs(settings.providers.cache.registry_ttl);
+
+    // The CNMV's web page is the main source of data, the rest are fallbacks.
+    let mut providers = vec![ShortProvider::Cnmv(CNMVProvider::with_client(
+        http_client.clone(),
+        Arc::clone(&coordinator),
+    ))];
+    if let Some(source) = settings.providers.registry_file.as_deref() {
+        info!("Using the registry file {source} as fallback provider");
+        providers.push(ShortProvider::Registry(
+            RegistryProvider::with_client(source, http_client.clone(), Arc::clone(&coordinator))
+                .with_ttl(registry_ttl),
+        ));
+    }
+    let mut providers = ProviderChain::new(providers);
+    if let Some(source) = settings.providers.shadow_registry_file.as_deref() {
+        providers = providers.with_shadow(ShortProvider::Registry(
+            RegistryProvider::with_client(source, http_client.clone(), Arc::clone(&coordinator))
+                .with_ttl(registry_ttl),
+        ));
+    }
+    let providers = Arc::new(providers);
+
+    // Only the CNMV's web page publishes the historical series of short positions.
+    let history_provider = Arc::new(CNMVProvider::with_client(
+        http_client.clone(),
+        Arc::clone(&coordinator),
+    ));
+
+    // Keep the short positions of the whole index in memory for the aggregated queries.
+    let cache = Arc::new(ShortCache::new(
+        providers,
+        Duration::from_secs(settings.providers.cache.ttl),
+    ));
+
     info!("Started ShortBot server");
 
-    let bot = Bot::new(settings.application.api_token.expose_secret());
+    // Messages to Telegram are throttled, the rest of the requests use the plain client.
+    let bot = Throttle::spawn_with_settings(
+        Bot::new(settings.application.api_token.expose_secret()),
+        settings.application.throttle.build_settings(),
+    );
+
+    let alerts = Arc::new(match settings.ops_alerts.as_ref() {
+        Some(alerts_settings) => OpsAlerts::spawn(bot.clone(), alerts_settings),
+        None => OpsAlerts::default(),
+    });
+
+    // Background tasks beat a heartbeat, so the watchdog notices when they get stuck.
+    let watchdog = Arc::new(Watchdog::default());
+    tokio::spawn(Arc::clone(&watchdog).run(watchdog::CHECK_PERIOD, Arc::clone(&alerts)));
+
+    let refresh_period = Duration::from_secs(settings.providers.cache.refresh_period);
+    let heartbeat = watchdog.register("short cache refresh", 2 * refresh_period);
+    {
+        let cache = Arc::clone(&cache);
+        let ibex35 = Arc::clone(&ibex35);
+        let alerts = Arc::clone(&alerts);
+        watchdog::supervise("short cache refresh", Arc::clone(&alerts), move || {
+            Arc::clone(&cache).refresh_periodically(
+                Arc::clone(&ibex35),
+                refresh_period,
+                Arc::clone(&alerts),
+                heartbeat.clone(),
+            )
+        });
+    }
 
     // Configure the supported languages of the Bot.
     debug!("Setting up commands of the bot");
-    bot.set_my_commands(CommandSpa::bot_commands())
-        .language_code("es")
-        .await?;
-    bot.set_my_commands(CommandEng::bot_commands())
-        .language_code("en")
-        .await?;
+    let updated = commands::sync_commands(bot.inner(), false).await?;
+    info!("Updated {updated} lists of commands");
 
     info!("Dispatching");
 
     let ibex35_clone = Arc::clone(&ibex35);
+    let dead_letters = Arc::new(DeadLetters::new(
+        settings.application.dead_letter_file.as_deref(),
+    ));
+    let readiness = Readiness::default();
+    let routes = public_stats::router(Arc::clone(&ibex35), Arc::clone(&cache))
+        .merge(health::router(readiness.clone()));
 
-    Dispatcher::builder(bot, handlers::schema())
-        .dependencies(dptree::deps![ibex35_clone, InMemStorage::<State>::new()])
+    let mut dispatcher = Dispatcher::builder(bot.clone(), handlers::schema())
+        .dependencies(dptree::deps![
+            ibex35_clone,
+            cache,
+            history_provider,
+            glossary,
+            issuers,
+            aliases,
+            Arc::new(settings.support.clone()),
+            start_time,
+            AdminChat(settings.ops_alerts.as_ref().map(|s| ChatId(s.chat_id))),
+            Arc::clone(&dead_letters),
+            Arc::new(RecentTickers::default()),
+            templates,
+            InMemStorage::<State>::new()
+        ])
+        .default_handler(move |update| {
+            let dead_letters = Arc::clone(&dead_letters);
+            async move { dead_letters.record(&update, "no handler for the update") }
+        })
         .enable_ctrlc_handler()
-        .build()
-        .dispatch()
-        .await;
+        .build();
+
+    match settings.webhook.as_ref() {
+        Some(webhook_settings) => {
+            let heartbeat = watchdog.register(
+                "webhook watcher",
+                2 * Duration::from_secs(webhook_settings.drift_check_period),
+            );
+            let listener = webhook::listener(
+                bot.into_inner(),
+                webhook_settings,
+                routes,
+                alerts,
+                heartbeat,
+                readiness,
+            )
+            .await?;
+            dispatcher
+                .dispatch_with_listener(
+                    listener,
+                    LoggingErrorHandler::with_custom_text("An error from the update listener"),
+                )
+                .await;
+        }
+        None => dispatcher.dispatch().await,
+    }
 
     info!("Gracefully closed ShortBot server");
 
@@ -0,0 +1,230 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Alerts about operational events sent to the chat of the administrators.
+//!
+//! # Description
+//!
+//! Important events (e.g. all the data providers failing, or an IP guessing the secret
+//! token of the webhook) are always logged. When an admin chat is configured
+//! ([Settings::ops_alerts][crate::configuration::Settings]), the events whose severity
+//! reaches the configured level are also sent to that chat.
+//!
+//! Alerts of the same kind are rate limited: after an alert is sent, the following ones
+//! of the same kind are suppressed for a while, and the next one tells how many were
+//! suppressed.
+
+use crate::clock::{Clock, SystemClock};
+use crate::configuration::OpsAlertsSettings;
+use crate::ThrottledBot;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use teloxide::prelude::*;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{error, info, warn};
+
+/// Severity levels of the operational events.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Events worth knowing, which need no action.
+    Info,
+    /// Degraded service, which might need an action.
+    #[default]
+    Warning,
+    /// The service is down or under attack.
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "ℹ️ INFO",
+            Severity::Warning => "⚠️ WARNING",
+            Severity::Critical => "🚨 CRITICAL",
+        })
+    }
+}
+
+/// Rate limit of the alerts of one kind.
+#[derive(Debug)]
+struct Sent {
+    at: Instant,
+    suppressed: u32,
+}
+
+/// Channel of the alerts to the chat of the administrators.
+pub struct OpsAlerts {
+    sender: Option<UnboundedSender<String>>,
+    min_severity: Severity,
+    min_interval: Duration,
+    clock: Arc<dyn Clock>,
+    sent: Mutex<HashMap<&'static str, Sent>>,
+}
+
+impl Default for OpsAlerts {
+    /// Build a channel that only logs the events.
+    fn default() -> Self {
+        OpsAlerts {
+            sender: None,
+            min_severity: Severity::Critical,
+            min_interval: Duration::ZERO,
+            clock: Arc::new(SystemClock),
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl OpsAlerts {
+    /// Build a channel whose alerts are sent to the admin chat by a background task.
+    pub fn spawn(bot: ThrottledBot, settings: &OpsAlertsSettings) -> OpsAlerts {
+        let (alerts, receiver) = OpsAlerts::new(settings, Arc::new(SystemClock));
+        tokio::spawn(deliver(bot, ChatId(settings.chat_id), receiver));
+        info!("Operational alerts will be sent to the admin chat");
+
+        alerts
+    }
+
+    /// Build a channel whose alerts are received by the returned [UnboundedReceiver].
+    pub fn new(
+        settings: &OpsAlertsSettings,
+        clock: Arc<dyn Clock>,
+    ) -> (OpsAlerts, UnboundedReceiver<String>) {
+        let (sender, receiver) = unbounded_channel();
+        let alerts = OpsAlerts {
+            sender: Some(sender),
+            min_severity: settings.min_severity,
+            min_interval: Duration::from_secs(settings.min_interval),
+            clock,
+            sent: Mutex::new(HashMap::new()),
+        };
+
+        (alerts, receiver)
+    }
+
+    /// Report an operational event.
+    ///
+    /// ## Arguments
+    ///
+    /// - _severity_: how important the event is.
+    /// - _kind_: identifier of the kind of event, used to rate limit the alerts.
+    /// - _text_: description of the event.
+    pub fn alert(&self, severity: Severity, kind: &'static str, text: &str) {
+        match severity {
+            Severity::Info => info!("Operational alert ({kind}): {text}"),
+            Severity::Warning => warn!("Operational alert ({kind}): {text}"),
+            Severity::Critical => error!("Operational alert ({kind}): {text}"),
+        }
+
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        if severity < self.min_severity {
+            return;
+        }
+
+        let now = self.clock.now();
+        let mut sent = self.sent.lock().expect("Poisoned lock of the alerts");
+
+        let suppressed = match sent.get_mut(kind) {
+            Some(last) if now.duration_since(last.at) < self.min_interval => {
+                last.suppressed += 1;
+                return;
+            }
+            Some(last) => last.suppressed,
+            None => 0,
+        };
+        sent.insert(
+            kind,
+            Sent {
+                at: now,
+                suppressed: 0,
+            },
+        );
+
+        let mut message = format!("{severity}: {text}");
+        if suppressed > 0 {
+            message.push_str(&format!("\n({suppressed} similar alerts were suppressed)"));
+        }
+
+        if sender.send(message).is_err() {
+            error!("The delivery of the operational alerts stopped");
+        }
+    }
+}
+
+/// Send the alerts to the admin chat, until the channel is closed.
+async fn deliver(bot: ThrottledBot, chat_id: ChatId, mut receiver: UnboundedReceiver<String>) {
+    while let Some(message) = receiver.recv().await {
+        if let Err(e) = bot.send_message(chat_id, message).await {
+            error!("Failed to send an operational alert to the admin chat: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use date::Date;
+    use rstest::{fixture, rstest};
+
+    #[fixture]
+    fn settings() -> OpsAlertsSettings {
+        OpsAlertsSettings {
+            chat_id: 1234,
+            min_severity: Severity::Warning,
+            min_interval: 600,
+        }
+    }
+
+    #[rstest]
+    fn severity_filter(settings: OpsAlertsSettings) {
+        let clock = Arc::new(ManualClock::new(Date::new(2024, 6, 12)));
+        let (alerts, mut receiver) = OpsAlerts::new(&settings, clock);
+
+        alerts.alert(Severity::Info, "test", "info");
+        alerts.alert(Severity::Critical, "test", "critical");
+
+        assert_eq!(receiver.try_recv().unwrap(), "🚨 CRITICAL: critical");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[rstest]
+    fn rate_limit(settings: OpsAlertsSettings) {
+        let clock = Arc::new(ManualClock::new(Date::new(2024, 6, 12)));
+        let (alerts, mut receiver) = OpsAlerts::new(&settings, clock.clone());
+
+        alerts.alert(Severity::Warning, "drift", "first");
+        alerts.alert(Severity::Warning, "drift", "second");
+        alerts.alert(Severity::Warning, "drift", "third");
+        alerts.alert(Severity::Warning, "other", "other");
+
+        assert_eq!(receiver.try_recv().unwrap(), "⚠️ WARNING: first");
+        assert_eq!(receiver.try_recv().unwrap(), "⚠️ WARNING: other");
+        assert!(receiver.try_recv().is_err());
+
+        clock.advance(Duration::from_secs(600));
+        alerts.alert(Severity::Warning, "drift", "fourth");
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            "⚠️ WARNING: fourth\n(2 similar alerts were suppressed)"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Public statistics of the ShortBot.
+//!
+//! # Description
+//!
+//! This module serves a read-only JSON document with aggregated data of the market,
+//! suitable for embedding in the web page of the project. No data of the users is
+//! included. The route is served by the [axum] server of the webhook mode.
+
+use crate::finance::{AliveShortPositions, Ibex35Market, ShortCache, Ticker};
+use axum::{extract::State, routing::get, Json, Router};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Route of the public statistics.
+pub const PUBLIC_STATS_ROUTE: &str = "/stats/public";
+
+/// Number of companies included in the list of the most shorted companies.
+const MOST_SHORTED_COUNT: usize = 5;
+
+/// Aggregated statistics of the market.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PublicStats {
+    /// Name of the market.
+    pub market: String,
+    /// Number of companies of the market.
+    pub companies: usize,
+    /// Number of companies with known short positions.
+    pub tracked: usize,
+    /// Seconds since the last refresh of the whole market, if any.
+    pub last_refresh_secs: Option<u64>,
+    /// Companies with the highest short interest, sorted by it (highest first).
+    pub most_shorted: Vec<ShortedCompany>,
+}
+
+/// Short interest of a company.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ShortedCompany {
+    pub ticker: Ticker,
+    pub name: String,
+    /// Summation of the alive short positions (% of the capital).
+    pub total: f32,
+}
+
+/// Build the [Router] that serves the public statistics.
+pub fn router(market: Arc<Ibex35Market>, cache: Arc<ShortCache>) -> Router {
+    Router::new()
+        .route(PUBLIC_STATS_ROUTE, get(public_stats_handler))
+        .with_state((market, cache))
+}
+
+async fn public_stats_handler(
+    State((market, cache)): State<(Arc<Ibex35Market>, Arc<ShortCache>)>,
+) -> Json<PublicStats> {
+    let last_refresh = cache
+        .last_refresh()
+        .map(|instant| cache.clock().now().duration_since(instant));
+
+    Json(public_stats(&market, &cache.snapshot(), last_refresh))
+}
+
+fn public_stats(
+    market: &Ibex35Market,
+    snapshot: &HashMap<Ticker, Arc<AliveShortPositions>>,
+    last_refresh: Option<Duration>,
+) -> PublicStats {
+    let mut most_shorted: Vec<ShortedCompany> = market
+        .get_companies()
+        .into_iter()
+        .filter_map(|stock| {
+            snapshot
+                .get(stock.ticker())
+                .map(|positions| ShortedCompany {
+                    ticker: stock.ticker().clone(),
+                    name: String::from(stock.name()),
+                    total: positions.total,
+                })
+        })
+        .collect();
+
+    let tracked = most_shorted.len();
+
+    most_shorted.sort_by(|a, b| b.total.total_cmp(&a.total).then(a.ticker.cmp(&b.ticker)));
+    most_shorted.retain(|company| company.total > 0.0);
+    most_shorted.truncate(MOST_SHORTED_COUNT);
+
+    PublicStats {
+        market: String::from(market.market_name()),
+        companies: market.get_companies().len(),
+        tracked,
+        last_refresh_secs: last_refresh.map(|age| age.as_secs()),
+        most_shorted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finance::{IbexCompany, ProviderChain};
+    use axum::{body::Body, http::Request, http::StatusCode};
+    use pretty_assertions::assert_eq;
+    use rstest::{fixture, rstest};
+    use tower::ServiceExt;
+
+    #[fixture]
+    fn market() -> Ibex35Market {
+        let companies = [
+            IbexCompany::new(
+                None,
+                "GRIFOLS",
+                Ticker::new("GRF").unwrap(),
+                "ES0171996087",
+                None,
+            ),
+            IbexCompany::new(
+                None,
+                "ROVI",
+                Ticker::new("ROVI").unwrap(),
+                "ES0157261019",
+                None,
+            ),
+            IbexCompany::new(
+                None,
+                "BBVA",
+                Ticker::new("BBVA").unwrap(),
+                "ES0113211835",
+                None,
+            ),
+            IbexCompany::new(
+                None,
+                "SOLARIA",
+                Ticker::new("SLR").unwrap(),
+                "ES0165386014",
+                None,
+            ),
+        ];
+
+        Ibex35Market::new(
+            companies
+                .into_iter()
+                .map(|c| (c.ticker().clone(), c))
+                .collect(),
+        )
+    }
+
+    fn positions(total: f32) -> Arc<AliveShortPositions> {
+        Arc::new(AliveShortPositions {
+            total,
+            ..Default::default()
+        })
+    }
+
+    #[rstest]
+    fn aggregated_stats(market: Ibex35Market) {
+        let snapshot = HashMap::from([
+            (Ticker::new("GRF").unwrap(), positions(2.25)),
+            (Ticker::new("ROVI").unwrap(), positions(0.0)),
+            (Ticker::new("SLR").unwrap(), positions(4.0)),
+        ]);
+
+        let stats = public_stats(&market, &snapshot, Some(Duration::from_millis(90_500)));
+
+        assert_eq!(stats.companies, 4);
+        assert_eq!(stats.tracked, 3);
+        assert_eq!(stats.last_refresh_secs, Some(90));
+        assert_eq!(
+            stats
+                .most_shorted
+                .iter()
+                .map(|c| c.ticker.as_str())
+                .collect::<Vec<_>>(),
+            ["SLR", "GRF"]
+        );
+    }
+
+    #[rstest]
+    fn serve_stats(market: Ibex35Market) {
+        let cache = ShortCache::new(
+            Arc::new(ProviderChain::new(Vec::new())),
+            Duration::from_secs(60),
+        );
+        let router = router(Arc::new(market), Arc::new(cache));
+
+        let response = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(
+                router.oneshot(
+                    Request::get(PUBLIC_STATS_ROUTE)
+                        .body(Body::empty())
+                        .unwrap(),
+                ),
+            )
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/json");
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Tickers recently checked in each chat.
+//!
+//! # Description
+//!
+//! The bot remembers the last companies checked in a chat, so they can be checked again
+//! quickly. As the rest of the state of the dialogues, this data is kept in memory and
+//! it is lost when the bot restarts. The number of chats is bounded: the chats that
+//! checked a company least recently are forgotten first.
+
+use crate::finance::Ticker;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use teloxide::types::ChatId;
+
+/// Number of tickers remembered per chat.
+pub const RECENT_CAPACITY: usize = 5;
+
+/// Default number of chats remembered.
+pub const DEFAULT_MAX_CHATS: usize = 10_000;
+
+/// Tickers recently checked in each chat, most recent first.
+#[derive(Debug)]
+pub struct RecentTickers {
+    chats: Mutex<Chats>,
+    max_chats: usize,
+}
+
+/// Recent tickers of each chat, along with the order in which the chats were used.
+#[derive(Debug, Default)]
+struct Chats {
+    /// Last use and recent tickers of each chat.
+    recent: HashMap<ChatId, (u64, VecDeque<Ticker>)>,
+    /// Counter of the calls to [RecentTickers::record], which orders the uses of the chats.
+    uses: u64,
+}
+
+impl Default for RecentTickers {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CHATS)
+    }
+}
+
+impl RecentTickers {
+    /// Class constructor.
+    ///
+    /// ## Arguments
+    ///
+    /// - _max_chats_: maximum number of chats remembered. When a new chat exceeds it,
+    ///   the chat that recorded a ticker least recently is forgotten.
+    pub fn new(max_chats: usize) -> Self {
+        RecentTickers {
+            chats: Mutex::new(Chats::default()),
+            max_chats,
+        }
+    }
+
+    /// Remember that `ticker` was checked in the chat.
+    ///
+    /// # Description
+    ///
+    /// A ticker that was already remembered moves to the first place. The oldest ticker
+    /// is forgotten when there are more than [RECENT_CAPACITY].
+    pub fn record(&self, chat_id: ChatId, ticker: &Ticker) {
+        let mut chats = self
+            .chats
+            .lock()
+            .expect("Poisoned lock of the recent tickers");
+
+        if !chats.recent.contains_key(&chat_id) && chats.recent.len() >= self.max_chats {
+            let oldest = chats
+                .recent
+                .iter()
+                .min_by_key(|(_, (last_use, _))| *last_use)
+                .map(|(chat_id, _)| *chat_id);
+            if let Some(oldest) = oldest {
+                chats.recent.remove(&oldest);
+            }
+        }
+
+        chats.uses += 1;
+        let uses = chats.uses;
+        let (last_use, recent) = chats.recent.entry(chat_id).or_default();
+
+        *last_use = uses;
+        recent.retain(|t| t != ticker);
+        recent.push_front(ticker.clone());
+        recent.truncate(RECENT_CAPACITY);
+    }
+
+    /// Get the tickers recently checked in the chat, most recent first.
+    pub fn get(&self, chat_id: ChatId) -> Vec<Ticker> {
+        self.chats
+            .lock()
+            .expect("Poisoned lock of the recent tickers")
+            .recent
+            .get(&chat_id)
+            .map(|(_, recent)| recent.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the last ticker checked in the chat.
+    pub fn last(&self, chat_id: ChatId) -> Option<Ticker> {
+        self.chats
+            .lock()
+            .expect("Poisoned lock of the recent tickers")
+            .recent
+            .get(&chat_id)
+            .and_then(|(_, recent)| recent.front().cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    fn recent_tickers() {
+        let recent = RecentTickers::default();
+        let chat = ChatId(1);
+
+        assert_eq!(recent.last(chat), None);
+
+        for ticker in ["SAN", "GRF", "REP", "ITX", "IAG", "san", "BBVA"] {
+            recent.record(chat, &Ticker::new(ticker).unwrap());
+        }
+
+        assert_eq!(recent.get(chat), ["BBVA", "SAN", "IAG", "ITX", "REP"]);
+        assert_eq!(recent.last(chat), Ticker::new("BBVA").ok());
+        assert!(recent.get(ChatId(2)).is_empty());
+    }
+
+    #[rstest]
+    fn least_recent_chat_forgotten() {
+        let recent = RecentTickers::new(2);
+        let san = Ticker::new("SAN").unwrap();
+
+        recent.record(ChatId(1), &san);
+        recent.record(ChatId(2), &san);
+        recent.record(ChatId(1), &san);
+        recent.record(ChatId(3), &san);
+
+        assert_eq!(recent.last(ChatId(1)), Some(san.clone()));
+        assert_eq!(recent.last(ChatId(2)), None);
+        assert_eq!(recent.last(ChatId(3)), Some(san));
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Startup self-check of the ShortBot.
+//!
+//! # Description
+//!
+//! This module verifies that all the external dependencies of the bot are in place before
+//! serving users. It is meant for deploy pipelines: run the binary with `--check` and it
+//! prints a pass/fail matrix and exits with a non-zero code if any check fails.
+
+use crate::configuration::Settings;
+use crate::finance::{load_ibex35_companies, CNMVProvider, IssuerRegistry, OwnerAliases};
+use crate::glossary::{Glossary, GLOSSARY_FILE};
+use crate::{IBEX35_STOCK_DESCRIPTORS, ISSUER_DESCRIPTORS, OWNER_ALIASES};
+use secrecy::ExposeSecret;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use teloxide::prelude::*;
+
+/// Command line flag that runs the self-check instead of the bot.
+pub const CHECK_FLAG: &str = "--check";
+
+/// Result of a single check.
+pub struct CheckResult {
+    /// Name of the check.
+    pub name: &'static str,
+    /// Details of the check: `Ok` when it passes, `Err` otherwise.
+    pub outcome: Result<String, String>,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, outcome: Result<String, String>) -> CheckResult {
+        CheckResult { name, outcome }
+    }
+
+    /// Whether the check passed.
+    pub fn passed(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (status, details) = match &self.outcome {
+            Ok(details) => ("PASS", details),
+            Err(details) => ("FAIL", details),
+        };
+
+        write!(f, "[{status}] {:<20} {details}", self.name)
+    }
+}
+
+/// Run all the checks.
+///
+/// # Description
+///
+/// Checks don't stop at the first failure, so the full matrix is reported at once.
+pub async fn run_checks(settings: &Settings) -> Vec<CheckResult> {
+    let mut results = Vec::new();
+
+    // Listing of the companies.
+    let listing_path = PathBuf::from(&settings.data_path).join(IBEX35_STOCK_DESCRIPTORS);
+    let listing_path = listing_path.to_string_lossy();
+    let outcome = match load_ibex35_companies(&listing_path) {
+        Ok(market) if market.get_companies().is_empty() => {
+            Err(format!("no companies in {listing_path}"))
+        }
+        Ok(market) => Ok(format!(
+            "{} companies in {listing_path}",
+            market.get_companies().len()
+        )),
+        Err(e) => Err(e.to_string()),
+    };
+    results.push(CheckResult::new("Listing file", outcome));
+
+    let glossary_path = PathBuf::from(&settings.data_path).join(GLOSSARY_FILE);
+    let glossary_path = glossary_path.to_string_lossy();
+    let outcome = match Glossary::load(&glossary_path) {
+        Ok(glossary) => Ok(format!(
+            "{} terms in {glossary_path}",
+            glossary.entries().count()
+        )),
+        Err(e) => Err(e.to_string()),
+    };
+    results.push(CheckResult::new("Glossary file", outcome));
+
+    // The registry of issuers is optional.
+    let issuers_path = PathBuf::from(&settings.data_path).join(ISSUER_DESCRIPTORS);
+    if issuers_path.exists() {
+        let issuers_path = issuers_path.to_string_lossy();
+        let outcome = match IssuerRegistry::load(&issuers_path) {
+            Ok(issuers) => Ok(format!("{} issuers in {issuers_path}", issuers.len())),
+            Err(e) => Err(e.to_string()),
+        };
+        results.push(CheckResult::new("Issuers file", outcome));
+    }
+
+    // The aliases of the owners are optional.
+    let aliases_path = PathBuf::from(&settings.data_path).join(OWNER_ALIASES);
+    if aliases_path.exists() {
+        let aliases_path = aliases_path.to_string_lossy();
+        let outcome = match OwnerAliases::load(&aliases_path) {
+            Ok(aliases) => Ok(format!("{} aliases in {aliases_path}", aliases.len())),
+            Err(e) => Err(e.to_string()),
+        };
+        results.push(CheckResult::new("Owner aliases file", outcome));
+    }
+
+    // Telegram API.
+    let bot = Bot::new(settings.application.api_token.expose_secret());
+    let outcome = match bot.get_me().await {
+        Ok(me) => Ok(format!("logged in as @{}", me.username())),
+        Err(e) => Err(e.to_string()),
+    };
+    results.push(CheckResult::new("Telegram token", outcome));
+
+    // Long polling is not compatible with a registered webhook, and the webhook mode
+    // requires Telegram pointing at the configured URL.
+    let expected = settings
+        .webhook
+        .as_ref()
+        .map(|webhook| webhook.url.as_str());
+    let outcome = match bot.get_webhook_info().await {
+        Ok(info) => match (info.url.as_ref().map(|url| url.as_str()), expected) {
+            (None, None) => Ok(String::from("no webhook registered (polling mode)")),
+            (Some(url), None) => Err(format!(
+                "a webhook is registered ({url}), polling won't work"
+            )),
+            (Some(url), Some(expected)) if url == expected => Ok(format!("registered ({url})")),
+            (url, Some(expected)) => Err(format!(
+                "Telegram points at {} instead of {expected}",
+                url.unwrap_or("nothing")
+            )),
+        },
+        Err(e) => Err(e.to_string()),
+    };
+    results.push(CheckResult::new("Telegram webhook", outcome));
+
+    // Data sources.
+    let outcome = match settings.providers.http.build_client() {
+        Ok(client) => {
+            let coordinator = Arc::new(settings.providers.scraping.build_coordinator());
+            match CNMVProvider::with_client(client, coordinator).ping().await {
+                Ok(_) => Ok(String::from("reachable")),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+        Err(e) => Err(format!("wrong HTTP client settings: {e}")),
+    };
+    results.push(CheckResult::new("CNMV web page", outcome));
+
+    if let Some(source) = settings.providers.registry_file.as_deref() {
+        let outcome = if source.starts_with("http://") || source.starts_with("https://") {
+            Ok(format!("{source} (remote, not checked)"))
+        } else {
+            match std::fs::metadata(source) {
+                Ok(meta) if meta.is_file() => Ok(String::from(source)),
+                Ok(_) => Err(format!("{source} is not a file")),
+                Err(e) => Err(format!("{source}: {e}")),
+            }
+        };
+        results.push(CheckResult::new("Registry file", outcome));
+    }
+
+    results
+}
+
+/// Print the results of the checks as a matrix.
+///
+/// ## Returns
+///
+/// `true` when all the checks passed.
+pub fn print_results(results: &[CheckResult]) -> bool {
+    println!("ShortBot self-check");
+    for result in results {
+        println!("  {result}");
+    }
+
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    if failed == 0 {
+        println!("All checks passed");
+    } else {
+        println!("{failed} check(s) failed");
+    }
+
+    failed == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    fn display_result() {
+        let pass = CheckResult::new("Listing file", Ok(String::from("35 companies")));
+        let fail = CheckResult::new("CNMV web page", Err(String::from("timeout")));
+
+        assert_eq!(pass.to_string(), "[PASS] Listing file         35 companies");
+        assert_eq!(fail.to_string(), "[FAIL] CNMV web page        timeout");
+        assert!(print_results(&[pass]));
+        assert!(!print_results(&[fail]));
+    }
+}
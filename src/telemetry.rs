@@ -12,13 +12,78 @@
 //    See the License for the specific language governing permissions and
 //    limitations under the License.
 
+use crate::latency::LatencyLayer;
+use serde_derive::Deserialize;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+use teloxide::types::Update;
 use tracing::{
-    subscriber::{set_global_default, Subscriber},
-    Level,
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    span::{Attributes, Id},
+    subscriber::{set_global_default, Interest, Subscriber},
+    Level, Metadata,
 };
-use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
 
-pub fn get_subscriber(tracing_level: &str) -> impl Subscriber + Send + Sync {
+/// Identifier of the processing of an incoming update.
+///
+/// # Description
+///
+/// A new ID is generated for each update received from Telegram, and it is injected in the
+/// dependencies of the handlers. Handlers shall include it in their tracing spans, and
+/// show it to the user when something fails, so the log lines of a complaint can be found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorrelationId(String);
+
+impl CorrelationId {
+    /// Build a new ID for `update`.
+    pub fn new(update: &Update) -> CorrelationId {
+        let mut hasher = RandomState::new().build_hasher();
+        update.id.hash(&mut hasher);
+        SystemTime::now().hash(&mut hasher);
+
+        CorrelationId(format!("{:08x}", hasher.finish() as u32))
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<str> for CorrelationId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Tracing level of the application, set by [get_subscriber].
+static TRACING_LEVEL: OnceLock<Level> = OnceLock::new();
+
+/// Redacted ID of the user traced by [UserTraceFilter].
+static TRACED_USER: RwLock<Option<String>> = RwLock::new(None);
+
+/// Build the subscriber of the application.
+///
+/// # Description
+///
+/// Logs more verbose than `tracing_level` are only shown for the user given to
+/// [trace_user], see [UserTraceFilter].
+///
+/// ## Arguments
+///
+/// - _tracing_level_: level of the logs.
+/// - _slow_threshold_: handlers that take longer are logged as slow, see [LatencyLayer].
+pub fn get_subscriber(
+    tracing_level: &str,
+    slow_threshold: Duration,
+) -> impl Subscriber + Send + Sync {
     // Set the tracing logic.
     let tracing_level = match tracing_level {
         "info" => Level::INFO,
@@ -27,12 +92,303 @@ pub fn get_subscriber(tracing_level: &str) -> impl Subscriber + Send + Sync {
         "error" => Level::ERROR,
         _ => Level::TRACE,
     };
+    let _ = TRACING_LEVEL.set(tracing_level);
 
-    FmtSubscriber::builder()
-        .with_max_level(tracing_level)
-        .finish()
+    tracing_subscriber::registry()
+        .with(UserTraceFilter::new(tracing_level))
+        .with(tracing_subscriber::fmt::layer())
+        .with(LatencyLayer::new(slow_threshold))
 }
 
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     set_global_default(subscriber).expect("Failed to set subscriber.");
 }
+
+/// Show every log of a user regardless of the tracing level, or stop doing it with `None`.
+///
+/// # Description
+///
+/// The spans of the user are found by their `chat_id` (or `user_id`) field, hence the ID
+/// of a user traces their private chat with the bot. Only one user is traced at a time.
+pub fn trace_user(user_id: Option<i64>) {
+    let user = user_id.map(|id| redact(id).to_string());
+
+    *TRACED_USER
+        .write()
+        .expect("Poisoned lock of the traced user") = user;
+}
+
+/// Get the redacted ID of the traced user.
+fn traced_user() -> Option<String> {
+    TRACED_USER
+        .read()
+        .expect("Poisoned lock of the traced user")
+        .clone()
+}
+
+/// Filter of the logs that shows every level for the traced user.
+///
+/// # Description
+///
+/// Spans and events up to the tracing level are always enabled. The more verbose ones are
+/// only enabled inside the spans of the user given to [trace_user], e.g. the span of a
+/// handler and the requests made by it, while the rest of the bot stays at its level.
+pub struct UserTraceFilter {
+    level: Level,
+}
+
+/// Mark of the spans of the traced user.
+struct Traced;
+
+impl UserTraceFilter {
+    pub fn new(level: Level) -> Self {
+        UserTraceFilter { level }
+    }
+}
+
+impl<S> Layer<S> for UserTraceFilter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        // The verbose callsites are checked every time, as the traced user may change.
+        if *metadata.level() <= self.level {
+            Interest::always()
+        } else {
+            Interest::sometimes()
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        if *metadata.level() <= self.level {
+            return true;
+        }
+        if TRACED_USER.read().map_or(true, |user| user.is_none()) {
+            return false;
+        }
+
+        ctx.lookup_current().is_some_and(|span| {
+            span.scope()
+                .any(|span| span.extensions().get::<Traced>().is_some())
+        })
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(user) = traced_user() else {
+            return;
+        };
+        let mut visitor = UserVisitor {
+            user: &user,
+            found: false,
+        };
+        attrs.record(&mut visitor);
+
+        if let (true, Some(span)) = (visitor.found, ctx.span(id)) {
+            span.extensions_mut().insert(Traced);
+        }
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(LevelFilter::TRACE)
+    }
+}
+
+/// Visitor that looks for the traced user in the fields of a span.
+struct UserVisitor<'a> {
+    user: &'a str,
+    found: bool,
+}
+
+impl Visit for UserVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if matches!(field.name(), "chat_id" | "user_id") && format!("{value:?}") == self.user {
+            self.found = true;
+        }
+    }
+}
+
+/// Modes to show personal identifiers (user and chat IDs) in the logs.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactionMode {
+    /// Identifiers are shown as they are.
+    None,
+    /// Identifiers are replaced by a keyed hash (HMAC-SHA256), so all the lines of a user
+    /// can still be linked, but the identifiers can't be recovered without the key.
+    #[default]
+    Hash,
+    /// Only the last digits of the identifiers are shown.
+    Truncate,
+}
+
+/// Redaction mode of the application, set once at startup.
+static REDACTION_MODE: OnceLock<RedactionMode> = OnceLock::new();
+
+/// Key of the hashes of [RedactionMode::Hash], set once at startup.
+static REDACTION_KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Set the redaction mode for personal identifiers in the logs.
+///
+/// # Description
+///
+/// This function shall be called once, at startup. When it's not called, identifiers
+/// are hashed. Regardless of the mode, raw identifiers are shown when the tracing level
+/// of the application is _trace_, which is meant for local debugging only. Users traced
+/// by [trace_user] are still redacted.
+///
+/// Hashes are keyed with `key`, which shall be kept secret: Telegram IDs are easy to
+/// enumerate, so anyone that knows the key can map the hashes back to the IDs. When no
+/// key is given, a random one is used, hence the hashes of a user change when the bot
+/// restarts.
+pub fn init_redaction(mode: RedactionMode, key: Option<&str>) {
+    if REDACTION_MODE.set(mode).is_err() {
+        tracing::warn!("The redaction mode was already set");
+    }
+
+    match key {
+        Some(key) if REDACTION_KEY.set(key.as_bytes().to_vec()).is_err() => {
+            tracing::warn!("The redaction key was already set")
+        }
+        Some(_) => (),
+        None if mode == RedactionMode::Hash => tracing::warn!(
+            "No log_redaction_key given, the hashes of the IDs will change after a restart"
+        ),
+        None => (),
+    }
+}
+
+/// Get the key of the hashes, a random one when none was given.
+fn redaction_key() -> &'static [u8] {
+    REDACTION_KEY.get_or_init(|| {
+        (0..4)
+            .flat_map(|_| RandomState::new().build_hasher().finish().to_be_bytes())
+            .collect()
+    })
+}
+
+/// Wrapper of a personal identifier that shall be redacted in the logs.
+///
+/// Use [redact] to build it inside the fields of a span, e.g.
+/// `fields(chat_id = %redact(msg.chat.id))`.
+pub struct Redacted<T>(T);
+
+/// Wrap a personal identifier, so it is redacted when displayed.
+pub fn redact<T: fmt::Display>(value: T) -> Redacted<T> {
+    Redacted(value)
+}
+
+impl<T: fmt::Display> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = if TRACING_LEVEL.get() == Some(&Level::TRACE) {
+            RedactionMode::None
+        } else {
+            REDACTION_MODE.get().copied().unwrap_or_default()
+        };
+
+        write!(
+            f,
+            "{}",
+            redact_with(mode, redaction_key(), &self.0.to_string())
+        )
+    }
+}
+
+/// Redact `value` following `mode`. Hashes are keyed with `key`.
+fn redact_with(mode: RedactionMode, key: &[u8], value: &str) -> String {
+    match mode {
+        RedactionMode::None => String::from(value),
+        RedactionMode::Hash => {
+            let mac = hmac_sha256::HMAC::mac(value.as_bytes(), key);
+            let hex: String = mac[..8].iter().map(|b| format!("{b:02x}")).collect();
+            format!("#{hex}")
+        }
+        RedactionMode::Truncate => {
+            let start = value.char_indices().rev().nth(2).map_or(0, |(i, _)| i);
+            format!("…{}", &value[start..])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[rstest]
+    fn redaction_none() {
+        assert_eq!(
+            redact_with(RedactionMode::None, b"key", "123456789"),
+            "123456789"
+        );
+    }
+
+    #[rstest]
+    fn redaction_hash() {
+        let hashed = redact_with(RedactionMode::Hash, b"key", "123456789");
+
+        assert!(!hashed.contains("123456789"));
+        assert_eq!(hashed.len(), 17);
+        assert_eq!(
+            hashed,
+            redact_with(RedactionMode::Hash, b"key", "123456789")
+        );
+        assert_ne!(
+            hashed,
+            redact_with(RedactionMode::Hash, b"key", "123456780")
+        );
+        // Without the key, the hash can't be matched with the ID.
+        assert_ne!(
+            hashed,
+            redact_with(RedactionMode::Hash, b"other", "123456789")
+        );
+    }
+
+    #[rstest]
+    #[case("123456789", "…789")]
+    #[case("-100123", "…123")]
+    #[case("12", "…12")]
+    fn redaction_truncate(#[case] value: &str, #[case] expected: &str) {
+        assert_eq!(
+            redact_with(RedactionMode::Truncate, b"key", value),
+            expected
+        );
+    }
+
+    /// Layer that counts the events that reach it.
+    struct EventCounter(Arc<AtomicUsize>);
+
+    impl<S: Subscriber> Layer<S> for EventCounter {
+        fn on_event(&self, _event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[rstest]
+    fn user_tracing() {
+        let events = Arc::new(AtomicUsize::new(0));
+        let subscriber = tracing_subscriber::registry()
+            .with(UserTraceFilter::new(Level::INFO))
+            .with(EventCounter(Arc::clone(&events)));
+        let log_as = |chat_id: i64| {
+            tracing::info_span!("Test handler", chat_id = %redact(chat_id)).in_scope(|| {
+                tracing::info!("Always shown");
+                tracing::trace!("Only shown for the traced user");
+            });
+            events.swap(0, Ordering::Relaxed)
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert_eq!(log_as(42), 1);
+            trace_user(Some(42));
+            assert_eq!(log_as(42), 2);
+            assert_eq!(log_as(7), 1);
+            tracing::trace!("Outside the spans of the user");
+            assert_eq!(events.swap(0, Ordering::Relaxed), 0);
+            trace_user(None);
+            assert_eq!(log_as(42), 1);
+        });
+    }
+}
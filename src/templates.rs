@@ -0,0 +1,315 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Customizable texts of the bot.
+//!
+//! # Description
+//!
+//! The greeting of `/start` and the answer to the messages that the bot doesn't
+//! understand are templates. The bundled ones are used unless the settings point to
+//! other files (see [TemplateSettings]), so operators can change the tone of the bot
+//! without building it again.
+//!
+//! Templates may include these placeholders:
+//!
+//! - `{name}`: first name (or username) of the user.
+//! - `{highlight}`: a sentence about the most shorted company of the market, empty when
+//!   no short positions are known yet.
+//!
+//! Unknown placeholders are rejected when the templates are loaded. The greeting is sent
+//! as plain text, whereas the fallback is sent as HTML, hence the values given to the
+//! fallback are escaped.
+
+use crate::configuration::TemplateSettings;
+use std::fs::read_to_string;
+use std::path::Path;
+use teloxide::utils::html;
+use thiserror::Error;
+use tracing::info;
+
+/// Placeholders supported by the templates.
+pub const PLACEHOLDERS: [&str; 2] = ["name", "highlight"];
+
+/// Error types for the loader of templates.
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    /// A template file could not be read.
+    #[error("error opening the template file {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// A template includes a placeholder that is not supported.
+    #[error("the template {path} includes the unknown placeholder {{{placeholder}}}")]
+    UnknownPlaceholder { path: String, placeholder: String },
+}
+
+/// Greeting and fallback texts, in all the supported languages.
+#[derive(Debug)]
+pub struct Templates {
+    welcome_en: String,
+    welcome_es: String,
+    fallback_en: String,
+    fallback_es: String,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Templates {
+            welcome_en: String::from(include_str!("../data/templates/welcome_en.txt")),
+            welcome_es: String::from(include_str!("../data/templates/welcome_es.txt")),
+            fallback_en: String::from(include_str!("../data/templates/warning_en.txt")),
+            fallback_es: String::from(include_str!("../data/templates/warning_es.txt")),
+        }
+    }
+}
+
+impl Templates {
+    /// Build the [Templates] from the settings.
+    ///
+    /// ## Arguments
+    ///
+    /// - _settings_: paths of the templates that replace the bundled ones.
+    /// - _data_path_: directory against which relative paths are resolved.
+    pub fn load(settings: &TemplateSettings, data_path: &str) -> Result<Templates, TemplateError> {
+        let defaults = Templates::default();
+        let load = |path: &Option<String>, default: String| match path {
+            Some(path) => _read_template(&Path::new(data_path).join(path)),
+            None => Ok(default),
+        };
+
+        Ok(Templates {
+            welcome_en: load(&settings.welcome_en, defaults.welcome_en)?,
+            welcome_es: load(&settings.welcome_es, defaults.welcome_es)?,
+            fallback_en: load(&settings.fallback_en, defaults.fallback_en)?,
+            fallback_es: load(&settings.fallback_es, defaults.fallback_es)?,
+        })
+    }
+
+    /// Get the greeting of `/start` in the language of the user, with its placeholders
+    /// replaced. English is used for unsupported languages.
+    pub fn welcome(&self, lang_code: &str, name: &str, highlight: &str) -> String {
+        let template = match lang_code {
+            "es" => &self.welcome_es,
+            _ => &self.welcome_en,
+        };
+
+        render(template, name, highlight)
+    }
+
+    /// Get the answer to the messages that the bot doesn't understand in the language of
+    /// the user, with its placeholders replaced. English is used for unsupported languages.
+    ///
+    /// The answer is HTML: `name` and `highlight` are given as plain text, and escaped.
+    pub fn fallback(&self, lang_code: &str, name: &str, highlight: &str) -> String {
+        let template = match lang_code {
+            "es" => &self.fallback_es,
+            _ => &self.fallback_en,
+        };
+
+        render(template, &html::escape(name), &html::escape(highlight))
+    }
+}
+
+/// Replace the placeholders of a template.
+///
+/// # Description
+///
+/// The template is rendered in a single pass, so placeholders included in the values
+/// (e.g. a user named `{highlight}`) are not replaced.
+fn render(template: &str, name: &str, highlight: &str) -> String {
+    let values = [("{name}", name), ("{highlight}", highlight)];
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        match values.iter().find(|(key, _)| rest.starts_with(key)) {
+            Some((key, value)) => {
+                rendered.push_str(value);
+                rest = &rest[key.len()..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+
+    rendered.trim_end().to_owned()
+}
+
+/// Read a template file, and check that its placeholders are supported.
+fn _read_template(path: &Path) -> Result<String, TemplateError> {
+    let path_str = path.to_string_lossy().into_owned();
+    info!("Template {path_str} will replace the bundled one.");
+
+    let template = read_to_string(path).map_err(|e| TemplateError::Io {
+        path: path_str.clone(),
+        source: e,
+    })?;
+
+    let unknown = _placeholders(&template)
+        .find(|p| !PLACEHOLDERS.contains(p))
+        .map(String::from);
+
+    match unknown {
+        Some(placeholder) => Err(TemplateError::UnknownPlaceholder {
+            path: path_str,
+            placeholder,
+        }),
+        None => Ok(template),
+    }
+}
+
+/// Iterate over the placeholders of a template, i.e. the words between braces.
+fn _placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template.split('{').skip(1).filter_map(|chunk| {
+        let (word, _) = chunk.split_once('}')?;
+        (!word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+            .then_some(word)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::path::PathBuf;
+
+    fn template_file(test: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("template_{test}_{}.txt", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[rstest]
+    fn bundled_templates() {
+        let templates = Templates::default();
+
+        for lang in ["en", "es"] {
+            for template in [
+                templates.welcome(lang, "", ""),
+                templates.fallback(lang, "", ""),
+            ] {
+                assert!(_placeholders(&template).next().is_none());
+            }
+        }
+
+        assert!(templates
+            .welcome("es", "Ana", "")
+            .starts_with("¡Bienvenido Ana"));
+        assert!(templates
+            .welcome("fr", "Ana", "")
+            .starts_with("Welcome Ana"));
+    }
+
+    #[rstest]
+    fn custom_templates() {
+        let path = template_file("custom", "Hi {name}! {highlight}");
+        let settings = TemplateSettings {
+            welcome_en: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+
+        let templates = Templates::load(&settings, "/").unwrap();
+
+        assert_eq!(
+            templates.welcome("en", "Ana", "GRF is the most shorted."),
+            "Hi Ana! GRF is the most shorted."
+        );
+        assert_eq!(
+            templates.welcome("es", "Ana", ""),
+            Templates::default().welcome("es", "Ana", "")
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[rstest]
+    #[case("Hi {name}! {highlight}", "{highlight}", "GRF", "Hi {highlight}! GRF")]
+    #[case("{name}{name}", "Ana", "", "AnaAna")]
+    #[case("Braces { are } fine, {name}", "Ana", "", "Braces { are } fine, Ana")]
+    fn single_pass(
+        #[case] template: &str,
+        #[case] name: &str,
+        #[case] highlight: &str,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(render(template, name, highlight), expected);
+    }
+
+    #[rstest]
+    fn escaped_fallback() {
+        let path = template_file("escaped", "<b>{name}</b>, {highlight}");
+        let settings = TemplateSettings {
+            fallback_en: Some(path.to_string_lossy().into_owned()),
+            welcome_en: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let templates = Templates::load(&settings, "/").unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            templates.fallback("en", "Tom & <Jerry>", "A&B"),
+            "<b>Tom &amp; &lt;Jerry&gt;</b>, A&amp;B"
+        );
+        // The greeting is plain text.
+        assert_eq!(
+            templates.welcome("en", "Tom & <Jerry>", "A&B"),
+            "<b>Tom & <Jerry></b>, A&B"
+        );
+    }
+
+    #[rstest]
+    #[case("Hi {nmae}!", Some("nmae"))]
+    #[case("Hi {name}, {highlight}", None)]
+    #[case("Braces { are } fine", None)]
+    fn unknown_placeholders(#[case] content: &str, #[case] unknown: Option<&str>) {
+        let path = template_file(
+            &content.replace(|c: char| !c.is_alphanumeric(), ""),
+            content,
+        );
+        let settings = TemplateSettings {
+            fallback_es: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let result = Templates::load(&settings, "/");
+        std::fs::remove_file(path).unwrap();
+
+        match (result, unknown) {
+            (Err(TemplateError::UnknownPlaceholder { placeholder, .. }), Some(unknown)) => {
+                assert_eq!(placeholder, unknown)
+            }
+            (Ok(_), None) => (),
+            (result, _) => panic!("Unexpected result: {result:?}"),
+        }
+    }
+
+    #[rstest]
+    fn missing_file() {
+        let settings = TemplateSettings {
+            welcome_en: Some(String::from("nonexistent/welcome.txt")),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            Templates::load(&settings, "/"),
+            Err(TemplateError::Io { .. })
+        ));
+    }
+}
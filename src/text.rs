@@ -0,0 +1,89 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Normalization of the text typed by the users.
+//!
+//! # Description
+//!
+//! Users type names in many ways: with or without accents, in any case, and with extra
+//! whitespace. The searches by name (companies, concepts of the glossary, funds) compare
+//! normalized strings, so _Posición corta_, _posicion  corta_ and _POSICIÓN CORTA_ are
+//! the same query. Tickers are normalized by [Ticker][crate::finance::Ticker].
+
+/// Remove the surrounding whitespace, and replace the inner runs of whitespace by a
+/// single space.
+pub fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Replace the accented letters used in Spanish (and a few common in company names) by
+/// their plain form, e.g. `Ñ` by `N` or `á` by `a`. The case is kept.
+pub fn fold_accents(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'á' | 'à' | 'ä' | 'â' => 'a',
+            'é' | 'è' | 'ë' | 'ê' => 'e',
+            'í' | 'ì' | 'ï' | 'î' => 'i',
+            'ó' | 'ò' | 'ö' | 'ô' => 'o',
+            'ú' | 'ù' | 'ü' | 'û' => 'u',
+            'ñ' => 'n',
+            'ç' => 'c',
+            'Á' | 'À' | 'Ä' | 'Â' => 'A',
+            'É' | 'È' | 'Ë' | 'Ê' => 'E',
+            'Í' | 'Ì' | 'Ï' | 'Î' => 'I',
+            'Ó' | 'Ò' | 'Ö' | 'Ô' => 'O',
+            'Ú' | 'Ù' | 'Ü' | 'Û' => 'U',
+            'Ñ' => 'N',
+            'Ç' => 'C',
+            c => c,
+        })
+        .collect()
+}
+
+/// Build the key used to compare names in the searches: lowercase, without accents and
+/// without extra whitespace.
+pub fn search_key(text: &str) -> String {
+    collapse_whitespace(&fold_accents(text)).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("  Banco\t de   Sabadell \n", "Banco de Sabadell")]
+    #[case("", "")]
+    fn collapse(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(collapse_whitespace(input), expected);
+    }
+
+    #[rstest]
+    #[case("ESPAÑA", "ESPANA")]
+    #[case("Posición corta neta", "Posicion corta neta")]
+    #[case("Interés en corto", "Interes en corto")]
+    #[case("pingüino", "pinguino")]
+    #[case("ÁÉÍÓÚ áéíóú", "AEIOU aeiou")]
+    fn accents(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(fold_accents(input), expected);
+    }
+
+    #[rstest]
+    #[case("  POSICIÓN   Corta ", "posicion corta")]
+    #[case("Telefónica", "telefonica")]
+    #[case("Año", "ano")]
+    fn search_keys(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(search_key(input), expected);
+    }
+}
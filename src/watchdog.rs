@@ -0,0 +1,225 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Watchdog of the background tasks.
+//!
+//! # Description
+//!
+//! The loops spawned at startup (e.g. the refresh of the short cache) die silently when
+//! they panic or get stuck. Each of them is registered in the [Watchdog], which hands
+//! out a [Heartbeat] that the loop shall beat on every iteration. The watchdog checks
+//! the heartbeats periodically and raises an alert when a task stays silent for too
+//! long.
+//!
+//! Tasks spawned through [supervise] are also restarted when they end.
+
+use crate::clock::{Clock, SystemClock};
+use crate::ops_alerts::{OpsAlerts, Severity};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Time between two checks of the heartbeats.
+pub const CHECK_PERIOD: Duration = Duration::from_secs(60);
+
+/// Time to wait before restarting a supervised task.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Number of heartbeats missed by the background tasks.
+static HEARTBEAT_MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Get the number of heartbeats missed since the start of the bot.
+pub fn heartbeat_misses() -> u64 {
+    HEARTBEAT_MISSES.load(Ordering::Relaxed)
+}
+
+/// Liveness of a registered task.
+struct Task {
+    name: &'static str,
+    max_silence: Duration,
+    last_beat: Mutex<Instant>,
+    /// Whether the current silence was already reported.
+    missed: AtomicBool,
+}
+
+/// Handle that a task uses to tell the [Watchdog] that it is alive.
+#[derive(Clone)]
+pub struct Heartbeat {
+    task: Arc<Task>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Heartbeat {
+    /// Tell the watchdog that the task is alive.
+    pub fn beat(&self) {
+        *self
+            .task
+            .last_beat
+            .lock()
+            .expect("Poisoned lock of the heartbeat") = self.clock.now();
+        self.task.missed.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Watchdog of the background tasks.
+pub struct Watchdog {
+    clock: Arc<dyn Clock>,
+    tasks: Mutex<Vec<Arc<Task>>>,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Watchdog::with_clock(Arc::new(SystemClock))
+    }
+}
+
+impl Watchdog {
+    /// Class constructor that takes the [Clock] used to measure the silences.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Watchdog {
+        Watchdog {
+            clock,
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Register a task.
+    ///
+    /// ## Arguments
+    ///
+    /// - _name_: name of the task shown in the logs and the alerts.
+    /// - _max_silence_: longest time allowed between two heartbeats of the task.
+    pub fn register(&self, name: &'static str, max_silence: Duration) -> Heartbeat {
+        let task = Arc::new(Task {
+            name,
+            max_silence,
+            last_beat: Mutex::new(self.clock.now()),
+            missed: AtomicBool::new(false),
+        });
+        self.tasks
+            .lock()
+            .expect("Poisoned lock of the watchdog")
+            .push(Arc::clone(&task));
+        debug!("Registered the task {name} in the watchdog");
+
+        Heartbeat {
+            task,
+            clock: Arc::clone(&self.clock),
+        }
+    }
+
+    /// Find the tasks that stopped beating since the last check.
+    ///
+    /// # Description
+    ///
+    /// A silent task is only reported once, until it beats again.
+    pub fn check(&self) -> Vec<&'static str> {
+        let now = self.clock.now();
+
+        self.tasks
+            .lock()
+            .expect("Poisoned lock of the watchdog")
+            .iter()
+            .filter(|task| {
+                let last_beat = *task
+                    .last_beat
+                    .lock()
+                    .expect("Poisoned lock of the heartbeat");
+                now.duration_since(last_beat) > task.max_silence
+                    && !task.missed.swap(true, Ordering::Relaxed)
+            })
+            .map(|task| task.name)
+            .collect()
+    }
+
+    /// Check the heartbeats every `period`, forever.
+    ///
+    /// # Description
+    ///
+    /// This method is meant to be spawned as a background task at startup.
+    pub async fn run(self: Arc<Self>, period: Duration, alerts: Arc<OpsAlerts>) {
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
+
+            for name in self.check() {
+                HEARTBEAT_MISSES.fetch_add(1, Ordering::Relaxed);
+                alerts.alert(
+                    Severity::Critical,
+                    "heartbeat",
+                    &format!("The background task {name} stopped beating"),
+                );
+            }
+        }
+    }
+}
+
+/// Spawn a background task that is restarted whenever it ends.
+///
+/// # Description
+///
+/// The task is built by `factory` on every start. When it ends, either because it
+/// returned or because it panicked, an alert is raised and it starts again after a
+/// short delay.
+pub fn supervise<F, Fut>(name: &'static str, alerts: Arc<OpsAlerts>, factory: F)
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            let reason = match tokio::spawn(factory()).await {
+                Ok(()) => String::from("returned"),
+                Err(e) => format!("failed: {e}"),
+            };
+            alerts.alert(
+                Severity::Critical,
+                "task-restart",
+                &format!("The background task {name} {reason}, restarting it"),
+            );
+            tokio::time::sleep(RESTART_DELAY).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use date::Date;
+    use rstest::rstest;
+
+    #[rstest]
+    fn missed_heartbeats() {
+        let clock = Arc::new(ManualClock::new(Date::new(2024, 6, 12)));
+        let watchdog = Watchdog::with_clock(clock.clone());
+        let refresher = watchdog.register("refresher", Duration::from_secs(60));
+        let watcher = watchdog.register("watcher", Duration::from_secs(600));
+
+        clock.advance(Duration::from_secs(60));
+        assert!(watchdog.check().is_empty());
+
+        clock.advance(Duration::from_secs(1));
+        watcher.beat();
+        assert_eq!(watchdog.check(), ["refresher"]);
+        // The same silence is not reported twice.
+        assert!(watchdog.check().is_empty());
+
+        refresher.beat();
+        clock.advance(Duration::from_secs(61));
+        assert_eq!(watchdog.check(), ["refresher"]);
+    }
+}
@@ -0,0 +1,589 @@
+// Copyright 2024 Felipe Torres González
+//
+//    Licensed under the Apache License, Version 2.0 (the "License");
+//    you may not use this file except in compliance with the License.
+//    You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+//    Unless required by applicable law or agreed to in writing, software
+//    distributed under the License is distributed on an "AS IS" BASIS,
+//    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//    See the License for the specific language governing permissions and
+//    limitations under the License.
+
+//! Webhook mode of the ShortBot.
+//!
+//! # Description
+//!
+//! By default, the bot pulls updates from Telegram (long polling). When the webhook mode
+//! is configured, Telegram pushes the updates to an [axum] server instead. This module
+//! sets up such server, registers the webhook in Telegram, and checks periodically that
+//! the registration has not drifted (e.g. another instance of the bot took it over).
+
+use crate::configuration::WebhookSettings;
+use crate::health::Readiness;
+use crate::lockout::AuthLockout;
+use crate::ops_alerts::{OpsAlerts, Severity};
+use crate::watchdog::Heartbeat;
+use axum::{
+    extract::{ConnectInfo, DefaultBodyLimit, State},
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Json,
+};
+use secrecy::ExposeSecret;
+use serde_json::json;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use teloxide::{
+    prelude::*,
+    update_listeners::{webhooks, UpdateListener},
+};
+use tracing::{debug, error, info, warn};
+
+/// Number of times the registered webhook was found pointing elsewhere.
+static WEBHOOK_DRIFTS: AtomicU64 = AtomicU64::new(0);
+
+/// Get the number of times the webhook drifted since the start of the bot.
+pub fn webhook_drifts() -> u64 {
+    WEBHOOK_DRIFTS.load(Ordering::Relaxed)
+}
+
+/// Error types for the webhook mode.
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    /// The settings of the webhook are not valid.
+    #[error("wrong webhook settings: {0}")]
+    Settings(String),
+    /// Telegram refused the registration of the webhook.
+    #[error("failed to register the webhook: {0}")]
+    Registration(#[from] teloxide::RequestError),
+}
+
+/// Build an update listener that receives the updates through a webhook.
+///
+/// # Description
+///
+/// This function registers the webhook in Telegram (`setWebhook`), spawns the [axum]
+/// server that receives the updates, and spawns the task that watches the registration.
+/// The webhook is removed from Telegram when the listener is stopped.
+///
+/// Every request to the webhook route must carry the secret token in the header
+/// `X-Telegram-Bot-Api-Secret-Token`, otherwise it is rejected with the status
+/// `401 Unauthorized`. Source IPs that fail too many times are locked out for a while,
+/// see [AuthLockout]. See [http_policies] for the rules applied to all the routes.
+///
+/// ## Arguments
+///
+/// - _bot_: the bot that registers the webhook.
+/// - _settings_: the settings of the webhook mode.
+/// - _routes_: additional routes served by the same server, which are not protected
+///   by the secret token.
+/// - _alerts_: channel of the alerts about drifts and lockouts.
+/// - _heartbeat_: heartbeat of the task that watches the registration.
+/// - _readiness_: set while the webhook is registered in Telegram.
+pub async fn listener(
+    bot: Bot,
+    settings: &WebhookSettings,
+    routes: axum::Router,
+    alerts: Arc<OpsAlerts>,
+    heartbeat: Heartbeat,
+    readiness: Readiness,
+) -> Result<impl UpdateListener<Err = Infallible>, WebhookError> {
+    let mut options = options(settings)?;
+    let address = options.address;
+    let url = options.url.clone();
+    let secret = options.get_or_gen_secret_token().to_owned();
+    let max_body_size = settings.max_body_size;
+    let lockout = Arc::new(
+        AuthLockout::new(
+            settings.max_auth_failures,
+            Duration::from_secs(settings.auth_lockout_period),
+        )
+        .with_trusted_proxies(settings.trusted_proxies.clone()),
+    );
+
+    info!("Registering the webhook {url}");
+    let (listener, stop_flag, router) = webhooks::axum_to_router(bot.clone(), options).await?;
+    readiness.set_ready(true);
+
+    let guard_alerts = Arc::clone(&alerts);
+    tokio::spawn(async move {
+        if let Err(e) = axum::Server::bind(&address)
+            .serve(
+                http_policies(
+                    guard_auth(router, lockout, guard_alerts).merge(routes),
+                    max_body_size,
+                )
+                .into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(stop_flag)
+            .await
+        {
+            error!("The webhook server failed: {e}");
+        }
+    });
+
+    tokio::spawn(watch_webhook(
+        bot,
+        url,
+        secret,
+        Duration::from_secs(settings.drift_check_period),
+        alerts,
+        heartbeat,
+        readiness,
+    ));
+
+    Ok(listener)
+}
+
+/// Build the options of the webhook listener out of the settings.
+fn options(settings: &WebhookSettings) -> Result<webhooks::Options, WebhookError> {
+    let address: SocketAddr = settings
+        .address
+        .parse()
+        .map_err(|e| WebhookError::Settings(format!("address {}: {e}", settings.address)))?;
+    let url = reqwest::Url::parse(&settings.url)
+        .map_err(|e| WebhookError::Settings(format!("url {}: {e}", settings.url)))?;
+
+    let options = webhooks::Options::new(address, url);
+
+    match &settings.secret_token {
+        Some(token) => {
+            let token = token.expose_secret();
+            check_secret_token(token)?;
+            Ok(options.secret_token(token.clone()))
+        }
+        None => Ok(options),
+    }
+}
+
+/// Check that a secret token complies with the format required by Telegram.
+///
+/// # Description
+///
+/// The token shall have 1-256 characters, and only `A-Z`, `a-z`, `0-9`, `_` and `-` are
+/// allowed.
+fn check_secret_token(token: &str) -> Result<(), WebhookError> {
+    if token.is_empty() || token.len() > 256 {
+        return Err(WebhookError::Settings(String::from(
+            "the secret token must have 1-256 characters",
+        )));
+    }
+
+    if !token
+        .bytes()
+        .all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-')
+    {
+        return Err(WebhookError::Settings(String::from(
+            "the secret token only allows the characters A-Z, a-z, 0-9, _ and -",
+        )));
+    }
+
+    Ok(())
+}
+
+/// Apply the rules common to all the routes of the server.
+///
+/// # Description
+///
+/// - Requests whose body is bigger than `max_body_size` are rejected with the status
+///   `413 Payload Too Large`.
+/// - `POST` requests must send JSON, otherwise they are rejected with the status
+///   `415 Unsupported Media Type`.
+/// - Errors are answered with a JSON document: `{"error": {"status": <code>, "message": <reason>}}`.
+fn http_policies(router: axum::Router, max_body_size: usize) -> axum::Router {
+    router
+        .layer(middleware::from_fn(require_json))
+        .layer(DefaultBodyLimit::max(max_body_size))
+        .layer(middleware::map_response(json_errors))
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"))
+}
+
+async fn require_json<B>(request: Request<B>, next: Next<B>) -> Response {
+    if request.method() == Method::POST && !is_json(request.headers()) {
+        debug!(
+            "Rejected a request without JSON content to {}",
+            request.uri()
+        );
+        return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+    }
+
+    next.run(request).await
+}
+
+async fn json_errors(response: Response) -> Response {
+    let status = response.status();
+
+    if !(status.is_client_error() || status.is_server_error()) || is_json(response.headers()) {
+        return response;
+    }
+
+    let mut envelope = (
+        status,
+        Json(json!({
+            "error": {
+                "status": status.as_u16(),
+                "message": status.canonical_reason().unwrap_or("Error"),
+            }
+        })),
+    )
+        .into_response();
+
+    // Keep the headers that are not about the content, e.g. Retry-After.
+    for (name, value) in response.headers() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            envelope.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+
+    envelope
+}
+
+/// Lock out the source IPs that fail to authenticate too many times.
+fn guard_auth(
+    router: axum::Router,
+    lockout: Arc<AuthLockout>,
+    alerts: Arc<OpsAlerts>,
+) -> axum::Router {
+    router.layer(middleware::from_fn_with_state(
+        (lockout, alerts),
+        check_lockout,
+    ))
+}
+
+async fn check_lockout<B>(
+    State((lockout, alerts)): State<(Arc<AuthLockout>, Arc<OpsAlerts>)>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(ip) = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| lockout.client_ip(info.0.ip(), request.headers()))
+    else {
+        return next.run(request).await;
+    };
+
+    if let Some(left) = lockout.locked(ip) {
+        debug!("Rejected a request from {ip}, which is locked out");
+        // Round up, so the client doesn't retry before the lockout expires.
+        let retry_after = left.as_secs() + u64::from(left.subsec_nanos() > 0);
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+        )
+            .into_response();
+    }
+
+    let response = next.run(request).await;
+
+    match response.status() {
+        StatusCode::UNAUTHORIZED if lockout.record_failure(ip) => alerts.alert(
+            Severity::Critical,
+            "webhook-auth",
+            &format!(
+                "{ip} was locked out after failing to authenticate in the webhook ({} lockouts so far)",
+                lockout.lockouts()
+            ),
+        ),
+        status if status.is_success() => lockout.clear(ip),
+        _ => (),
+    }
+
+    response
+}
+
+/// Check periodically that Telegram points at the configured webhook.
+///
+/// # Description
+///
+/// When the registered URL differs from `url`, the drift is logged and counted (see
+/// [webhook_drifts]), and the webhook is registered again. The bot is not ready until
+/// the registration succeeds.
+async fn watch_webhook(
+    bot: Bot,
+    url: reqwest::Url,
+    secret: String,
+    period: Duration,
+    alerts: Arc<OpsAlerts>,
+    heartbeat: Heartbeat,
+    readiness: Readiness,
+) {
+    let mut interval = tokio::time::interval(period);
+    // The first tick completes immediately, and the webhook was just registered.
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        heartbeat.beat();
+
+        let info = match bot.get_webhook_info().await {
+            Ok(info) => info,
+            Err(e) => {
+                warn!("Failed to check the registered webhook: {e}");
+                continue;
+            }
+        };
+
+        if info.url.as_ref() == Some(&url) {
+            debug!("The registered webhook matches the settings");
+            continue;
+        }
+
+        WEBHOOK_DRIFTS.fetch_add(1, Ordering::Relaxed);
+        readiness.set_ready(false);
+        alerts.alert(
+            Severity::Warning,
+            "webhook-drift",
+            &format!(
+                "Webhook drift detected: Telegram points at {:?} instead of {url}",
+                info.url.as_ref().map(|u| u.as_str())
+            ),
+        );
+
+        match bot
+            .set_webhook(url.clone())
+            .secret_token(secret.clone())
+            .await
+        {
+            Ok(_) => readiness.set_ready(true),
+            Err(e) => error!("Failed to register the webhook again: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use rstest::*;
+    use secrecy::Secret;
+    use tower::ServiceExt;
+
+    const UPDATE: &str = r#"{
+        "update_id": 1,
+        "message": {
+            "message_id": 1,
+            "date": 1718000000,
+            "chat": {"id": 1, "type": "private", "first_name": "Test"},
+            "from": {"id": 1, "is_bot": false, "first_name": "Test"},
+            "text": "/start"
+        }
+    }"#;
+
+    #[fixture]
+    fn settings() -> WebhookSettings {
+        WebhookSettings {
+            url: String::from("https://shortbot.example.com/webhook"),
+            address: String::from("127.0.0.1:8443"),
+            secret_token: Some(Secret::new(String::from("my_secret-token_1"))),
+            drift_check_period: 300,
+            max_body_size: 4096,
+            max_auth_failures: 2,
+            auth_lockout_period: 60,
+            trusted_proxies: Vec::new(),
+        }
+    }
+
+    #[rstest]
+    #[case("a")]
+    #[case("my_secret-token_1")]
+    #[case(&"x".repeat(256))]
+    fn valid_secret_token(#[case] token: &str) {
+        assert!(check_secret_token(token).is_ok());
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case(&"x".repeat(257))]
+    #[case("my secret")]
+    #[case("my.secret")]
+    #[case("contraseña")]
+    fn invalid_secret_token(#[case] token: &str) {
+        assert!(matches!(
+            check_secret_token(token),
+            Err(WebhookError::Settings(_))
+        ));
+    }
+
+    #[rstest]
+    fn invalid_settings(mut settings: WebhookSettings) {
+        settings.secret_token = Some(Secret::new(String::from("my secret")));
+        assert!(options(&settings).is_err());
+    }
+
+    #[rstest]
+    #[case(None, StatusCode::UNAUTHORIZED)]
+    #[case(Some("spoofed_token"), StatusCode::UNAUTHORIZED)]
+    #[case(Some("my_secret-token_1"), StatusCode::OK)]
+    fn webhook_route(
+        settings: WebhookSettings,
+        #[case] token: Option<&str>,
+        #[case] status: StatusCode,
+    ) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let options = options(&settings).expect("Valid settings");
+            let (_listener, _stop_flag, router) = webhooks::axum_no_setup(options);
+
+            let mut request = Request::builder()
+                .method("POST")
+                .uri("/webhook")
+                .header("content-type", "application/json");
+            if let Some(token) = token {
+                request = request.header("x-telegram-bot-api-secret-token", token);
+            }
+            let request = request.body(Body::from(UPDATE)).unwrap();
+
+            let response = router.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), status);
+        });
+    }
+
+    #[rstest]
+    #[case("POST", "/webhook", "application/json", "x".repeat(8192), StatusCode::PAYLOAD_TOO_LARGE)]
+    #[case(
+        "POST",
+        "/webhook",
+        "text/plain",
+        String::from(UPDATE),
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    )]
+    #[case(
+        "POST",
+        "/webhook",
+        "application/json",
+        String::from(UPDATE),
+        StatusCode::UNAUTHORIZED
+    )]
+    #[case(
+        "GET",
+        "/unknown",
+        "application/json",
+        String::new(),
+        StatusCode::NOT_FOUND
+    )]
+    fn http_errors(
+        settings: WebhookSettings,
+        #[case] method: &str,
+        #[case] uri: &str,
+        #[case] content_type: &str,
+        #[case] body: String,
+        #[case] status: StatusCode,
+    ) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let options = options(&settings).expect("Valid settings");
+            let (_listener, _stop_flag, router) = webhooks::axum_no_setup(options);
+            let router = http_policies(router, settings.max_body_size);
+
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", content_type)
+                .header("x-telegram-bot-api-secret-token", "my_secret-token_1")
+                .body(Body::from(body))
+                .unwrap();
+
+            let request = if status == StatusCode::UNAUTHORIZED {
+                let (mut parts, body) = request.into_parts();
+                parts.headers.remove("x-telegram-bot-api-secret-token");
+                Request::from_parts(parts, body)
+            } else {
+                request
+            };
+
+            let response = router.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), status);
+            assert_eq!(response.headers()["content-type"], "application/json");
+        });
+    }
+
+    #[rstest]
+    fn json_errors_keep_repeated_headers() {
+        let mut response = (StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_response();
+        for cookie in ["a=1", "b=2"] {
+            response
+                .headers_mut()
+                .append(header::SET_COOKIE, header::HeaderValue::from_static(cookie));
+        }
+
+        let envelope = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(json_errors(response));
+
+        assert_eq!(envelope.headers()["content-type"], "application/json");
+        let cookies: Vec<_> = envelope
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .collect();
+        assert_eq!(cookies, ["a=1", "b=2"]);
+    }
+
+    #[rstest]
+    fn auth_lockout(settings: WebhookSettings) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let options = options(&settings).expect("Valid settings");
+            let (_listener, _stop_flag, router) = webhooks::axum_no_setup(options);
+            let lockout = Arc::new(AuthLockout::new(
+                settings.max_auth_failures,
+                Duration::from_secs(settings.auth_lockout_period),
+            ));
+            let router = http_policies(
+                guard_auth(router, lockout, Arc::new(OpsAlerts::default())),
+                settings.max_body_size,
+            );
+            let source: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+
+            for status in [
+                StatusCode::UNAUTHORIZED,
+                StatusCode::UNAUTHORIZED,
+                StatusCode::TOO_MANY_REQUESTS,
+            ] {
+                let mut request = Request::builder()
+                    .method("POST")
+                    .uri("/webhook")
+                    .header("content-type", "application/json")
+                    .header("x-telegram-bot-api-secret-token", "wrong_token")
+                    .body(Body::from(UPDATE))
+                    .unwrap();
+                request.extensions_mut().insert(ConnectInfo(source));
+
+                let response = router.clone().oneshot(request).await.unwrap();
+                assert_eq!(response.status(), status);
+
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    assert_eq!(response.headers()["retry-after"], "60");
+                    assert_eq!(response.headers()["content-type"], "application/json");
+                }
+            }
+        });
+    }
+}